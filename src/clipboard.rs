@@ -0,0 +1,15 @@
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+
+lazy_static! {
+    static ref CLIPBOARD: RwLock<String> = RwLock::new(String::new());
+}
+
+/// IDE内部的剪贴板，终端复制模式写入，编辑器从中粘贴
+pub fn copy(text: String) {
+    *CLIPBOARD.write().unwrap() = text;
+}
+
+pub fn paste() -> String {
+    CLIPBOARD.read().unwrap().clone()
+}
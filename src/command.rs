@@ -0,0 +1,33 @@
+use crate::ui::framework::Framework;
+
+/// 命令的动作，可以通过Framework切换焦点或打开浮层
+pub type Action = Box<dyn Fn(&mut Framework)>;
+
+/// 各组件注册的命名命令，命令面板和快捷键按名字执行
+#[derive(Default)]
+pub struct Registry {
+    commands: Vec<(String, Action)>,
+}
+
+impl Registry {
+    /// 名字形如"Editor: Save"，冒号前是提供命令的组件
+    pub fn register(&mut self, name: &str, action: impl Fn(&mut Framework) + 'static) {
+        self.commands.push((name.to_string(), Box::new(action)));
+    }
+
+    /// 按注册顺序排列的所有命令名
+    pub fn names(&self) -> Vec<String> {
+        self.commands.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// 执行名为name的命令，没有这个命令时返回false
+    pub fn run(&self, name: &str, framework: &mut Framework) -> bool {
+        match self.commands.iter().find(|(n, _)| n == name) {
+            Some((_, action)) => {
+                action(framework);
+                true
+            }
+            None => false,
+        }
+    }
+}
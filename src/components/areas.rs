@@ -6,7 +6,7 @@ use std::{
 use crate::{
     renderer::Renderer,
     ui::{
-        container::{Container, ContainerType},
+        container::{Container, ContainerType, Size},
         framework::Framework,
     },
 };
@@ -18,12 +18,15 @@ pub struct EditorArea {
 }
 
 impl EditorArea {
+    #[allow(clippy::arc_with_non_send_sync)]
     pub fn new() -> Arc<RwLock<Self>> {
         let mut container = Container::new("EditorArea", None);
+        // 至少放得下标题栏中的文件名和模式
+        container.set_split(Size::Weight(3), 24);
         container.set_type(ContainerType::Father {
-            subconts: [None, None],
+            subconts: Vec::new(),
             vert_layout: true,
-            all_own: true,
+            zoom: false,
         });
         let container = Arc::new(RwLock::new(container));
         Arc::new(RwLock::new(EditorArea { container }))
@@ -55,13 +58,16 @@ pub struct WorkArea {
 }
 
 impl WorkArea {
+    #[allow(clippy::arc_with_non_send_sync)]
     pub fn new() -> Arc<RwLock<Self>> {
         let mut container = Container::new("WorkArea", None);
         container.focus();
+        // 至少放得下上下两个最小的Editor
+        container.set_split(Size::Weight(1), 6);
         container.set_type(ContainerType::Father {
-            subconts: [None, None],
+            subconts: Vec::new(),
             vert_layout: false,
-            all_own: true,
+            zoom: false,
         });
         let container = Arc::new(RwLock::new(container));
         Arc::new(RwLock::new(WorkArea { container }))
@@ -87,3 +93,85 @@ impl Component for WorkArea {
         (false, (0, 0))
     }
 }
+
+/// 左侧的ProjectViewer和SearchPanel
+pub struct SideBar {
+    container: Arc<RwLock<Container>>,
+}
+
+impl SideBar {
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new() -> Arc<RwLock<Self>> {
+        let mut container = Container::new("SideBar", None);
+        container.focus();
+        container.set_split(Size::Weight(1), 12);
+        container.set_type(ContainerType::Father {
+            subconts: Vec::new(),
+            vert_layout: true,
+            zoom: false,
+        });
+        let container = Arc::new(RwLock::new(container));
+        Arc::new(RwLock::new(SideBar { container }))
+    }
+}
+
+impl Component for SideBar {
+    fn bind_to(
+        &mut self,
+        framework: &mut Framework,
+    ) -> Result<(), Box<dyn FnOnce(Framework) -> !>> {
+        match framework.add_container("/WorkArea", Arc::clone(&self.container)) {
+            Ok(()) => Ok(()),
+            Err(s) => Err(Box::new(move |fw| {
+                drop(fw);
+                println!("{}", s);
+                exit(-1)
+            })),
+        }
+    }
+
+    fn render(&mut self, _renderer: &Renderer) -> (bool, (usize, usize)) {
+        (false, (0, 0))
+    }
+}
+
+/// 下方的Terminal和Problems
+pub struct BottomArea {
+    container: Arc<RwLock<Container>>,
+}
+
+impl BottomArea {
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new() -> Arc<RwLock<Self>> {
+        let mut container = Container::new("BottomArea", None);
+        // 终端的高度不随窗口变化，窗口小时先保证WorkArea的最小大小
+        container.set_split(Size::Fixed(12), 4);
+        container.set_type(ContainerType::Father {
+            subconts: Vec::new(),
+            vert_layout: false,
+            zoom: false,
+        });
+        let container = Arc::new(RwLock::new(container));
+        Arc::new(RwLock::new(BottomArea { container }))
+    }
+}
+
+impl Component for BottomArea {
+    fn bind_to(
+        &mut self,
+        framework: &mut Framework,
+    ) -> Result<(), Box<dyn FnOnce(Framework) -> !>> {
+        match framework.add_container("/", Arc::clone(&self.container)) {
+            Ok(()) => Ok(()),
+            Err(s) => Err(Box::new(move |fw| {
+                drop(fw);
+                println!("{}", s);
+                exit(-1)
+            })),
+        }
+    }
+
+    fn render(&mut self, _renderer: &Renderer) -> (bool, (usize, usize)) {
+        (false, (0, 0))
+    }
+}
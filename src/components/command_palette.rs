@@ -0,0 +1,281 @@
+use crossterm::{
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    style::Stylize,
+};
+use std::{
+    iter,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::{mpsc::Sender, RwLock as AsyncRwLock};
+
+use crate::{
+    fuzzy::fuzzy_match,
+    named_pipe::{NamedPipe, PipeObject},
+    renderer::{fit, Renderer},
+    text_buffer::char_width,
+    ui::container::{Container, ContainerType},
+};
+
+/// 按名字模糊查找并执行命令的弹出窗口
+pub struct CommandPalette {
+    container: Arc<RwLock<Container>>,
+    /// (命令名, 绑定的按键)
+    commands: Vec<(String, Option<String>)>,
+    query: String,
+    /// 按分数排好序的(命令下标, 匹配到的字符下标)
+    ranked: Vec<(usize, Vec<usize>)>,
+    selected: usize,
+    scroll: usize,
+    run_command_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+    close_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+}
+
+impl CommandPalette {
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new() -> Arc<RwLock<Self>> {
+        let container = Container::new("CommandPalette", None);
+        let container = Arc::new(RwLock::new(container));
+        let palette = CommandPalette {
+            container,
+            commands: Vec::new(),
+            query: String::new(),
+            ranked: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            run_command_sender: NamedPipe::open_sender(String::from("RunCommand")),
+            close_sender: NamedPipe::open_sender(String::from("ClosePopup")),
+        };
+        let res = Arc::new(RwLock::new(palette));
+        res.read()
+            .unwrap()
+            .container
+            .write()
+            .unwrap()
+            .set_type(ContainerType::CommandPalette(Arc::clone(&res)));
+        let res_ref = Arc::clone(&res);
+        res.read()
+            .unwrap()
+            .container
+            .write()
+            .unwrap()
+            .set_handler(Box::new(move |event, _contsize| {
+                if let Event::Key(KeyEvent {
+                    code, modifiers, ..
+                }) = event
+                {
+                    let mut palette = res_ref.write().unwrap();
+                    match (code, modifiers) {
+                        (KeyCode::Char(ch), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                            palette.query.push(ch);
+                            palette.rank();
+                        }
+                        (KeyCode::Backspace, _) => {
+                            palette.query.pop();
+                            palette.rank();
+                        }
+                        (KeyCode::Up, _) => {
+                            palette.selected = palette.selected.saturating_sub(1);
+                        }
+                        (KeyCode::Down, _) => {
+                            if palette.selected + 1 < palette.ranked.len() {
+                                palette.selected += 1;
+                            }
+                        }
+                        (KeyCode::Enter, _) => {
+                            if let Some((command, _)) = palette.ranked.get(palette.selected) {
+                                // 先关闭面板，命令在原来的焦点上执行
+                                palette.close();
+                                let name = palette.commands[*command].0.clone();
+                                let _ = palette
+                                    .run_command_sender
+                                    .blocking_read()
+                                    .try_send(PipeObject::RunCommand(name));
+                            }
+                        }
+                        (KeyCode::Esc, _) => palette.close(),
+                        _ => (),
+                    }
+                }
+            }));
+        res
+    }
+
+    pub fn container(&self) -> Arc<RwLock<Container>> {
+        Arc::clone(&self.container)
+    }
+
+    /// commands是命令注册表中所有命令的名字和绑定的按键
+    pub fn set_commands(&mut self, commands: Vec<(String, Option<String>)>) {
+        self.commands = commands;
+        self.rank();
+    }
+
+    /// 清空上次的输入，准备再次弹出
+    pub fn reset(&mut self) {
+        self.query.clear();
+        self.rank();
+    }
+
+    fn close(&self) {
+        let _ = self
+            .close_sender
+            .blocking_read()
+            .try_send(PipeObject::ClosePopup);
+    }
+
+    /// 按当前输入重新排序，分数相同时保持注册顺序
+    fn rank(&mut self) {
+        let mut ranked = self
+            .commands
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (name, _))| {
+                fuzzy_match(&self.query, name).map(|(score, positions)| (score, i, positions))
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        self.ranked = ranked.into_iter().map(|(_, i, p)| (i, p)).collect();
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    pub fn render(&mut self, renderer: &Renderer) -> (bool, (usize, usize)) {
+        let size = renderer.get_size();
+        if size.0 < 2 || size.1 < 3 {
+            return (false, (0, 0));
+        }
+        // 标题，右侧为匹配数
+        let count = format!("{}/{} ", self.ranked.len(), self.commands.len());
+        let title = " Run command"
+            .chars()
+            .chain(iter::repeat(' '))
+            .take(size.0.saturating_sub(count.len()))
+            .chain(count.chars())
+            .take(size.0)
+            .collect::<String>();
+        renderer.set_section(0, 0, title.dark_red().on_dark_blue());
+        // 输入行
+        let input = format!("> {}", self.query);
+        let input_width = input.chars().map(char_width).sum::<usize>();
+        renderer.set_section(0, 1, fit(input.chars(), size.0).white().on_dark_grey());
+        // 候选
+        let height = size.1 - 2;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+        for row in 0..height {
+            let linen = row + 2;
+            let Some((command, positions)) = self.ranked.get(self.scroll + row) else {
+                renderer.set_section(0, linen, " ".repeat(size.0).on_dark_grey());
+                continue;
+            };
+            let selected = self.scroll + row == self.selected;
+            let (name, keys) = &self.commands[*command];
+            // 按键显示在右侧，放不下时不显示
+            let keys = keys
+                .as_ref()
+                .map(|keys| format!(" {} ", keys))
+                .filter(|keys| keys.len() + name.chars().map(char_width).sum::<usize>() <= size.0)
+                .unwrap_or_default();
+            let name_width = size.0 - keys.len();
+            let mut x = 0;
+            for (index, ch) in name.chars().enumerate() {
+                let w = char_width(ch);
+                if x + w > name_width {
+                    break;
+                }
+                let ch = if positions.contains(&index) {
+                    ch.yellow().bold()
+                } else {
+                    ch.white()
+                };
+                renderer.set(
+                    x,
+                    linen,
+                    if selected {
+                        ch.on_blue()
+                    } else {
+                        ch.on_dark_grey()
+                    },
+                );
+                x += w;
+            }
+            let rest = " ".repeat(name_width - x) + &keys;
+            renderer.set_section(
+                x,
+                linen,
+                if selected {
+                    rest.grey().on_blue()
+                } else {
+                    rest.grey().on_dark_grey()
+                },
+            );
+        }
+        (
+            true,
+            (renderer.x + input_width.min(size.0 - 1), renderer.y + 1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::{
+        overlay::Placement,
+        testing::{self, press, type_text},
+    };
+    use crossterm::style::Color;
+
+    #[test]
+    fn filters_and_runs_commands() {
+        let mut framework = testing::framework(40, 10);
+        testing::show(&mut framework, "/WorkArea");
+        let palette = CommandPalette::new();
+        palette.write().unwrap().set_commands(vec![
+            ("IDE: Quit".to_string(), Some("ctrl+d".to_string())),
+            ("Editor: Save".to_string(), Some("ctrl+s".to_string())),
+            ("Editor: Undo".to_string(), None),
+        ]);
+        framework.open_overlay(palette.read().unwrap().container(), Placement::Center, true);
+        type_text(&mut framework, "ed");
+        framework.render();
+        assert_eq!(
+            testing::lines(&framework)[1..5],
+            [
+                " Run command                        2/3",
+                "> ed",
+                "Editor: Save                     ctrl+s",
+                "Editor: Undo",
+            ]
+        );
+        assert_eq!(framework.cursor(), Some((4, 2)));
+        // 选中的一行为蓝色背景，匹配到的字符为黄色
+        let style = testing::style(&framework, 0, 3);
+        assert_eq!(style.background_color, Some(Color::Blue));
+        assert_eq!(style.foreground_color, Some(Color::Yellow));
+        assert_eq!(
+            testing::style(&framework, 2, 3).foreground_color,
+            Some(Color::White)
+        );
+        assert_eq!(
+            testing::style(&framework, 0, 4).background_color,
+            Some(Color::DarkGrey)
+        );
+
+        let run_command = NamedPipe::open_receiver(String::from("RunCommand"));
+        let close = NamedPipe::open_receiver(String::from("ClosePopup"));
+        press(&mut framework, KeyCode::Down);
+        press(&mut framework, KeyCode::Enter);
+        assert!(matches!(
+            close.blocking_write().try_recv(),
+            Ok(PipeObject::ClosePopup)
+        ));
+        let Ok(PipeObject::RunCommand(name)) = run_command.blocking_write().try_recv() else {
+            panic!("expected a command to run");
+        };
+        assert_eq!(name, "Editor: Undo");
+    }
+}
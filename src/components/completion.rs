@@ -0,0 +1,388 @@
+use crossterm::style::{Color, Stylize};
+use std::{
+    collections::HashSet,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::RwLock as AsyncRwLock;
+
+use crate::{
+    components::editor::Editing,
+    fuzzy::fuzzy_match,
+    lsp::CompletionItem,
+    renderer::{fit, Renderer},
+    text_buffer::char_width,
+    ui::container::{Container, ContainerType},
+};
+
+/// 最多同时显示的候选数
+const MAX_ROWS: usize = 8;
+const MAX_WIDTH: usize = 60;
+/// 候选右侧的说明最多占的列数
+const MAX_DETAIL: usize = 24;
+
+/// 编辑器光标处的补全列表，两个Editor共用
+///
+/// Framework把它画在所有Container上方，位置跟随Editor返回的光标
+pub struct Completion {
+    container: Arc<RwLock<Container>>,
+    /// 打开补全的Editor的id和被补全的单词开始的位置
+    owner: Option<(usize, (usize, usize))>,
+    items: Vec<CompletionItem>,
+    /// 按分数排好序的(候选下标, 匹配到的字符下标)
+    ranked: Vec<(usize, Vec<usize>)>,
+    query: String,
+    selected: usize,
+    scroll: usize,
+    /// 在等语言服务器的回复
+    loading: bool,
+    /// 没有语言服务器时从这些缓冲区中收集单词
+    buffers: Box<dyn Fn() -> Vec<Arc<AsyncRwLock<Editing>>>>,
+}
+
+impl Completion {
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new() -> Arc<RwLock<Self>> {
+        let container = Container::new("Completion", None);
+        let container = Arc::new(RwLock::new(container));
+        let res = Arc::new(RwLock::new(Completion {
+            container,
+            owner: None,
+            items: Vec::new(),
+            ranked: Vec::new(),
+            query: String::new(),
+            selected: 0,
+            scroll: 0,
+            loading: false,
+            buffers: Box::new(Vec::new),
+        }));
+        res.read()
+            .unwrap()
+            .container
+            .write()
+            .unwrap()
+            .set_type(ContainerType::Completion(Arc::clone(&res)));
+        res
+    }
+
+    pub fn container(&self) -> Arc<RwLock<Container>> {
+        Arc::clone(&self.container)
+    }
+
+    pub fn set_buffers(&mut self, buffers: Box<dyn Fn() -> Vec<Arc<AsyncRwLock<Editing>>>>) {
+        self.buffers = buffers;
+    }
+
+    /// editor在start处开始补全，候选稍后由set_items或use_words给出
+    pub fn open(&mut self, editor: usize, start: (usize, usize)) {
+        self.owner = Some((editor, start));
+        self.items.clear();
+        self.query.clear();
+        self.loading = true;
+        self.rank();
+    }
+
+    pub fn close(&mut self) {
+        self.owner = None;
+        self.items.clear();
+        self.ranked.clear();
+        self.loading = false;
+    }
+
+    pub fn owner(&self) -> Option<(usize, (usize, usize))> {
+        self.owner
+    }
+
+    pub fn owned_by(&self, editor: usize) -> bool {
+        self.owner.is_some_and(|(owner, _)| owner == editor)
+    }
+
+    pub fn set_items(&mut self, items: Vec<CompletionItem>) {
+        self.items = items;
+        self.loading = false;
+        self.rank();
+    }
+
+    /// 用所有打开的文件中出现过的单词作为候选
+    pub fn use_words(&mut self) {
+        let mut seen = HashSet::new();
+        let mut items = Vec::new();
+        for editing in (self.buffers)() {
+            let editing = editing.blocking_read();
+            let file = editing.path().last().cloned().unwrap_or_default();
+            let text = editing.buffer().text();
+            for word in text.split(|ch: char| !is_word_char(ch)) {
+                // 正在输入的单词本身不算
+                if word.chars().count() < 2 || word == self.query || !seen.insert(word.to_string())
+                {
+                    continue;
+                }
+                items.push(CompletionItem {
+                    label: word.to_string(),
+                    detail: Some(format!("word in {}", file)),
+                    documentation: None,
+                    insert: word.to_string(),
+                });
+            }
+        }
+        self.set_items(items);
+    }
+
+    /// 按输入的单词重新过滤
+    pub fn filter(&mut self, query: &str) {
+        if self.query != query {
+            self.query = query.to_string();
+            self.rank();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.ranked.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&CompletionItem> {
+        self.ranked
+            .get(self.selected)
+            .map(|(item, _)| &self.items[*item])
+    }
+
+    /// 分数相同时短的在前
+    fn rank(&mut self) {
+        let mut ranked = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| {
+                fuzzy_match(&self.query, &item.label)
+                    .map(|(score, positions)| (score, i, positions))
+            })
+            .collect::<Vec<_>>();
+        let items = &self.items;
+        ranked.sort_by(|a, b| {
+            let (x, y) = (&items[a.1].label, &items[b.1].label);
+            b.0.cmp(&a.0)
+                .then_with(|| x.len().cmp(&y.len()))
+                .then_with(|| x.cmp(y))
+        });
+        self.ranked = ranked.into_iter().map(|(_, i, p)| (i, p)).collect();
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    /// 需要显示的大小，没有打开或没有候选时为None
+    pub fn wanted_size(&self) -> Option<(usize, usize)> {
+        self.owner?;
+        if self.loading {
+            return Some((" loading… ".chars().count(), 1));
+        }
+        if self.ranked.is_empty() {
+            return None;
+        }
+        let width = self
+            .ranked
+            .iter()
+            .map(|(item, _)| {
+                let item = &self.items[*item];
+                let detail = item
+                    .detail
+                    .as_deref()
+                    .and_then(|d| d.lines().next())
+                    .map_or(0, |d| width_of(d).min(MAX_DETAIL));
+                width_of(&item.label) + detail + 4
+            })
+            .max()
+            .unwrap_or(0)
+            .clamp(20, MAX_WIDTH);
+        // 最后一行是选中候选的说明
+        Some((width, self.ranked.len().min(MAX_ROWS) + 1))
+    }
+
+    pub fn render(&mut self, renderer: &Renderer) -> (bool, (usize, usize)) {
+        let size = renderer.get_size();
+        if size.0 == 0 || size.1 == 0 {
+            return (false, (0, 0));
+        }
+        let bg = Color::Rgb {
+            r: 0x28,
+            g: 0x28,
+            b: 0x38,
+        };
+        if self.loading {
+            renderer.set_section(0, 0, fit(" loading…".chars(), size.0).grey().on(bg));
+            return (false, (0, 0));
+        }
+        let height = size.1.saturating_sub(1).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+        for row in 0..height.min(size.1) {
+            let Some((item, positions)) = self.ranked.get(self.scroll + row) else {
+                renderer.set_section(0, row, " ".repeat(size.0).on(bg));
+                continue;
+            };
+            let item = &self.items[*item];
+            let row_bg = if self.scroll + row == self.selected {
+                Color::DarkBlue
+            } else {
+                bg
+            };
+            let detail = item
+                .detail
+                .as_deref()
+                .and_then(|d| d.lines().next())
+                .map(|d| fit(d.chars(), width_of(d).min(MAX_DETAIL)))
+                .unwrap_or_default();
+            let detail_width = width_of(&detail).min(size.0.saturating_sub(4));
+            let label_width = size.0 - detail_width - 1;
+            // 候选，匹配到的字符高亮
+            renderer.set_section(0, row, " ".to_string().on(row_bg));
+            let mut x = 1;
+            for (index, ch) in item.label.chars().enumerate() {
+                let w = char_width(ch);
+                if x + w > label_width {
+                    break;
+                }
+                let ch = if positions.contains(&index) {
+                    ch.yellow().bold()
+                } else {
+                    ch.white()
+                };
+                renderer.set(x, row, ch.on(row_bg));
+                x += w;
+            }
+            renderer.set_section(x, row, " ".repeat(label_width - x).on(row_bg));
+            renderer.set_section(
+                label_width,
+                row,
+                (fit(detail.chars(), detail_width) + " ")
+                    .dark_grey()
+                    .on(row_bg),
+            );
+        }
+        // 选中候选的说明
+        if size.1 > height {
+            let doc = self
+                .selected()
+                .and_then(|item| item.documentation.as_deref().or(item.detail.as_deref()))
+                .and_then(|doc| doc.lines().map(str::trim).find(|line| !line.is_empty()))
+                .unwrap_or_default();
+            renderer.set_section(
+                0,
+                height,
+                fit(format!(" {}", doc).chars(), size.0)
+                    .italic()
+                    .grey()
+                    .on_black(),
+            );
+        }
+        (false, (0, 0))
+    }
+}
+
+pub fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+fn width_of(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::Registry,
+        components::{editor::Editor, project_viewer::ProjectViewer},
+        ui::{
+            framework::Framework,
+            overlay::Placement,
+            testing::{self, press, type_text},
+        },
+    };
+    use crossterm::event::KeyCode;
+
+    #[test]
+    fn lists_words_at_cursor() {
+        let root = testing::project("completion", &[("a.txt", "value valid other\n\n")]);
+        let mut framework = testing::framework(40, 10);
+        let viewer = ProjectViewer::new(root);
+        testing::bind(&mut framework, &viewer);
+        let completion = Completion::new();
+        let pv = Arc::clone(&viewer);
+        completion
+            .write()
+            .unwrap()
+            .set_buffers(Box::new(move || pv.read().unwrap().editings()));
+        framework.open_overlay(
+            completion.read().unwrap().container(),
+            Placement::Cursor,
+            false,
+        );
+        let editors = [
+            Editor::new(0, Arc::clone(&completion)),
+            Editor::new(1, Arc::clone(&completion)),
+        ];
+        for editor in &editors {
+            testing::bind(&mut framework, editor);
+        }
+        let mut registry = Registry::default();
+        Editor::register_commands(editors, &mut registry);
+        viewer
+            .write()
+            .unwrap()
+            .open_file(vec!["a.txt".to_string()], None);
+        testing::show(&mut framework, "/WorkArea/EditorArea/Editor0");
+
+        registry.run("Editor: Edit Mode", &mut framework);
+        press(&mut framework, KeyCode::Down);
+        type_text(&mut framework, "va");
+        registry.run("Editor: Trigger Completion", &mut framework);
+        framework.render();
+        // 列表画在光标的下一行，最后一行是选中候选的说明
+        let text = |framework: &Framework| {
+            testing::lines(framework)
+                .iter()
+                .map(|line| line.chars().skip(1).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            text(&framework)[2..7],
+            [
+                "value valid other",
+                "va",
+                "   valid  word in a.txt",
+                "   value  word in a.txt",
+                "   word in a.txt",
+            ]
+        );
+        assert_eq!(framework.cursor(), Some((3, 3)));
+        let style = testing::style(&framework, 4, 4);
+        assert_eq!(style.background_color, Some(Color::DarkBlue));
+        assert_eq!(style.foreground_color, Some(Color::Yellow));
+        assert_eq!(
+            testing::style(&framework, 6, 4).foreground_color,
+            Some(Color::White)
+        );
+        assert_eq!(
+            testing::style(&framework, 4, 5).background_color,
+            Some(Color::Rgb {
+                r: 0x28,
+                g: 0x28,
+                b: 0x38
+            })
+        );
+
+        press(&mut framework, KeyCode::Down);
+        press(&mut framework, KeyCode::Enter);
+        framework.render();
+        assert_eq!(text(&framework)[3..5], ["value", ""]);
+        assert_eq!(framework.cursor(), Some((6, 3)));
+    }
+}
@@ -2,23 +2,37 @@ use crossterm::{
     event::{Event, KeyCode, KeyEvent, KeyModifiers},
     style::{Color, Stylize},
 };
+use ropey::RopeSlice;
 use std::{
-    collections::HashMap,
-    fs::OpenOptions,
-    io::{BufRead, BufReader},
+    fs::{self, OpenOptions},
+    io::{self, BufReader, BufWriter},
     iter,
+    path::PathBuf,
     process::exit,
     sync::{Arc, Mutex, RwLock},
 };
-use tokio::sync::{mpsc::Receiver, RwLock as AsyncRwLock};
-use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use tokio::sync::{
+    mpsc::{Receiver, Sender},
+    RwLock as AsyncRwLock,
+};
 
 use crate::{
-    components::component::Component,
+    clipboard,
+    command::Registry,
+    components::{
+        completion::{is_word_char, Completion},
+        component::Component,
+    },
+    diagnostic::{Marker, Severity},
+    highlight::{Highlighter, Span, TokenKind},
+    history::{end_of, Change, History},
+    lsp::{Client, CompletionItem, Location, Pending, TextEdit},
     named_pipe::{NamedPipe, PipeObject},
-    renderer::Renderer,
+    renderer::{fit, truncate, Renderer},
+    search::{line_text, Matcher, SearchOptions},
+    text_buffer::{char_width, width_at, TextBuffer, TAB_WIDTH},
     ui::{
-        container::{Container, ContainerType},
+        container::{Container, ContainerType, Size},
         framework::Framework,
     },
 };
@@ -29,6 +43,33 @@ enum EditorMode {
     Edit,
 }
 
+/// 命令模式下在标题栏输入的提示
+enum Prompt {
+    /// 输入查询，origin为打开提示时光标的位置，replace表示之后还要输入替换文本
+    Search {
+        query: String,
+        origin: (usize, usize),
+        replace: bool,
+        error: Option<String>,
+    },
+    /// 输入替换文本
+    Replace { with: String },
+    /// 从文件开头逐个确认替换
+    Confirm { with: String },
+    /// 输入光标处符号的新名字
+    Rename { name: String },
+}
+
+/// 发给语言服务器、还没收到回复的请求
+enum LspRequest {
+    Hover(Pending<Option<String>>),
+    Definition(Pending<Vec<Location>>),
+    /// 请求时所在的文件和光标位置，用来找下一个引用
+    References(Pending<Vec<Location>>, PathBuf, (usize, usize)),
+    Rename(Pending<Vec<(PathBuf, Vec<TextEdit>)>>),
+    Completion(Pending<Vec<CompletionItem>>),
+}
+
 pub struct Editor {
     container: Arc<RwLock<Container>>,
     id: usize,
@@ -40,11 +81,26 @@ pub struct Editor {
     file_open_receiver: Arc<AsyncRwLock<Receiver<PipeObject>>>,
     last_rh: Arc<Mutex<usize>>,
     cursor: (usize, usize),
+    prompt: Option<Prompt>,
+    search_options: SearchOptions,
+    /// 最近一次搜索，高亮所有匹配并用于n/N跳转
+    matcher: Option<Matcher>,
+    /// 光标所在的匹配，(行号, 字符下标区间)
+    current_match: Option<(usize, (usize, usize))>,
+    lsp_request: Option<LspRequest>,
+    /// 显示在标题栏的消息，按下任意键后消失
+    notice: Option<String>,
+    open_file_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+    apply_edits_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+    completion: Arc<RwLock<Completion>>,
 }
 
 impl Editor {
-    pub fn new(id: usize) -> Arc<RwLock<Self>> {
-        let container = Container::new(&("Editor".to_string() + &id.to_string()), None);
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(id: usize, completion: Arc<RwLock<Completion>>) -> Arc<RwLock<Self>> {
+        let mut container = Container::new(&("Editor".to_string() + &id.to_string()), None);
+        // 标题栏和至少两行文本
+        container.set_split(Size::Weight(1), 3);
         let container = Arc::new(RwLock::new(container));
         let res = Arc::new(RwLock::new(Editor {
             container,
@@ -54,6 +110,15 @@ impl Editor {
             file_open_receiver: NamedPipe::open_receiver(format!("FileOpen{}", id)),
             last_rh: Arc::new(Mutex::new(0)),
             cursor: (0, 1),
+            prompt: None,
+            search_options: SearchOptions::default(),
+            matcher: None,
+            current_match: None,
+            lsp_request: None,
+            notice: None,
+            open_file_sender: NamedPipe::open_sender(String::from("OpenFile")),
+            apply_edits_sender: NamedPipe::open_sender(String::from("ApplyEdits")),
+            completion,
         }));
         res.read()
             .unwrap()
@@ -67,38 +132,784 @@ impl Editor {
             .container
             .write()
             .unwrap()
-            .set_handler(Box::new(move |event, contsize| match event {
-                Event::Key(KeyEvent {
+            .set_handler(Box::new(move |event, contsize| {
+                if let Event::Key(_) = event {
+                    res_ref.write().unwrap().notice = None;
+                }
+                if res_ref.write().unwrap().completion_key(&event, contsize) {
+                    return;
+                }
+                if res_ref.read().unwrap().prompt.is_some() {
+                    if let Event::Key(KeyEvent {
+                        code, modifiers, ..
+                    }) = event
+                    {
+                        res_ref.write().unwrap().prompt_key(code, modifiers, contsize);
+                    }
+                } else if let Event::Key(KeyEvent {
                     code,
-                    modifiers: KeyModifiers::NONE,
+                    modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
                     ..
-                }) => match code {
-                    KeyCode::Up => {
-                        res_ref.write().unwrap().cursor_up(contsize);
-                    }
-                    KeyCode::Down => {
-                        res_ref.write().unwrap().cursor_down(contsize);
-                    }
-                    KeyCode::Left => {
-                        res_ref.write().unwrap().cursor_left(contsize);
+                }) = event
+                {
+                    let mode = res_ref.read().unwrap().mode;
+                    match (mode, code) {
+                        (_, KeyCode::Up) => {
+                            res_ref.write().unwrap().cursor_up(contsize);
+                        }
+                        (_, KeyCode::Down) => {
+                            res_ref.write().unwrap().cursor_down(contsize);
+                        }
+                        (_, KeyCode::Left) => {
+                            res_ref.write().unwrap().cursor_left(contsize);
+                        }
+                        (_, KeyCode::Right) => {
+                            res_ref.write().unwrap().cursor_right(contsize);
+                        }
+                        (EditorMode::Edit, KeyCode::Char(ch)) => {
+                            let mut editor = res_ref.write().unwrap();
+                            editor.insert_char(ch, contsize);
+                            // 输入成员访问或路径分隔符后自动向语言服务器请求补全
+                            if editor.triggers_completion(ch) {
+                                editor.open_completion();
+                            }
+                        }
+                        (EditorMode::Edit, KeyCode::Tab) => {
+                            for _ in 0..TAB_WIDTH {
+                                res_ref.write().unwrap().insert_char(' ', contsize);
+                            }
+                        }
+                        (EditorMode::Edit, KeyCode::Backspace) => {
+                            res_ref.write().unwrap().backspace(contsize);
+                        }
+                        (EditorMode::Edit, KeyCode::Delete) => {
+                            res_ref.write().unwrap().delete(contsize);
+                        }
+                        (EditorMode::Edit, KeyCode::Enter) => {
+                            res_ref.write().unwrap().new_line(contsize);
+                        }
+                        _ => (),
                     }
-                    KeyCode::Right => {
-                        res_ref.write().unwrap().cursor_right(contsize);
+                }
+                res_ref.write().unwrap().update_completion();
+            }));
+        res
+    }
+
+    /// 注册编辑器的命令，命令作用于有焦点的Editor，都没有焦点时作用于Editor0
+    pub fn register_commands(editors: [Arc<RwLock<Self>>; 2], registry: &mut Registry) {
+        type Command = fn(&mut Editor, (usize, usize));
+        let commands: [(&str, Command); 15] = [
+            ("Editor: Save", |editor, _| editor.save()),
+            ("Editor: Undo", Editor::undo),
+            ("Editor: Redo", Editor::redo),
+            ("Editor: Paste", Editor::paste),
+            ("Editor: Find", |editor, _| editor.open_prompt(false)),
+            ("Editor: Find and Replace", |editor, _| {
+                editor.open_prompt(true)
+            }),
+            ("Editor: Find Next", |editor, size| {
+                editor.find_next(true, size)
+            }),
+            ("Editor: Find Previous", |editor, size| {
+                editor.find_next(false, size)
+            }),
+            ("Editor: Show Hover", |editor, _| editor.hover()),
+            ("Editor: Go to Definition", |editor, _| editor.definition()),
+            ("Editor: Find References", |editor, _| editor.references()),
+            ("Editor: Rename Symbol", |editor, _| editor.start_rename()),
+            ("Editor: Trigger Completion", |editor, _| {
+                editor.mode = EditorMode::Edit;
+                editor.open_completion();
+            }),
+            ("Editor: Edit Mode", |editor, _| {
+                editor.mode = EditorMode::Edit
+            }),
+            ("Editor: Command Mode", |editor, _| {
+                editor.mode = EditorMode::Command
+            }),
+        ];
+        for (name, command) in commands {
+            let editors = editors.clone();
+            registry.register(name, move |framework| {
+                let editor = editors
+                    .iter()
+                    .find(|editor| editor.read().unwrap().container.read().unwrap().focused())
+                    .unwrap_or(&editors[0]);
+                let id = editor.read().unwrap().id;
+                framework.set_focus(&format!("/WorkArea/EditorArea/Editor{}", id));
+                let mut editor = editor.write().unwrap();
+                editor.notice = None;
+                let size = editor.container.read().unwrap().get_size();
+                command(&mut editor, size);
+            });
+        }
+    }
+
+    /// 按键绑定的上下文，输入框或补全列表打开时按键都交给它们
+    pub fn key_context(&self) -> Option<&'static str> {
+        if self.prompt.is_some() || self.completion.read().unwrap().owned_by(self.id) {
+            return None;
+        }
+        Some(match self.mode {
+            EditorMode::Command => "editor",
+            EditorMode::Edit => "editor_edit",
+        })
+    }
+
+    fn save(&self) {
+        if let Some(file) = &self.file {
+            // 失败信息记录在Editing中并显示在标题栏
+            let _ = file.blocking_write().save();
+        }
+    }
+
+    fn undo(&mut self, contsize: (usize, usize)) {
+        if let Some(pos) = self.edit(Editing::undo).flatten() {
+            self.jump_to(pos, contsize);
+        }
+    }
+
+    fn redo(&mut self, contsize: (usize, usize)) {
+        if let Some(pos) = self.edit(Editing::redo).flatten() {
+            self.jump_to(pos, contsize);
+        }
+    }
+
+    /// 在光标处插入IDE剪贴板中的内容，作为一次编辑撤销
+    fn paste(&mut self, contsize: (usize, usize)) {
+        let text = clipboard::paste().chars().collect::<Vec<_>>();
+        if text.is_empty() {
+            return;
+        }
+        if let Some(pos) = self.position() {
+            if let Some(end) = self.edit(|f| {
+                f.seal_history();
+                let end = f.replace(pos, pos, &text);
+                f.seal_history();
+                end
+            }) {
+                self.jump_to(end, contsize);
+            }
+        }
+    }
+
+    /// 打开搜索提示，replace为true时确认查询后继续输入替换文本
+    fn open_prompt(&mut self, replace: bool) {
+        if let Some(origin) = self.position() {
+            self.prompt = Some(Prompt::Search {
+                query: String::new(),
+                origin,
+                replace,
+                error: None,
+            });
+        }
+    }
+
+    fn prompt_key(&mut self, code: KeyCode, modifiers: KeyModifiers, contsize: (usize, usize)) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        self.prompt = match prompt {
+            Prompt::Search {
+                mut query,
+                origin,
+                replace,
+                error: _,
+            } => match code {
+                KeyCode::Esc => {
+                    self.matcher = None;
+                    self.current_match = None;
+                    self.jump_to(origin, contsize);
+                    None
+                }
+                KeyCode::Enter if self.matcher.is_none() => None,
+                KeyCode::Enter if replace => Some(Prompt::Replace {
+                    with: String::new(),
+                }),
+                KeyCode::Enter => None,
+                _ => {
+                    match (code, modifiers) {
+                        (KeyCode::Char('c'), KeyModifiers::ALT) => {
+                            self.search_options.case_sensitive ^= true;
+                        }
+                        (KeyCode::Char('w'), KeyModifiers::ALT) => {
+                            self.search_options.whole_word ^= true;
+                        }
+                        (KeyCode::Char('r'), KeyModifiers::ALT) => {
+                            self.search_options.regex ^= true;
+                        }
+                        (KeyCode::Char(ch), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                            query.push(ch);
+                        }
+                        (KeyCode::Backspace, _) => {
+                            query.pop();
+                        }
+                        _ => (),
                     }
-                    KeyCode::Esc => {
-                        res_ref.write().unwrap().mode = EditorMode::Command;
+                    let error = self.update_search(&query, origin, contsize).err();
+                    Some(Prompt::Search {
+                        query,
+                        origin,
+                        replace,
+                        error,
+                    })
+                }
+            },
+            Prompt::Replace { mut with } => match code {
+                KeyCode::Esc => None,
+                KeyCode::Enter => {
+                    // 从文件开头开始逐个确认
+                    self.current_match = None;
+                    self.confirm_next((0, 0), contsize);
+                    self.current_match.map(|_| Prompt::Confirm { with })
+                }
+                KeyCode::Char(ch) => {
+                    with.push(ch);
+                    Some(Prompt::Replace { with })
+                }
+                KeyCode::Backspace => {
+                    with.pop();
+                    Some(Prompt::Replace { with })
+                }
+                _ => Some(Prompt::Replace { with }),
+            },
+            Prompt::Confirm { with } => {
+                let (Some(matcher), Some((line, range))) = (&self.matcher, self.current_match)
+                else {
+                    return;
+                };
+                match code {
+                    KeyCode::Char('y') => {
+                        let end = self
+                            .edit(|f| {
+                                f.seal_history();
+                                f.replace_match(matcher, line, range, &with)
+                            })
+                            .unwrap_or((line, range.1));
+                        self.confirm_next(end, contsize);
                     }
-                    KeyCode::Char('e') => {
-                        res_ref.write().unwrap().mode = EditorMode::Edit;
+                    KeyCode::Char('n') => self.confirm_next((line, range.1), contsize),
+                    KeyCode::Char('a') => {
+                        self.edit(|f| f.replace_all(matcher, (line, range.0), &with));
+                        self.current_match = None;
                     }
+                    KeyCode::Char('q') | KeyCode::Esc => self.current_match = None,
                     _ => (),
+                }
+                self.current_match.map(|_| Prompt::Confirm { with })
+            }
+            Prompt::Rename { mut name } => match code {
+                KeyCode::Esc => None,
+                KeyCode::Enter => {
+                    if !name.is_empty() {
+                        self.rename(&name);
+                    }
+                    None
+                }
+                KeyCode::Char(ch) => {
+                    name.push(ch);
+                    Some(Prompt::Rename { name })
+                }
+                KeyCode::Backspace => {
+                    name.pop();
+                    Some(Prompt::Rename { name })
+                }
+                _ => Some(Prompt::Rename { name }),
+            },
+        };
+    }
+
+    /// 当前文件的语言服务器、文件路径和光标位置，没有语言服务器时提示
+    fn lsp_target(&mut self) -> Option<(Arc<Client>, PathBuf, (usize, usize))> {
+        let at = self.position()?;
+        let target = self.file.as_ref()?.blocking_read().lsp();
+        if target.is_none() {
+            self.notice = Some("✗ no language server for this file".to_string());
+        }
+        let (client, path) = target?;
+        Some((client, path, at))
+    }
+
+    fn hover(&mut self) {
+        if let Some((client, path, at)) = self.lsp_target() {
+            self.lsp_request = Some(LspRequest::Hover(client.hover(&path, at)));
+        }
+    }
+
+    fn definition(&mut self) {
+        if let Some((client, path, at)) = self.lsp_target() {
+            self.lsp_request = Some(LspRequest::Definition(client.definition(&path, at)));
+        }
+    }
+
+    fn references(&mut self) {
+        if let Some((client, path, at)) = self.lsp_target() {
+            let pending = client.references(&path, at);
+            self.lsp_request = Some(LspRequest::References(pending, path, at));
+        }
+    }
+
+    /// 打开输入新名字的提示
+    fn start_rename(&mut self) {
+        if self.lsp_target().is_some() {
+            self.prompt = Some(Prompt::Rename {
+                name: String::new(),
+            });
+        }
+    }
+
+    fn rename(&mut self, name: &str) {
+        if let Some((client, path, at)) = self.lsp_target() {
+            self.lsp_request = Some(LspRequest::Rename(client.rename(&path, at, name)));
+        }
+    }
+
+    /// 在光标前的单词处打开补全，有语言服务器时向它请求候选，否则使用打开的文件中的单词
+    fn open_completion(&mut self) {
+        let (EditorMode::Edit, Some((line, index))) = (self.mode, self.position()) else {
+            return;
+        };
+        let start = self
+            .edit(|f| {
+                let text = line_text(f.buffer(), line).chars().collect::<Vec<_>>();
+                let mut start = index.min(text.len());
+                while start > 0 && is_word_char(text[start - 1]) {
+                    start -= 1;
+                }
+                start
+            })
+            .unwrap_or(index);
+        let mut completion = self.completion.write().unwrap();
+        completion.open(self.id, (line, start));
+        drop(completion);
+        self.update_completion();
+        match self.file.as_ref().and_then(|f| f.blocking_read().lsp()) {
+            Some((client, path)) => {
+                let pending = client.completion(&path, (line, index));
+                self.lsp_request = Some(LspRequest::Completion(pending));
+            }
+            None => self.completion.write().unwrap().use_words(),
+        }
+    }
+
+    /// 刚输入的ch是否应该自动打开补全
+    fn triggers_completion(&self, ch: char) -> bool {
+        let Some((line, index)) = self.position() else {
+            return false;
+        };
+        let attached = self.edit(|f| f.lsp().is_some()).unwrap_or(false);
+        let before = self
+            .edit(|f| line_text(f.buffer(), line).chars().collect::<Vec<_>>())
+            .and_then(|text| index.checked_sub(2).and_then(|i| text.get(i).copied()));
+        attached && (ch == '.' || (ch == ':' && before == Some(':')))
+    }
+
+    /// 补全列表打开时处理选择候选的按键，返回按键是否已被处理
+    fn completion_key(&mut self, event: &Event, contsize: (usize, usize)) -> bool {
+        let Event::Key(KeyEvent { code, .. }) = event else {
+            return false;
+        };
+        let mut completion = self.completion.write().unwrap();
+        let Some((owner, start)) = completion.owner() else {
+            return false;
+        };
+        if owner != self.id {
+            return false;
+        }
+        match code {
+            KeyCode::Up => completion.select_prev(),
+            KeyCode::Down => completion.select_next(),
+            KeyCode::Esc => completion.close(),
+            KeyCode::Enter | KeyCode::Tab => {
+                let item = completion.selected().cloned();
+                completion.close();
+                drop(completion);
+                // 还没有候选时按键照常处理
+                let Some(item) = item else {
+                    return false;
+                };
+                self.accept_completion(start, &item, contsize);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// 用选中的候选替换start到光标之间的单词，作为一次编辑撤销
+    fn accept_completion(
+        &mut self,
+        start: (usize, usize),
+        item: &CompletionItem,
+        contsize: (usize, usize),
+    ) {
+        let Some(pos) = self.position() else {
+            return;
+        };
+        let text = item.insert.chars().collect::<Vec<_>>();
+        if let Some(end) = self.edit(|f| {
+            f.seal_history();
+            let end = f.replace(start, pos, &text);
+            f.seal_history();
+            end
+        }) {
+            self.jump_to(end, contsize);
+        }
+    }
+
+    /// 按光标前输入的单词过滤补全列表，光标离开这个单词时关闭
+    fn update_completion(&mut self) {
+        let mut completion = self.completion.write().unwrap();
+        let Some((owner, (line, start))) = completion.owner() else {
+            return;
+        };
+        if owner != self.id {
+            return;
+        }
+        let query = match self.position() {
+            Some((l, index)) if l == line && index >= start => self.edit(|f| {
+                line_text(f.buffer(), line)
+                    .chars()
+                    .skip(start)
+                    .take(index - start)
+                    .collect::<String>()
+            }),
+            _ => None,
+        };
+        match query {
+            Some(query) if query.chars().all(is_word_char) => completion.filter(&query),
+            _ => completion.close(),
+        }
+    }
+
+    fn close_completion(&self) {
+        let mut completion = self.completion.write().unwrap();
+        if completion.owned_by(self.id) {
+            completion.close();
+        }
+    }
+
+    /// 取回语言服务器的回复
+    fn receive_lsp(&mut self, contsize: (usize, usize)) {
+        let Some(request) = self.lsp_request.take() else {
+            return;
+        };
+        let Some((client, path)) = self.file.as_ref().and_then(|f| f.blocking_read().lsp()) else {
+            return;
+        };
+        let notice = match &request {
+            LspRequest::Hover(pending) => match pending.poll() {
+                // 只显示第一行，一般是符号的签名
+                Some(Ok(Some(text))) => text
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty() && !line.starts_with("```"))
+                    .map(str::to_string),
+                Some(Ok(None)) => Some("no hover information".to_string()),
+                Some(Err(e)) => Some(format!("✗ {}", e)),
+                None => {
+                    self.lsp_request = Some(request);
+                    return;
+                }
+            },
+            LspRequest::Definition(pending) => match pending.poll() {
+                Some(Ok(locations)) => match locations.first() {
+                    Some(location) => self.goto(&client, &path, location, contsize),
+                    None => Some("no definition found".to_string()),
                 },
-                _ => (),
-            }));
-        res
+                Some(Err(e)) => Some(format!("✗ {}", e)),
+                None => {
+                    self.lsp_request = Some(request);
+                    return;
+                }
+            },
+            LspRequest::References(pending, from, origin) => match pending.poll() {
+                Some(Ok(mut locations)) if !locations.is_empty() => {
+                    locations.sort_by(|a, b| (&a.path, a.start).cmp(&(&b.path, b.start)));
+                    // 跳到请求位置之后的下一个引用，到最后时回到第一个
+                    let next = locations
+                        .iter()
+                        .position(|l| (&l.path, l.start) > (from, *origin))
+                        .unwrap_or(0);
+                    self.goto(&client, &path, &locations[next], contsize)
+                        .or(Some(format!("reference {}/{}", next + 1, locations.len())))
+                }
+                Some(Ok(_)) => Some("no references found".to_string()),
+                Some(Err(e)) => Some(format!("✗ {}", e)),
+                None => {
+                    self.lsp_request = Some(request);
+                    return;
+                }
+            },
+            LspRequest::Rename(pending) => match pending.poll() {
+                Some(Ok(files)) if !files.is_empty() => {
+                    let count = files.len();
+                    let files = files
+                        .into_iter()
+                        .filter_map(|(path, edits)| Some((client.relative(&path)?, edits)))
+                        .collect::<Vec<_>>();
+                    let skipped = count - files.len();
+                    let _ = self
+                        .apply_edits_sender
+                        .blocking_read()
+                        .try_send(PipeObject::ApplyEdits(files));
+                    if skipped > 0 {
+                        Some(format!(
+                            "✗ {} files outside the project not renamed",
+                            skipped
+                        ))
+                    } else {
+                        Some(format!("renamed in {} files", count))
+                    }
+                }
+                Some(Ok(_)) => Some("nothing to rename".to_string()),
+                Some(Err(e)) => Some(format!("✗ {}", e)),
+                None => {
+                    self.lsp_request = Some(request);
+                    return;
+                }
+            },
+            LspRequest::Completion(pending) => {
+                let items = match pending.poll() {
+                    Some(Ok(items)) => items,
+                    Some(Err(_)) => Vec::new(),
+                    None => {
+                        self.lsp_request = Some(request);
+                        return;
+                    }
+                };
+                let mut completion = self.completion.write().unwrap();
+                // 等待期间补全可能已经关闭
+                if completion.owned_by(self.id) {
+                    if items.is_empty() {
+                        completion.use_words();
+                    } else {
+                        completion.set_items(items);
+                    }
+                }
+                return;
+            }
+        };
+        self.notice = notice;
+    }
+
+    /// 跳到语言服务器给出的位置，其他文件交给ProjectViewer打开，失败时返回提示
+    fn goto(
+        &mut self,
+        client: &Client,
+        current: &PathBuf,
+        location: &Location,
+        contsize: (usize, usize),
+    ) -> Option<String> {
+        if location.path == *current {
+            self.edit(Editing::seal_history);
+            self.jump_to(location.start, contsize);
+            return None;
+        }
+        let Some(path) = client.relative(&location.path) else {
+            return Some(format!(
+                "✗ outside the project: {}",
+                location.path.display()
+            ));
+        };
+        let _ = self
+            .open_file_sender
+            .blocking_read()
+            .try_send(PipeObject::OpenFile(path, Some(location.start)));
+        None
+    }
+
+    /// 重新编译查询，并把光标移到origin之后的第一个匹配
+    fn update_search(
+        &mut self,
+        query: &str,
+        origin: (usize, usize),
+        contsize: (usize, usize),
+    ) -> Result<(), String> {
+        self.matcher = None;
+        self.current_match = None;
+        let matcher = Matcher::new(query, self.search_options);
+        if let Ok(Some(matcher)) = &matcher {
+            self.current_match = self.file.as_ref().and_then(|file| {
+                matcher.find(file.blocking_read().buffer(), origin, true)
+            });
+        }
+        match self.current_match {
+            Some((line, range)) => self.jump_to((line, range.0), contsize),
+            None => self.jump_to(origin, contsize),
+        }
+        self.matcher = matcher?;
+        Ok(())
+    }
+
+    /// 跳到光标之后(forward)或之前的匹配
+    fn find_next(&mut self, forward: bool, contsize: (usize, usize)) {
+        let (Some(matcher), Some(file), Some((line, index))) =
+            (&self.matcher, &self.file, self.position())
+        else {
+            return;
+        };
+        let from = if forward {
+            (line, index + 1)
+        } else {
+            (line, index)
+        };
+        self.current_match = matcher.find(file.blocking_read().buffer(), from, forward);
+        if let Some((line, range)) = self.current_match {
+            self.edit(Editing::seal_history);
+            self.jump_to((line, range.0), contsize);
+        }
+    }
+
+    /// 逐个确认替换时跳到from之后的匹配，到文件末尾时结束
+    fn confirm_next(&mut self, from: (usize, usize), contsize: (usize, usize)) {
+        let (Some(matcher), Some(file)) = (&self.matcher, &self.file) else {
+            return;
+        };
+        self.current_match = matcher
+            .find(file.blocking_read().buffer(), from, true)
+            .filter(|&(line, range)| (line, range.0) >= from);
+        if let Some((line, range)) = self.current_match {
+            self.jump_to((line, range.0), contsize);
+        }
+    }
+
+    /// 把光标移到buffer中的(行号, 字符下标)，必要时纵向滚动
+    fn jump_to(&mut self, (line, index): (usize, usize), contsize: (usize, usize)) {
+        let rows = contsize.1.saturating_sub(1).max(1);
+        if let Some((line, showing_start, column)) = self.edit(|f| {
+            // 跳转目标可能来自外部，超出文件时停在最后一行
+            let line = line.min(f.line_count().saturating_sub(1));
+            if line < f.showing_start {
+                f.showing_start = line;
+            } else if line >= f.showing_start + rows {
+                f.showing_start = line + 1 - rows;
+            }
+            (line, f.showing_start, f.column_of(line, index))
+        }) {
+            self.cursor.1 = line - showing_start + 1;
+            self.place_cursor(column, contsize);
+        }
+    }
+
+    /// 光标所在的行号和字符下标，行号为buffer中的下标
+    fn position(&self) -> Option<(usize, usize)> {
+        let file = self.file.as_ref()?.blocking_read();
+        let line = file.showing_start + self.cursor.1 - 1;
+        if line >= file.line_count() {
+            return None;
+        }
+        Some((line, file.index_of(line, file.line_start + self.cursor.0)))
+    }
+
+    fn edit<R>(&self, f: impl FnOnce(&mut Editing) -> R) -> Option<R> {
+        self.file.as_ref().map(|file| f(&mut file.blocking_write()))
+    }
+
+    /// 把光标放到当前行的第column显示列，必要时横向滚动
+    fn place_cursor(&mut self, column: usize, contsize: (usize, usize)) {
+        let width = self.text_width(contsize).max(2);
+        if let Some(file) = &self.file {
+            let mut file = file.blocking_write();
+            while column < file.line_start {
+                file.line_start = file.line_start.saturating_sub(2);
+            }
+            while column >= file.line_start + width {
+                file.line_start += 2;
+            }
+            self.cursor.0 = column - file.line_start;
+        }
+    }
+
+    /// 左侧标记诊断的列宽，文件没有诊断时不占位置
+    fn gutter(&self) -> usize {
+        match &self.file {
+            Some(file) if file.blocking_read().has_markers() => 1,
+            _ => 0,
+        }
+    }
+
+    /// 除去左侧标记列后显示文本的宽度
+    fn text_width(&self, contsize: (usize, usize)) -> usize {
+        contsize.0.saturating_sub(self.gutter())
+    }
+
+    fn cursor_line_up(&mut self) {
+        if self.cursor.1 > 1 {
+            self.cursor.1 -= 1;
+        } else {
+            self.scroll_up(1);
+        }
+    }
+
+    fn cursor_line_down(&mut self, contsize: (usize, usize)) {
+        if self.cursor.1 + 1 < contsize.1 {
+            self.cursor.1 += 1;
+        } else if let Some(file) = &self.file {
+            file.blocking_write().showing_start += 1;
+        }
+    }
+
+    fn insert_char(&mut self, ch: char, contsize: (usize, usize)) {
+        if let Some((line, index)) = self.position() {
+            if let Some(column) = self.edit(|f| {
+                f.insert(line, index, ch);
+                f.column_of(line, index + 1)
+            }) {
+                self.place_cursor(column, contsize);
+            }
+        }
+    }
+
+    fn backspace(&mut self, contsize: (usize, usize)) {
+        if let Some((line, index)) = self.position() {
+            if index > 0 {
+                if let Some(column) = self.edit(|f| {
+                    f.remove(line, index - 1);
+                    f.column_of(line, index - 1)
+                }) {
+                    self.place_cursor(column, contsize);
+                }
+            } else if line > 0 {
+                // 行首退格，与上一行合并
+                if let Some(column) = self.edit(|f| {
+                    let column = f.column_of(line - 1, f.content_len(line - 1));
+                    f.join_line(line - 1);
+                    column
+                }) {
+                    self.cursor_line_up();
+                    self.place_cursor(column, contsize);
+                }
+            }
+        }
+    }
+
+    fn delete(&mut self, contsize: (usize, usize)) {
+        if let Some((line, index)) = self.position() {
+            if let Some(column) = self.edit(|f| {
+                if index < f.content_len(line) {
+                    f.remove(line, index);
+                } else {
+                    // 行尾删除，与下一行合并
+                    f.join_line(line);
+                }
+                f.column_of(line, index)
+            }) {
+                self.place_cursor(column, contsize);
+            }
+        }
+    }
+
+    fn new_line(&mut self, contsize: (usize, usize)) {
+        if let Some((line, index)) = self.position() {
+            self.edit(|f| f.split_line(line, index));
+            self.cursor_line_down(contsize);
+            self.place_cursor(0, contsize);
+        }
     }
 
     fn cursor_up(&mut self, _contsize: (usize, usize)) {
+        self.edit(Editing::seal_history);
         match self.mode {
             EditorMode::Command => {
                 self.scroll_up(1);
@@ -114,6 +925,7 @@ impl Editor {
     }
 
     fn cursor_down(&mut self, contsize: (usize, usize)) {
+        self.edit(Editing::seal_history);
         match self.mode {
             EditorMode::Command => {
                 self.scroll_down(1);
@@ -131,44 +943,29 @@ impl Editor {
     }
 
     fn cursor_left(&mut self, contsize: (usize, usize)) {
+        self.edit(Editing::seal_history);
         match self.mode {
             EditorMode::Command => {
                 self.scroll_left(1);
-                if self.cursor.0 < contsize.0 {
-                    self.cursor.0 += if self.cursor.0 + 1 == contsize.0 {
-                        1
-                    } else {
-                        2
-                    };
+                let width = self.text_width(contsize);
+                if self.cursor.0 < width {
+                    self.cursor.0 += if self.cursor.0 + 1 == width { 1 } else { 2 };
                 }
             }
             EditorMode::Edit => {
-                if self.cursor.0 > 0 {
-                    let l = if let Some(f) = &self.file {
-                        let shst = f.blocking_read().showing_start;
-                        let lnst = f.blocking_read().line_start;
-                        let linelen = f.blocking_read().len_of_line(shst + self.cursor.1 - 1);
-                        if linelen < lnst {
-                            0
-                        } else {
-                            linelen - lnst
+                if let Some((line, index)) = self.position() {
+                    if index > 0 {
+                        if let Some(column) = self.edit(|f| f.column_of(line, index - 1)) {
+                            self.place_cursor(column, contsize);
                         }
-                    } else {
-                        0
-                    };
-                    if self.cursor.0 > l {
-                        self.cursor.0 = l;
-                    } else {
-                        self.cursor.0 -= 1;
                     }
-                } else {
-                    self.scroll_left(1);
                 }
             }
         }
     }
 
     fn cursor_right(&mut self, contsize: (usize, usize)) {
+        self.edit(Editing::seal_history);
         match self.mode {
             EditorMode::Command => {
                 self.scroll_right(1);
@@ -177,22 +974,14 @@ impl Editor {
                 }
             }
             EditorMode::Edit => {
-                let l = if let Some(f) = &self.file {
-                    let shst = f.blocking_read().showing_start;
-                    let lnst = f.blocking_read().line_start;
-                    f.blocking_read().len_of_line(shst + self.cursor.1 - 1) - lnst
-                } else {
-                    0
-                };
-                if self.cursor.0 < contsize.0 - 1 {
-                    if self.cursor.0 < l {
-                        self.cursor.0 += 1;
-                    }
-                } else {
-                    if let Some(f) = &self.file {
-                        if self.cursor.0 < l - 1 {
-                            self.scroll_right(1);
-                        }
+                if let Some((line, index)) = self.position() {
+                    if let Some(column) = self
+                        .edit(|f| {
+                            (index < f.content_len(line)).then(|| f.column_of(line, index + 1))
+                        })
+                        .flatten()
+                    {
+                        self.place_cursor(column, contsize);
                     }
                 }
             }
@@ -202,7 +991,7 @@ impl Editor {
     fn scroll_up(&self, count: usize) {
         for _ in 0..count {
             if let Some(file) = &self.file {
-                if file.blocking_read().showing_start > 1 {
+                if file.blocking_read().showing_start > 0 {
                     file.blocking_write().showing_start -= 1;
                 }
             }
@@ -255,85 +1044,120 @@ impl Component for Editor {
     }
 
     fn render(&mut self, renderer: &Renderer) -> (bool, (usize, usize)) {
+        let size = renderer.get_size();
         if let Ok(PipeObject::Editing(edi)) = self.file_open_receiver.blocking_write().try_recv() {
             self.file = Some(edi);
+            self.close_completion();
         }
-        let size = renderer.get_size();
+        if let Some(at) = self.edit(|f| f.jump.take()).flatten() {
+            self.jump_to(at, size);
+        }
+        self.receive_lsp(size);
         let focused = self.container.read().unwrap().focused();
+        if !focused {
+            self.close_completion();
+        }
+        let cursor_line = self.position().map(|(line, _)| line);
         let title = if let Some(f) = &self.file {
-            " ".to_string() + &f.blocking_read().path.last().unwrap().clone()
+            let f = f.blocking_read();
+            let name = if let Some(e) = &f.save_error {
+                format!(" ✗ {}", e)
+            } else if f.dirty() {
+                format!(" {} ●", f.path.last().unwrap())
+            } else {
+                " ".to_string() + f.path.last().unwrap()
+            };
+            // 光标所在行有诊断时在文件名后显示
+            match cursor_line.and_then(|line| f.markers_at(line).first().copied()) {
+                Some(marker) if f.save_error.is_none() => format!(
+                    "{}  {} {}",
+                    name,
+                    marker.severity.sign(),
+                    marker.message.lines().next().unwrap_or_default()
+                ),
+                _ => name,
+            }
         } else {
             format!(" Editor {}", self.id)
         };
+        let title = match &self.notice {
+            Some(notice) => format!(" {}", notice),
+            None => title,
+        };
         let mode = match self.mode {
             EditorMode::Command => "Command".to_string(),
             EditorMode::Edit => "Editing".to_string(),
         };
-        // 标题
-        if !focused {
-            let title = title.chars().collect::<Vec<_>>();
-            let mut mode = mode.chars().collect::<Vec<_>>();
-            if size.0 == 1 {
-                let mut title = if title.len() > size.1 {
-                    title.split_at(size.1).0.to_vec()
-                } else {
-                    title
-                };
-                if title.len() < size.1 {
-                    title.append(
-                        &mut iter::repeat(' ')
-                            .take(size.1 - title.len())
-                            .collect::<Vec<_>>(),
-                    );
-                }
-                for i in 0..title.len() {
-                    renderer.set(0, i, title[i].white().on_dark_grey());
-                }
-            } else {
-                let mut title = if title.len() > size.0 - mode.len() - 1 {
-                    title.split_at(size.1 - mode.len() - 1).0.to_vec()
-                } else {
-                    title
+        // 提示打开时标题栏显示提示，右侧显示搜索选项，输入时光标停在标题栏
+        let mut prompt_cursor = None;
+        let (title, mode) = match &self.prompt {
+            Some(prompt) => {
+                let title = match prompt {
+                    Prompt::Search { query, error, .. } => {
+                        let input = format!(" /{}", query);
+                        prompt_cursor = Some(input.chars().map(char_width).sum::<usize>());
+                        match error {
+                            Some(e) => format!("{}  ✗ {}", input, e),
+                            None => input,
+                        }
+                    }
+                    Prompt::Replace { with } => {
+                        let input = format!(" Replace with: {}", with);
+                        prompt_cursor = Some(input.chars().map(char_width).sum::<usize>());
+                        input
+                    }
+                    Prompt::Confirm { with } => format!(" Replace with \"{}\"? (y/n/a/q)", with),
+                    Prompt::Rename { name } => {
+                        let input = format!(" Rename to: {}", name);
+                        prompt_cursor = Some(input.chars().map(char_width).sum::<usize>());
+                        input
+                    }
                 };
-                if title.len() < size.0 - mode.len() - 1 {
-                    title.append(
-                        &mut iter::repeat(' ')
-                            .take(size.0 - title.len() - mode.len())
-                            .collect::<Vec<_>>(),
-                    );
-                }
-                title.append(&mut mode);
-                let title = String::from_iter(title.iter());
-                renderer.set_section(0, 0, title.white().on_dark_grey());
+                let options = self.search_options;
+                let mode = [
+                    (options.case_sensitive, "[Aa]"),
+                    (options.whole_word, "[W]"),
+                    (options.regex, "[.*]"),
+                ]
+                .iter()
+                .filter(|(on, _)| *on)
+                .map(|(_, flag)| *flag)
+                .collect::<String>();
+                (title, mode)
+            }
+            None => (title, mode),
+        };
+        // 标题，右侧为模式，窄到放不下时先截短标题再截短模式
+        if !focused && size.0 == 1 {
+            for (i, ch) in fit(title.chars(), size.1).chars().enumerate() {
+                renderer.set(0, i, ch.white().on_dark_grey());
             }
         } else {
-            let title = title.chars().collect::<Vec<_>>();
-            let mut mode = mode.chars().collect::<Vec<_>>();
-            let mut title = if title.len() > size.0 - mode.len() - 1 {
-                title.split_at(size.1 - mode.len() - 1).0.to_vec()
+            let (mode, mode_width) = truncate(mode.chars(), size.0);
+            let (title, _) = truncate(title.chars(), (size.0 - mode_width).saturating_sub(1));
+            let title = fit(title.chars(), size.0 - mode_width) + &mode;
+            if focused {
+                renderer.set_section(0, 0, title.dark_red().on_dark_blue());
             } else {
-                title
-            };
-            if title.len() < size.0 - mode.len() - 1 {
-                title.append(
-                    &mut iter::repeat(' ')
-                        .take(size.0 - title.len() - mode.len())
-                        .collect::<Vec<_>>(),
-                );
+                renderer.set_section(0, 0, title.white().on_dark_grey());
             }
-            title.append(&mut mode);
-            let title = String::from_iter(title.iter());
-            renderer.set_section(0, 0, title.dark_red().on_dark_blue());
         }
         // 内容
         let mut cursor_loc = self.cursor;
         if size.0 > 1 && size.1 > 1 {
             let mut linen = 1;
+            let gutter = self.gutter();
+            let width = size.0 - gutter;
             if let Some(file) = &self.file {
-                file.blocking_write().showing_length = size.1;
-                let lnst = file.blocking_read().line_start;
-                for line in file.blocking_write().get() {
-                    let linelen = line.len();
+                let mut file = file.blocking_write();
+                file.showing_length = size.1 - 1;
+                let spans = file.visible_spans();
+                let lnst = file.line_start;
+                let showing_start = file.showing_start;
+                for (line, spans) in file.get().zip(spans) {
+                    let linelen = line
+                        .chars()
+                        .fold(0, |column, ch| column + width_at(column, ch));
                     if linen == cursor_loc.1 {
                         if linelen > lnst && cursor_loc.0 > linelen - lnst {
                             cursor_loc.0 = linelen - lnst;
@@ -341,63 +1165,138 @@ impl Component for Editor {
                             cursor_loc.0 = 0;
                         }
                     }
-                    let mut lining = line.clone();
-                    if !lining.is_empty() && *lining.last().unwrap() == '\n' {
-                        lining.pop();
-                    }
-                    let mut displaying = Vec::new();
-                    let mut rawl = 0;
-                    while !lining.is_empty() && rawl < lnst {
-                        let ch = lining.remove(0);
-                        rawl += UnicodeWidthChar::width(ch).unwrap();
+                    let bg = if linen == self.cursor.1 {
+                        Color::Rgb {
+                            r: 0x38,
+                            g: 0x38,
+                            b: 0x58,
+                        }
+                    } else {
+                        Color::Rgb {
+                            r: 0x10,
+                            g: 0x10,
+                            b: 0x20,
+                        }
+                    };
+                    let line_no = showing_start + linen - 1;
+                    let matches = self
+                        .matcher
+                        .as_ref()
+                        .map(|m| m.find_in_line(&line_text(file.buffer(), line_no)))
+                        .unwrap_or_default();
+                    if gutter > 0 {
+                        let sign = match file.markers_at(line_no).first() {
+                            Some(marker) => marker
+                                .severity
+                                .sign()
+                                .to_string()
+                                .with(marker.severity.color()),
+                            None => " ".to_string().reset(),
+                        };
+                        renderer.set_section(0, linen, sign.on(bg));
                     }
-                    rawl = 0;
-                    while rawl < size.0 {
-                        if lining.is_empty() {
+                    let file = &*file;
+                    let bg_at = |index: usize| {
+                        match matches.iter().find(|m| m.0 <= index && index < m.1) {
+                            Some(&m) if self.current_match == Some((line_no, m)) => Color::Rgb {
+                                r: 0x9a,
+                                g: 0x74,
+                                b: 0x10,
+                            },
+                            Some(_) => Color::Rgb {
+                                r: 0x5a,
+                                g: 0x4a,
+                                b: 0x14,
+                            },
+                            // 搜索匹配之外标出诊断的范围
+                            None => file
+                                .severity_at(line_no, index)
+                                .map_or(bg, |severity| severity.tint()),
+                        }
+                    };
+                    // 按高亮记号和搜索匹配分段绘制
+                    let mut spans = spans.into_iter().peekable();
+                    let mut kind = TokenKind::Plain;
+                    let mut style = (kind, bg);
+                    let mut section = String::new();
+                    let mut section_x = gutter;
+                    let mut rawl: usize = 0;
+                    for (index, ch) in line.chars().enumerate() {
+                        while let Some((_, k)) = spans.next_if(|&(start, _)| start <= index) {
+                            kind = k;
+                        }
+                        if (kind, bg_at(index)) != style {
+                            if !section.is_empty() {
+                                renderer.set_section(
+                                    section_x,
+                                    linen,
+                                    section.with(style.0.color()).on(style.1),
+                                );
+                            }
+                            section = String::new();
+                            section_x = gutter + rawl.saturating_sub(lnst);
+                            style = (kind, bg_at(index));
+                        }
+                        if ch.is_control() && ch != '\t' {
+                            continue;
+                        }
+                        let w = width_at(rawl, ch);
+                        if rawl + w > lnst + width {
+                            if ch == '\t' {
+                                // 制表符能显示多少就显示多少
+                                let from = rawl.max(lnst);
+                                section.extend(iter::repeat_n(' ', lnst + width - from));
+                                rawl = lnst + width;
+                            }
                             break;
                         }
-                        let ch = lining.remove(0);
-                        rawl += UnicodeWidthChar::width(ch).unwrap();
-                        displaying.push(ch);
-                    }
-                    if rawl < size.0 {
-                        displaying.append(
-                            &mut iter::repeat(' ').take(size.0 - rawl).collect::<Vec<char>>(),
-                        );
+                        if rawl >= lnst {
+                            if ch == '\t' {
+                                section.extend(iter::repeat_n(' ', w));
+                            } else {
+                                section.push(ch);
+                            }
+                        } else if rawl + w > lnst {
+                            // 被左边界切开的宽字符或制表符
+                            section.extend(iter::repeat_n(' ', rawl + w - lnst));
+                        }
+                        rawl += w;
                     }
-                    if linen == self.cursor.1 {
+                    if !section.is_empty() {
                         renderer.set_section(
-                            0,
+                            section_x,
                             linen,
-                            displaying
-				.iter()
-				.collect::<String>()
-				.on(Color::Rgb {r: 0x38, g: 0x38, b: 0x58}),
-                        );
-                    } else {
-                        renderer.set_section(
-                            0,
-                            linen,
-                            displaying
-				.iter()
-				.collect::<String>()
-				.on(Color::Rgb {r: 0x10, g: 0x10, b: 0x20}),
+                            section.with(style.0.color()).on(style.1),
                         );
                     }
+                    let used = rawl.saturating_sub(lnst);
+                    renderer.set_section(
+                        gutter + used,
+                        linen,
+                        iter::repeat_n(' ', width - used).collect::<String>().on(bg),
+                    );
                     linen += 1;
                 }
             }
             *self.last_rh.lock().unwrap() = linen;
             while linen < size.1 {
-                let l = iter::repeat(' ').take(size.0).collect::<Vec<_>>();
+                let l = iter::repeat_n(' ', size.0).collect::<Vec<_>>();
                 let l = String::from_iter(&mut l.iter());
                 renderer.set_section(
                     0,
                     linen,
-                    l.on(Color::Rgb {r: 0x10, g: 0x10, b: 0x20}),
+                    l.on(Color::Rgb {
+                        r: 0x10,
+                        g: 0x10,
+                        b: 0x20,
+                    }),
                 );
                 linen += 1;
             }
+            cursor_loc.0 = (cursor_loc.0 + gutter).min(size.0 - 1);
+        }
+        if let Some(x) = prompt_cursor {
+            cursor_loc = (x.min(size.0.saturating_sub(1)), 0);
         }
         cursor_loc.0 += renderer.x;
         cursor_loc.1 += renderer.y;
@@ -407,71 +1306,615 @@ impl Component for Editor {
 
 pub struct Editing {
     path: Vec<String>,
-    buffer: Vec<Vec<char>>,
+    buffer: TextBuffer,
+    highlighter: Highlighter,
+    history: History,
+    /// 上次保存时的历史版本
+    saved_state: usize,
+    /// 上次保存失败的原因
+    save_error: Option<String>,
     showing_start: usize,
     showing_length: usize,
     line_start: usize,
+    /// 打开文件时要求Editor把光标移到的位置
+    jump: Option<(usize, usize)>,
+    /// 这个文件的诊断，随编辑移动
+    markers: Vec<Marker>,
+    /// 负责这个文件的语言服务器，内容的修改都会同步过去
+    lsp: Option<Arc<Client>>,
 }
 
 impl Editing {
     pub fn new(path: Vec<String>) -> Self {
         let mut res = Editing {
+            highlighter: Highlighter::new(path.last().map(String::as_str).unwrap_or_default()),
             path,
-            buffer: vec![Vec::new()],
-            showing_start: 1,
+            buffer: TextBuffer::new(),
+            history: History::new(),
+            saved_state: 0,
+            save_error: None,
+            showing_start: 0,
             showing_length: 0,
             line_start: 0,
+            jump: None,
+            markers: Vec::new(),
+            lsp: None,
         };
         res.load();
         res
     }
 
-    pub fn load(&mut self) {
+    fn file_path(&self) -> String {
         let mut path = String::new();
-	for p in &self.path {
-	    path += "/";
-	    path += &p;
-	}
-	let file = OpenOptions::new().read(true).open(&path).unwrap();
-	let mut reader = BufReader::new(file);
-	loop {
-	    let mut line = Vec::new();
-	    let l = match reader.read_until(b'\n', &mut line) {
-		Ok(n) => n,
-		Err(_) => 0,
-	    };
-	    if l == 0 {
-		break;
-	    }
-	    let line = String::from_utf8(line).unwrap().chars().collect::<Vec<char>>();
-	    self.buffer.push(line);
-	}
-    }
-
-    pub fn len_of_line(&self, line: usize) -> usize {
-        if let Some(l) = self.buffer.get(line) {
-            UnicodeWidthStr::width(l.iter().collect::<String>().as_str())
-        } else {
-            0
+        for p in &self.path {
+            path += "/";
+            path += p;
+        }
+        path
+    }
+
+    /// 项目路径以/开头，file_path()会得到//开头的路径，这里去掉多余的/
+    fn absolute_path(&self) -> PathBuf {
+        PathBuf::from(self.file_path()).components().collect()
+    }
+
+    pub fn load(&mut self) {
+        let file = OpenOptions::new()
+            .read(true)
+            .open(self.file_path())
+            .unwrap();
+        self.buffer = TextBuffer::from_reader(BufReader::new(file)).unwrap();
+    }
+
+    /// 先写入同目录下的临时文件再重命名覆盖原文件，保留原文件的权限
+    pub fn save(&mut self) -> io::Result<()> {
+        let res = self.write_atomic();
+        match &res {
+            Ok(()) => {
+                self.history.seal();
+                self.saved_state = self.history.state();
+                self.save_error = None;
+                if let Some(client) = &self.lsp {
+                    client.did_save(&self.absolute_path());
+                }
+            }
+            Err(e) => self.save_error = Some(e.to_string()),
+        }
+        res
+    }
+
+    fn write_atomic(&self) -> io::Result<()> {
+        let path = self.file_path();
+        let tmp_path = match path.rsplit_once('/') {
+            Some((dir, name)) => format!("{}/.{}.ide-termux.tmp", dir, name),
+            None => format!(".{}.ide-termux.tmp", path),
+        };
+        let write = || -> io::Result<()> {
+            let file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&tmp_path)?;
+            let mut writer = BufWriter::new(file);
+            self.buffer.write_to(&mut writer)?;
+            let file = writer.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
+            if let Ok(meta) = fs::metadata(&path) {
+                fs::set_permissions(&tmp_path, meta.permissions())?;
+            }
+            fs::rename(&tmp_path, &path)
+        };
+        let res = write();
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        res
+    }
+
+    /// 自上次载入或保存后是否被修改过
+    pub fn dirty(&self) -> bool {
+        self.history.state() != self.saved_state
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.buffer.line_count()
+    }
+
+    pub fn content_len(&self, line: usize) -> usize {
+        self.buffer.content_len(line)
+    }
+
+    pub fn column_of(&self, line: usize, index: usize) -> usize {
+        self.buffer.column_of(line, index)
+    }
+
+    pub fn index_of(&self, line: usize, column: usize) -> usize {
+        self.buffer.index_of(line, column)
+    }
+
+    pub fn insert(&mut self, line: usize, index: usize, ch: char) {
+        self.replace((line, index), (line, index), &[ch]);
+    }
+
+    pub fn remove(&mut self, line: usize, index: usize) {
+        if index < self.content_len(line) {
+            self.replace((line, index), (line, index + 1), &[]);
         }
     }
 
-    pub fn get(&mut self) -> Vec<Vec<char>> {
-        let mut res = Vec::new();
-        for i in self.showing_start..(self.showing_start + self.showing_length) {
-	    if i >= self.buffer.len() {
-		break;
-	    }
-	    res.push(self.buffer[i].clone());
-	}
+    /// 在index处把一行拆成两行
+    pub fn split_line(&mut self, line: usize, index: usize) {
+        self.replace((line, index), (line, index), &['\n']);
+    }
+
+    /// 把下一行接到这一行末尾
+    pub fn join_line(&mut self, line: usize) {
+        if line + 1 < self.line_count() {
+            let len = self.content_len(line);
+            self.replace((line, len), (line + 1, 0), &[]);
+        }
+    }
+
+    /// 用text替换start到end之间的内容并记入撤销历史，返回插入文本的结束位置
+    pub fn replace(
+        &mut self,
+        start: (usize, usize),
+        end: (usize, usize),
+        text: &[char],
+    ) -> (usize, usize) {
+        let removed = self.splice(start, end, text);
+        let change = Change {
+            start,
+            removed,
+            inserted: text.to_vec(),
+        };
+        let end = change.inserted_end();
+        self.history.record(change);
+        end
+    }
+
+    /// 撤销最近一组编辑，返回撤销后光标应在的位置
+    pub fn undo(&mut self) -> Option<(usize, usize)> {
+        let changes = self.history.undo()?;
+        for change in changes.iter().rev() {
+            self.splice(change.start, change.inserted_end(), &change.removed);
+        }
+        Some(changes[0].removed_end())
+    }
+
+    /// 重做最近撤销的一组编辑，返回重做后光标应在的位置
+    pub fn redo(&mut self) -> Option<(usize, usize)> {
+        let changes = self.history.redo()?;
+        for change in &changes {
+            self.splice(change.start, change.removed_end(), &change.inserted);
+        }
+        changes.last().map(|c| c.inserted_end())
+    }
+
+    /// 把line中range处的匹配替换成with，返回替换后文本的结束位置
+    pub fn replace_match(
+        &mut self,
+        matcher: &Matcher,
+        line: usize,
+        range: (usize, usize),
+        with: &str,
+    ) -> (usize, usize) {
+        let text = matcher.replacement(&line_text(&self.buffer, line), range, with);
+        self.replace(
+            (line, range.0),
+            (line, range.1),
+            &text.chars().collect::<Vec<_>>(),
+        )
+    }
+
+    /// 替换从from开始到文件末尾的所有匹配，作为一组编辑撤销，返回替换的个数
+    pub fn replace_all(&mut self, matcher: &Matcher, from: (usize, usize), with: &str) -> usize {
+        let mut count = 0;
+        self.history.begin_batch();
+        for line in from.0..self.line_count() {
+            let text = line_text(&self.buffer, line);
+            let matches = matcher.find_in_line(&text);
+            // 从行尾往前替换，前面匹配的下标不受影响
+            for &range in matches.iter().rev() {
+                if line == from.0 && range.0 < from.1 {
+                    continue;
+                }
+                let replacement = matcher.replacement(&text, range, with);
+                self.replace(
+                    (line, range.0),
+                    (line, range.1),
+                    &replacement.chars().collect::<Vec<_>>(),
+                );
+                count += 1;
+            }
+        }
+        self.history.end_batch();
+        count
+    }
+
+    /// 下一次渲染时把光标移到(行号, 字符下标)
+    pub fn request_jump(&mut self, at: (usize, usize)) {
+        self.jump = Some(at);
+    }
+
+    /// 光标跳转后调用，之后的编辑另起一组
+    pub fn seal_history(&mut self) {
+        self.history.seal();
+    }
+
+    /// 修改buffer并作废受影响行的高亮缓存
+    fn splice(&mut self, start: (usize, usize), end: (usize, usize), text: &[char]) -> Vec<char> {
+        let removed = self.buffer.splice(start, end, text);
+        let inserted = text.iter().filter(|&&ch| ch == '\n').count();
+        self.highlighter.edited(start.0, end.0 - start.0, inserted);
+        let inserted_end = end_of(start, text);
+        for marker in &mut self.markers {
+            marker.shift(start, end, inserted_end);
+        }
+        if let Some(client) = &self.lsp {
+            client.did_change(&self.absolute_path(), start, end, text);
+        }
+        removed
+    }
+
+    /// 在语言服务器中打开这个文件，之后的修改都会同步过去
+    pub fn attach(&mut self, client: Arc<Client>, language: &str) {
+        client.did_open(&self.absolute_path(), language, self.buffer.text());
+        self.lsp = Some(client);
+    }
+
+    /// 负责这个文件的语言服务器和文件的绝对路径
+    pub fn lsp(&self) -> Option<(Arc<Client>, PathBuf)> {
+        self.lsp
+            .as_ref()
+            .map(|client| (Arc::clone(client), self.absolute_path()))
+    }
+
+    /// 应用语言服务器给出的修改，作为一组编辑撤销
+    pub fn apply_edits(&mut self, edits: &[TextEdit]) {
+        let mut edits = edits.to_vec();
+        edits.sort_by_key(|edit| edit.start);
+        self.history.seal();
+        self.history.begin_batch();
+        // 从后往前修改，前面的位置不受影响
+        for edit in edits.iter().rev() {
+            self.replace(edit.start, edit.end, &edit.text.chars().collect::<Vec<_>>());
+        }
+        self.history.end_batch();
+    }
+
+    /// 用source的新诊断替换旧的
+    pub fn set_markers(&mut self, source: &'static str, markers: Vec<Marker>) {
+        self.markers.retain(|m| m.source != source);
+        self.markers.extend(markers);
+    }
+
+    /// 涉及line的诊断，最严重的在前
+    pub fn markers_at(&self, line: usize) -> Vec<&Marker> {
+        let mut res = self
+            .markers
+            .iter()
+            .filter(|m| m.start.0 <= line && line <= m.end.0)
+            .collect::<Vec<_>>();
+        res.sort_by_key(|m| m.severity);
         res
     }
 
+    /// (line, index)处字符所在诊断中最严重的
+    pub fn severity_at(&self, line: usize, index: usize) -> Option<Severity> {
+        self.markers
+            .iter()
+            .filter(|m| m.contains((line, index)))
+            .map(|m| m.severity)
+            .min()
+    }
+
+    pub fn has_markers(&self) -> bool {
+        !self.markers.is_empty()
+    }
+
+    /// 当前显示的各行的高亮记号，与get()一一对应
+    pub fn visible_spans(&mut self) -> Vec<Vec<Span>> {
+        let end = (self.showing_start + self.showing_length).min(self.line_count());
+        (self.showing_start..end)
+            .map(|line| self.highlighter.spans(&self.buffer, line))
+            .collect()
+    }
+
+    /// 当前显示的各行，借用buffer中的内容
+    pub fn get(&self) -> impl Iterator<Item = RopeSlice<'_>> {
+        self.buffer.lines(self.showing_start, self.showing_length)
+    }
+
+    pub fn buffer(&self) -> &TextBuffer {
+        &self.buffer
+    }
+
     pub fn path(&self) -> &Vec<String> {
         &self.path
     }
 
-    pub fn eof(&self, cursor: usize) -> bool {
-	self.showing_start + cursor > self.buffer.len()
+    /// line是否为最后一行
+    pub fn eof(&self, line: usize) -> bool {
+        line + 1 >= self.line_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::project_viewer::ProjectViewer,
+        ui::testing::{self, press, type_text},
+    };
+    use std::os::unix::fs::PermissionsExt;
+
+    /// 在临时项目name中放一个a.txt并在Editor0中打开，返回的Registry中有Editor的命令
+    fn open(name: &str, text: &str) -> (Framework, Registry, Arc<RwLock<ProjectViewer>>) {
+        let root = testing::project(name, &[("a.txt", text)]);
+        let mut framework = testing::framework(40, 8);
+        let viewer = ProjectViewer::new(root);
+        testing::bind(&mut framework, &viewer);
+        let completion = Completion::new();
+        let editors = [
+            Editor::new(0, Arc::clone(&completion)),
+            Editor::new(1, Arc::clone(&completion)),
+        ];
+        for editor in &editors {
+            testing::bind(&mut framework, editor);
+        }
+        let mut registry = Registry::default();
+        Editor::register_commands(editors, &mut registry);
+        viewer
+            .write()
+            .unwrap()
+            .open_file(vec!["a.txt".to_string()], None);
+        testing::show(&mut framework, "/WorkArea/EditorArea/Editor0");
+        (framework, registry, viewer)
+    }
+
+    /// 屏幕上的文字，缩放时左边是SideBar的竖排标题，去掉这一列
+    fn text(framework: &Framework) -> Vec<String> {
+        testing::lines(framework)
+            .iter()
+            .map(|line| line.chars().skip(1).collect::<String>())
+            .collect()
+    }
+
+    #[test]
+    fn inserts_and_deletes_in_edit_mode() {
+        let (mut framework, registry, _viewer) =
+            open("editor-edit", "fn main() {\n    let x = 1;\n}\n");
+        // 上面一行是Editor1的标题
+        assert_eq!(
+            text(&framework)[1..5],
+            [
+                " a.txt                          Command",
+                "fn main() {",
+                "    let x = 1;",
+                "}",
+            ]
+        );
+        assert_eq!(framework.cursor(), Some((1, 2)));
+
+        // 命令模式下输入的字符不修改文件
+        type_text(&mut framework, "q");
+        registry.run("Editor: Edit Mode", &mut framework);
+        press(&mut framework, KeyCode::Down);
+        for _ in 0..14 {
+            press(&mut framework, KeyCode::Right);
+        }
+        type_text(&mut framework, " // 中文");
+        framework.render();
+        assert_eq!(text(&framework)[3], "    let x = 1; // 中文");
+        // 宽字符占两列
+        assert_eq!(framework.cursor(), Some((23, 3)));
+
+        press(&mut framework, KeyCode::Backspace);
+        press(&mut framework, KeyCode::Enter);
+        type_text(&mut framework, "y");
+        framework.render();
+        assert_eq!(text(&framework)[3..6], ["    let x = 1; // 中", "y", "}"]);
+        assert_eq!(framework.cursor(), Some((2, 4)));
+
+        // 行首的Backspace和前一行合并，Delete删除光标处的字符
+        press(&mut framework, KeyCode::Left);
+        press(&mut framework, KeyCode::Backspace);
+        press(&mut framework, KeyCode::Delete);
+        framework.render();
+        assert_eq!(text(&framework)[3..5], ["    let x = 1; // 中", "}"]);
+        assert_eq!(framework.cursor(), Some((21, 3)));
+    }
+
+    #[test]
+    fn draws_tabs_to_next_stop() {
+        let (mut framework, registry, _viewer) = open("editor-tab", "\tx\n中\ty\n");
+        assert_eq!(text(&framework)[2..4], ["    x", "中  y"]);
+
+        registry.run("Editor: Edit Mode", &mut framework);
+        press(&mut framework, KeyCode::Right);
+        framework.render();
+        assert_eq!(framework.cursor(), Some((1 + TAB_WIDTH, 2)));
+        press(&mut framework, KeyCode::Down);
+        framework.render();
+        assert_eq!(framework.cursor(), Some((1 + TAB_WIDTH, 3)));
+        press(&mut framework, KeyCode::Left);
+        framework.render();
+        assert_eq!(framework.cursor(), Some((3, 3)));
+        // 制表符前插入字符后只补到下一个制表位
+        type_text(&mut framework, "a");
+        framework.render();
+        assert_eq!(text(&framework)[3], "中a y");
+    }
+
+    #[test]
+    fn saves_atomically_and_keeps_mode() {
+        let root = testing::project("editor-save", &[("run.sh", "echo hi\n")]);
+        let path = format!("{}/run.sh", root);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let mut editing = Editing::new(path.split('/').map(|s| s.to_string()).collect());
+        assert!(!editing.dirty());
+        for (index, ch) in "set -e\n".chars().enumerate() {
+            editing.insert(0, index, ch);
+        }
+        assert!(editing.dirty());
+
+        editing.save().unwrap();
+        assert!(!editing.dirty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "set -e\necho hi\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        // 临时文件已经被重命名，目录中只剩原文件
+        let names = fs::read_dir(&root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["run.sh"]);
+
+        editing.remove(0, 0);
+        assert!(editing.dirty());
+    }
+
+    #[test]
+    fn dirty_marker_follows_saves() {
+        let (mut framework, registry, viewer) = open("editor-dirty", "one\n");
+        registry.run("Editor: Edit Mode", &mut framework);
+        type_text(&mut framework, "x");
+        framework.render();
+        assert_eq!(
+            text(&framework)[1],
+            " a.txt ●                        Editing"
+        );
+
+        registry.run("Editor: Save", &mut framework);
+        framework.render();
+        assert_eq!(
+            text(&framework)[1],
+            " a.txt                          Editing"
+        );
+        let path = format!("{}/a.txt", viewer.read().unwrap().root());
+        assert_eq!(fs::read_to_string(path).unwrap(), "xone\n");
+
+        type_text(&mut framework, "y");
+        framework.render();
+        assert_eq!(
+            text(&framework)[1],
+            " a.txt ●                        Editing"
+        );
+    }
+
+    #[test]
+    fn save_all_writes_every_dirty_buffer() {
+        let root = testing::project("editor-save-all", &[("a.txt", "a\n"), ("b.txt", "b\n")]);
+        let viewer = ProjectViewer::new(root.clone());
+        let mut registry = Registry::default();
+        ProjectViewer::register_commands(&viewer, &mut registry);
+        for name in ["a.txt", "b.txt"] {
+            viewer
+                .write()
+                .unwrap()
+                .open_file(vec![name.to_string()], None);
+        }
+        for editing in viewer.read().unwrap().editings() {
+            editing.blocking_write().insert(0, 0, '+');
+        }
+
+        let mut framework = testing::framework(40, 8);
+        registry.run("ProjectViewer: Save All Files", &mut framework);
+        assert_eq!(
+            fs::read_to_string(format!("{}/a.txt", root)).unwrap(),
+            "+a\n"
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{}/b.txt", root)).unwrap(),
+            "+b\n"
+        );
+        for editing in viewer.read().unwrap().editings() {
+            assert!(!editing.blocking_read().dirty());
+        }
+    }
+
+    #[test]
+    fn finds_and_replaces() {
+        let (mut framework, registry, _viewer) = open("editor-find", "let x = 1;\nx + x\n");
+        let background =
+            |framework: &Framework, x, y| testing::style(framework, x, y).background_color;
+        let current = Some(Color::Rgb {
+            r: 0x9a,
+            g: 0x74,
+            b: 0x10,
+        });
+        let other = Some(Color::Rgb {
+            r: 0x5a,
+            g: 0x4a,
+            b: 0x14,
+        });
+        // 搜索时标题栏是输入框，光标跳到第一个匹配
+        registry.run("Editor: Find", &mut framework);
+        type_text(&mut framework, "x");
+        framework.render();
+        assert_eq!(text(&framework)[1], " /x");
+        assert_eq!(framework.cursor(), Some((4, 1)));
+        assert_eq!(background(&framework, 5, 2), current);
+        assert_eq!(background(&framework, 1, 3), other);
+        assert_eq!(background(&framework, 5, 3), other);
+
+        press(&mut framework, KeyCode::Enter);
+        registry.run("Editor: Find Next", &mut framework);
+        framework.render();
+        assert_eq!(
+            text(&framework)[1],
+            " a.txt                          Command"
+        );
+        assert_eq!(framework.cursor(), Some((1, 3)));
+        assert_eq!(background(&framework, 1, 3), current);
+        assert_eq!(background(&framework, 5, 2), other);
+
+        // 跳过第一个匹配，替换其余的，一次撤销全部恢复
+        registry.run("Editor: Find and Replace", &mut framework);
+        type_text(&mut framework, "x");
+        press(&mut framework, KeyCode::Enter);
+        type_text(&mut framework, "y");
+        press(&mut framework, KeyCode::Enter);
+        framework.render();
+        assert_eq!(text(&framework)[1], " Replace with \"y\"? (y/n/a/q)");
+        type_text(&mut framework, "na");
+        framework.render();
+        assert_eq!(text(&framework)[2..4], ["let x = 1;", "y + y"]);
+        registry.run("Editor: Undo", &mut framework);
+        framework.render();
+        assert_eq!(text(&framework)[2..4], ["let x = 1;", "x + x"]);
+    }
+
+    #[test]
+    fn shrinks_to_min_width_and_still_renders() {
+        let (mut framework, registry, _viewer) = open("editor-shrink", "fn main() {}\n");
+        framework.toggle_zoom();
+        framework.set_focus("/WorkArea/EditorArea/Editor0");
+        for _ in 0..10 {
+            framework.resize_focused(-2, false);
+            framework.render();
+        }
+        // EditorArea停在最小宽度，标题栏仍然放得下文件名和模式
+        let title = testing::lines(&framework)[0]
+            .chars()
+            .skip(40 - 24)
+            .collect::<String>();
+        assert_eq!(title, " Editor 1        Command");
+        assert_eq!(
+            testing::style(&framework, 40 - 24, 0).background_color,
+            Some(Color::DarkGrey)
+        );
+
+        // 比最小大小还窄时后面的Container被压缩，标题被截短
+        for columns in 1..=40 {
+            framework.set_size(columns, 12);
+            framework.render();
+        }
+        registry.run("Editor: Find", &mut framework);
+        for columns in 1..=40 {
+            framework.set_size(columns, 12);
+            framework.render();
+        }
     }
 }
@@ -0,0 +1,269 @@
+use crossterm::{
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    style::Stylize,
+};
+use std::{
+    iter,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::{mpsc::Sender, RwLock as AsyncRwLock};
+
+use crate::{
+    fuzzy::fuzzy_match,
+    named_pipe::{NamedPipe, PipeObject},
+    renderer::{fit, Renderer},
+    text_buffer::char_width,
+    ui::container::{Container, ContainerType},
+};
+
+/// 按文件路径模糊查找并打开文件的弹出窗口
+pub struct FileFinder {
+    container: Arc<RwLock<Container>>,
+    files: Vec<String>,
+    query: String,
+    /// 按分数排好序的(文件下标, 匹配到的字符下标)
+    ranked: Vec<(usize, Vec<usize>)>,
+    selected: usize,
+    scroll: usize,
+    open_file_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+    close_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+}
+
+impl FileFinder {
+    /// files是项目中所有文件相对于项目根目录的路径
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(files: Vec<Vec<String>>) -> Arc<RwLock<Self>> {
+        let container = Container::new("FileFinder", None);
+        let container = Arc::new(RwLock::new(container));
+        let mut finder = FileFinder {
+            container,
+            files: files.iter().map(|path| path.join("/")).collect(),
+            query: String::new(),
+            ranked: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            open_file_sender: NamedPipe::open_sender(String::from("OpenFile")),
+            close_sender: NamedPipe::open_sender(String::from("ClosePopup")),
+        };
+        finder.rank();
+        let res = Arc::new(RwLock::new(finder));
+        res.read()
+            .unwrap()
+            .container
+            .write()
+            .unwrap()
+            .set_type(ContainerType::FileFinder(Arc::clone(&res)));
+        let res_ref = Arc::clone(&res);
+        res.read()
+            .unwrap()
+            .container
+            .write()
+            .unwrap()
+            .set_handler(Box::new(move |event, _contsize| {
+                if let Event::Key(KeyEvent {
+                    code, modifiers, ..
+                }) = event
+                {
+                    let mut finder = res_ref.write().unwrap();
+                    match (code, modifiers) {
+                        (KeyCode::Char(ch), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                            finder.query.push(ch);
+                            finder.rank();
+                        }
+                        (KeyCode::Backspace, _) => {
+                            finder.query.pop();
+                            finder.rank();
+                        }
+                        (KeyCode::Up, _) => {
+                            finder.selected = finder.selected.saturating_sub(1);
+                        }
+                        (KeyCode::Down, _) => {
+                            if finder.selected + 1 < finder.ranked.len() {
+                                finder.selected += 1;
+                            }
+                        }
+                        (KeyCode::Enter, _) => {
+                            if let Some((file, _)) = finder.ranked.get(finder.selected) {
+                                let path = finder.files[*file]
+                                    .split("/")
+                                    .map(|s| s.to_string())
+                                    .collect();
+                                let _ = finder
+                                    .open_file_sender
+                                    .blocking_read()
+                                    .try_send(PipeObject::OpenFile(path, None));
+                                finder.close();
+                            }
+                        }
+                        (KeyCode::Esc, _) => finder.close(),
+                        _ => (),
+                    }
+                }
+            }));
+        res
+    }
+
+    pub fn container(&self) -> Arc<RwLock<Container>> {
+        Arc::clone(&self.container)
+    }
+
+    /// 清空上次的输入，准备再次弹出
+    pub fn reset(&mut self) {
+        self.query.clear();
+        self.rank();
+    }
+
+    fn close(&self) {
+        let _ = self
+            .close_sender
+            .blocking_read()
+            .try_send(PipeObject::ClosePopup);
+    }
+
+    /// 按当前输入重新排序，分数相同时短路径在前
+    fn rank(&mut self) {
+        let mut ranked = self
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(i, path)| {
+                fuzzy_match(&self.query, path).map(|(score, positions)| (score, i, positions))
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then_with(|| self.files[a.1].len().cmp(&self.files[b.1].len()))
+                .then_with(|| self.files[a.1].cmp(&self.files[b.1]))
+        });
+        self.ranked = ranked.into_iter().map(|(_, i, p)| (i, p)).collect();
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    pub fn render(&mut self, renderer: &Renderer) -> (bool, (usize, usize)) {
+        let size = renderer.get_size();
+        if size.0 < 2 || size.1 < 3 {
+            return (false, (0, 0));
+        }
+        // 标题，右侧为匹配数
+        let count = format!("{}/{} ", self.ranked.len(), self.files.len());
+        let title = " Go to file"
+            .chars()
+            .chain(iter::repeat(' '))
+            .take(size.0.saturating_sub(count.len()))
+            .chain(count.chars())
+            .take(size.0)
+            .collect::<String>();
+        renderer.set_section(0, 0, title.dark_red().on_dark_blue());
+        // 输入行
+        let input = format!("> {}", self.query);
+        let input_width = input.chars().map(char_width).sum::<usize>();
+        renderer.set_section(0, 1, fit(input.chars(), size.0).white().on_dark_grey());
+        // 候选
+        let height = size.1 - 2;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+        for row in 0..height {
+            let linen = row + 2;
+            let Some((file, positions)) = self.ranked.get(self.scroll + row) else {
+                renderer.set_section(0, linen, " ".repeat(size.0).on_dark_grey());
+                continue;
+            };
+            let selected = self.scroll + row == self.selected;
+            let mut x = 0;
+            for (index, ch) in self.files[*file].chars().enumerate() {
+                let w = char_width(ch);
+                if x + w > size.0 {
+                    break;
+                }
+                let ch = if positions.contains(&index) {
+                    ch.yellow().bold()
+                } else {
+                    ch.white()
+                };
+                renderer.set(x, linen, if selected { ch.on_blue() } else { ch.on_dark_grey() });
+                x += w;
+            }
+            let rest = " ".repeat(size.0 - x);
+            renderer.set_section(
+                x,
+                linen,
+                if selected {
+                    rest.on_blue()
+                } else {
+                    rest.on_dark_grey()
+                },
+            );
+        }
+        (
+            true,
+            (
+                renderer.x + input_width.min(size.0 - 1),
+                renderer.y + 1,
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::{
+        overlay::Placement,
+        testing::{self, press, type_text},
+    };
+    use crossterm::style::Color;
+
+    #[test]
+    fn filters_and_opens_files() {
+        let mut framework = testing::framework(40, 10);
+        testing::show(&mut framework, "/WorkArea");
+        let files = ["src/main.rs", "src/lib.rs", "README.md"]
+            .iter()
+            .map(|path| path.split('/').map(|s| s.to_string()).collect())
+            .collect();
+        let finder = FileFinder::new(files);
+        framework.open_overlay(finder.read().unwrap().container(), Placement::Center, true);
+        type_text(&mut framework, "rs");
+        framework.render();
+        assert_eq!(
+            testing::lines(&framework)[1..6],
+            [
+                " Go to file                         2/3",
+                "> rs",
+                "src/lib.rs",
+                "src/main.rs",
+                "",
+            ]
+        );
+        assert_eq!(framework.cursor(), Some((4, 2)));
+        // 选中的一行为蓝色背景，匹配到的字符为黄色
+        let style = testing::style(&framework, 8, 3);
+        assert_eq!(style.background_color, Some(Color::Blue));
+        assert_eq!(style.foreground_color, Some(Color::Yellow));
+        assert_eq!(
+            testing::style(&framework, 7, 3).foreground_color,
+            Some(Color::White)
+        );
+        assert_eq!(
+            testing::style(&framework, 0, 4).background_color,
+            Some(Color::DarkGrey)
+        );
+
+        let open_file = NamedPipe::open_receiver(String::from("OpenFile"));
+        let close = NamedPipe::open_receiver(String::from("ClosePopup"));
+        press(&mut framework, KeyCode::Down);
+        press(&mut framework, KeyCode::Enter);
+        assert!(matches!(
+            close.blocking_write().try_recv(),
+            Ok(PipeObject::ClosePopup)
+        ));
+        let Ok(PipeObject::OpenFile(path, None)) = open_file.blocking_write().try_recv() else {
+            panic!("expected a file to open");
+        };
+        assert_eq!(path, ["src", "main.rs"]);
+    }
+}
@@ -1,5 +1,10 @@
 pub mod areas;
+pub mod command_palette;
+pub mod completion;
 pub mod component;
 pub mod editor;
+pub mod file_finder;
+pub mod problems;
 pub mod project_viewer;
+pub mod search_panel;
 pub mod terminal;
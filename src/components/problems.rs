@@ -0,0 +1,390 @@
+use crossterm::{
+    event::{Event, KeyCode, KeyEvent},
+    style::{StyledContent, Stylize},
+};
+use std::{
+    fs,
+    process::exit,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::{mpsc::Sender, RwLock as AsyncRwLock};
+
+use crate::{
+    command::Registry,
+    components::component::Component,
+    diagnostic::{FileMarkers, Marker, Severity},
+    named_pipe::{NamedPipe, PipeObject},
+    renderer::{fit, Renderer},
+    task::{Diagnostic, Task, TaskEvent, TaskKind},
+    text_buffer::char_width,
+    ui::{
+        container::{Container, ContainerType},
+        framework::Framework,
+    },
+};
+
+/// 运行cargo命令并列出编译器诊断，在Terminal旁边
+pub struct Problems {
+    container: Arc<RwLock<Container>>,
+    root: String,
+    task: Option<Task>,
+    /// 最近一次运行的命令和结果
+    last_run: Option<(TaskKind, String)>,
+    diagnostics: Vec<Diagnostic>,
+    selected: usize,
+    scroll: usize,
+    open_file_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+    diagnostics_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+}
+
+impl Problems {
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(root: String) -> Arc<RwLock<Self>> {
+        let container = Container::new("Problems", None);
+        let container = Arc::new(RwLock::new(container));
+        let res = Arc::new(RwLock::new(Problems {
+            container,
+            root,
+            task: None,
+            last_run: None,
+            diagnostics: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            open_file_sender: NamedPipe::open_sender(String::from("OpenFile")),
+            diagnostics_sender: NamedPipe::open_sender(String::from("Diagnostics")),
+        }));
+        res.read()
+            .unwrap()
+            .container
+            .write()
+            .unwrap()
+            .set_type(ContainerType::Problems(Arc::clone(&res)));
+        let res_ref = Arc::clone(&res);
+        res.read()
+            .unwrap()
+            .container
+            .write()
+            .unwrap()
+            .set_handler(Box::new(move |event, _contsize| {
+                if let Event::Key(KeyEvent { code, .. }) = event {
+                    let mut problems = res_ref.write().unwrap();
+                    match code {
+                        KeyCode::Up => problems.selected = problems.selected.saturating_sub(1),
+                        KeyCode::Down => {
+                            if problems.selected + 1 < problems.diagnostics.len() {
+                                problems.selected += 1;
+                            }
+                        }
+                        KeyCode::Enter => problems.open(),
+                        _ => (),
+                    }
+                }
+            }));
+        res
+    }
+
+    pub fn register_commands(this: &Arc<RwLock<Self>>, registry: &mut Registry) {
+        type Command = fn(&mut Problems);
+        let commands: [(&str, Command); 4] = [
+            ("Problems: Cargo Build", |problems| {
+                problems.run(TaskKind::Build)
+            }),
+            ("Problems: Cargo Test", |problems| {
+                problems.run(TaskKind::Test)
+            }),
+            ("Problems: Cargo Clippy", |problems| {
+                problems.run(TaskKind::Clippy)
+            }),
+            ("Problems: Stop", Problems::stop),
+        ];
+        for (name, command) in commands {
+            let problems = Arc::clone(this);
+            registry.register(name, move |framework| {
+                framework.set_focus("/BottomArea/Problems");
+                command(&mut problems.write().unwrap());
+            });
+        }
+    }
+
+    /// 停止正在运行的命令
+    fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            self.last_run = Some((task.kind, "cancelled".to_string()));
+        }
+    }
+
+    /// 开始运行cargo命令，正在运行的命令会被结束
+    fn run(&mut self, kind: TaskKind) {
+        self.task = None;
+        self.diagnostics.clear();
+        self.selected = 0;
+        self.scroll = 0;
+        match Task::spawn(kind, &self.root) {
+            Ok(task) => {
+                self.task = Some(task);
+                self.last_run = Some((kind, "running…".to_string()));
+            }
+            Err(e) => self.last_run = Some((kind, e.to_string())),
+        }
+    }
+
+    /// 收下后台命令已经送来的诊断
+    fn receive(&mut self) {
+        let Some(task) = &self.task else {
+            return;
+        };
+        let kind = task.kind;
+        for event in task.events() {
+            match event {
+                TaskEvent::Diagnostic(diagnostic) => self.diagnostics.push(diagnostic),
+                TaskEvent::Finished(success, error) => {
+                    self.task = None;
+                    let result = match (success, error) {
+                        (true, _) => "ok".to_string(),
+                        (false, Some(error)) => error,
+                        (false, None) => "failed".to_string(),
+                    };
+                    self.last_run = Some((kind, result));
+                    self.publish();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// 把诊断按文件分组交给编辑器显示，替换上一次运行的结果
+    fn publish(&self) {
+        let mut files: FileMarkers = Vec::new();
+        for diagnostic in &self.diagnostics {
+            let Some(path) = self.resolve(&diagnostic.file) else {
+                continue;
+            };
+            let marker = Marker {
+                source: "cargo",
+                severity: diagnostic.severity,
+                start: diagnostic.start,
+                end: diagnostic.end,
+                message: diagnostic.message.clone(),
+            };
+            match files.iter_mut().find(|(p, _)| *p == path) {
+                Some((_, markers)) => markers.push(marker),
+                None => files.push((path, vec![marker])),
+            }
+        }
+        let _ = self
+            .diagnostics_sender
+            .blocking_read()
+            .try_send(PipeObject::Diagnostics("cargo", files));
+    }
+
+    /// 在编辑器中打开选中的诊断
+    fn open(&self) {
+        let Some(diagnostic) = self.diagnostics.get(self.selected) else {
+            return;
+        };
+        let Some(path) = self.resolve(&diagnostic.file) else {
+            return;
+        };
+        let _ = self
+            .open_file_sender
+            .blocking_read()
+            .try_send(PipeObject::OpenFile(path, Some(diagnostic.start)));
+    }
+
+    /// 把cargo输出的路径转成相对于项目根目录的路径
+    ///
+    /// 项目是workspace的成员时，路径相对于上层的workspace根目录
+    fn resolve(&self, file: &str) -> Option<Vec<String>> {
+        let root = fs::canonicalize(&self.root).ok()?;
+        let path = root
+            .ancestors()
+            .map(|dir| dir.join(file))
+            .find(|path| path.is_file())?;
+        let path = fs::canonicalize(path).ok()?;
+        Some(
+            path.strip_prefix(&root)
+                .ok()?
+                .iter()
+                .map(|s| s.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+
+    fn status(&self) -> String {
+        let count = |severity| {
+            self.diagnostics
+                .iter()
+                .filter(|d| d.severity == severity)
+                .count()
+        };
+        match &self.last_run {
+            Some((kind, result)) => format!(
+                "{}: {}  {}✗ {}⚠ ",
+                kind.name(),
+                result,
+                count(Severity::Error),
+                count(Severity::Warning)
+            ),
+            None => "b:build t:test c:clippy ".to_string(),
+        }
+    }
+}
+
+fn severity_sign(severity: Severity, s: String) -> StyledContent<String> {
+    match severity {
+        Severity::Error => s.red().bold(),
+        Severity::Warning => s.yellow().bold(),
+        Severity::Note | Severity::Help => s.cyan(),
+    }
+}
+
+impl Component for Problems {
+    fn bind_to(
+        &mut self,
+        framework: &mut Framework,
+    ) -> Result<(), Box<dyn FnOnce(Framework) -> !>> {
+        match framework.add_container("/BottomArea", Arc::clone(&self.container)) {
+            Ok(()) => Ok(()),
+            Err(s) => Err(Box::new(move |fw| {
+                drop(fw);
+                println!("{}", s);
+                exit(-1)
+            })),
+        }
+    }
+
+    fn render(&mut self, renderer: &Renderer) -> (bool, (usize, usize)) {
+        self.receive();
+        let size = renderer.get_size();
+        if size.0 == 0 || size.1 == 0 {
+            return (false, (0, 0));
+        }
+        let focused = self.container.read().unwrap().focused();
+        // 标题，右侧为命令的状态
+        let status = self.status();
+        // 窄的时候优先显示标题
+        let status_width = status
+            .chars()
+            .map(char_width)
+            .sum::<usize>()
+            .min(size.0.saturating_sub(" Problems ".len()));
+        let title =
+            fit(" Problems ".chars(), size.0 - status_width) + &fit(status.chars(), status_width);
+        if focused {
+            renderer.set_section(0, 0, title.dark_red().on_dark_blue());
+        } else {
+            renderer.set_section(0, 0, title.white().on_dark_grey());
+        }
+        // 诊断列表，没有焦点时也显示
+        let height = size.1 - 1;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if height > 0 && self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+        for row in 0..height {
+            let linen = row + 1;
+            let Some(diagnostic) = self.diagnostics.get(self.scroll + row) else {
+                renderer.set_section(0, linen, " ".repeat(size.0).reset());
+                continue;
+            };
+            let sign = match diagnostic.severity {
+                Severity::Error => "E ",
+                Severity::Warning => "W ",
+                Severity::Note => "N ",
+                Severity::Help => "H ",
+            };
+            let sign = fit(sign.chars(), size.0.min(2));
+            let text = format!(
+                "{}:{}:{} {}",
+                diagnostic.file,
+                diagnostic.start.0 + 1,
+                diagnostic.start.1 + 1,
+                diagnostic.message.lines().next().unwrap_or_default()
+            );
+            let text = fit(text.chars(), size.0 - sign.len());
+            renderer.set_section(0, linen, severity_sign(diagnostic.severity, sign));
+            let selected = focused && self.scroll + row == self.selected;
+            renderer.set_section(
+                size.0.min(2),
+                linen,
+                if selected {
+                    text.black().on_grey()
+                } else {
+                    text.reset()
+                },
+            );
+        }
+        (false, (0, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::testing::{self, press};
+    use crossterm::style::Color;
+
+    fn diagnostic(severity: Severity, file: &str, line: usize, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity,
+            file: file.to_string(),
+            start: (line, 4),
+            end: (line, 5),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn lists_diagnostics_and_opens_selected() {
+        let root = testing::project("problems", &[("src/main.rs", "fn main() {}\n")]);
+        let mut framework = testing::framework(50, 6);
+        let problems = Problems::new(root);
+        testing::bind(&mut framework, &problems);
+        problems.write().unwrap().diagnostics = vec![
+            diagnostic(Severity::Error, "src/main.rs", 0, "mismatched types\nmore"),
+            diagnostic(Severity::Warning, "src/main.rs", 2, "unused variable"),
+        ];
+        testing::show(&mut framework, "/BottomArea/Problems");
+        // 缩放时WorkArea只剩第一行，消息只显示第一行
+        assert_eq!(
+            testing::lines(&framework)[1..4],
+            [
+                " Problems                 b:build t:test c:clippy",
+                "E src/main.rs:1:5 mismatched types",
+                "W src/main.rs:3:5 unused variable",
+            ]
+        );
+        assert_eq!(
+            testing::style(&framework, 0, 1).background_color,
+            Some(Color::DarkBlue)
+        );
+        assert_eq!(
+            testing::style(&framework, 0, 2).foreground_color,
+            Some(Color::Red)
+        );
+        assert_eq!(
+            testing::style(&framework, 0, 3).foreground_color,
+            Some(Color::Yellow)
+        );
+        assert_eq!(
+            testing::style(&framework, 2, 2).background_color,
+            Some(Color::Grey)
+        );
+
+        let open_file = NamedPipe::open_receiver(String::from("OpenFile"));
+        press(&mut framework, KeyCode::Down);
+        framework.render();
+        assert_eq!(testing::style(&framework, 2, 2).background_color, None);
+        assert_eq!(
+            testing::style(&framework, 2, 3).background_color,
+            Some(Color::Grey)
+        );
+        press(&mut framework, KeyCode::Enter);
+        let Ok(PipeObject::OpenFile(path, at)) = open_file.blocking_write().try_recv() else {
+            panic!("expected the diagnostic to be opened");
+        };
+        assert_eq!(path, ["src", "main.rs"]);
+        assert_eq!(at, Some((2, 4)));
+    }
+}
@@ -1,21 +1,21 @@
 use crate::{
+    command::Registry,
     components::{component::Component, editor::Editing},
+    diagnostic::FileMarkers,
+    lsp::{self, Client, TextEdit},
     named_pipe::{NamedPipe, PipeObject},
     renderer::Renderer,
     Container, ContainerType, Framework,
 };
 use crossterm::{
-    cursor,
     event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers},
-    queue,
-    style::{self, Stylize},
+    style::Stylize,
 };
 use std::{
     cell::RefCell,
     cmp::Ordering,
-    fs,
-    io::Write,
-    iter,
+    collections::HashMap,
+    fs, iter,
     process::exit,
     rc::Rc,
     sync::{Arc, RwLock},
@@ -28,22 +28,29 @@ pub struct ProjectViewer {
     at_line: usize,
     fs: Filesystem,
     editor_stack: Vec<Arc<AsyncRwLock<Editing>>>,
+    /// 每个来源最近给出的诊断，打开文件时交给新的Editing
+    diagnostics: HashMap<&'static str, FileMarkers>,
+    /// 已经启动的语言服务器，启动失败的记为None，不再重试
+    servers: HashMap<&'static str, Option<Arc<Client>>>,
     file_open_sender: [Arc<AsyncRwLock<Sender<PipeObject>>>; 2],
     move_focus_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
 }
 
 impl ProjectViewer {
-    pub fn new() -> Arc<RwLock<Self>> {
+    /// 显示root下的目录树，root为项目根目录
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(root: String) -> Arc<RwLock<Self>> {
         let mut container = Container::new("ProjectViewer", None);
         container.focus();
         let container = Arc::new(RwLock::new(container));
-        let path = std::env::var("PWD").unwrap();
         let res = Arc::new(RwLock::new(ProjectViewer {
             container,
-            path: path.clone(),
+            path: root.clone(),
             at_line: 0,
             editor_stack: Vec::new(),
-            fs: Filesystem::new(path),
+            diagnostics: HashMap::new(),
+            servers: HashMap::new(),
+            fs: Filesystem::new(root),
             file_open_sender: [
                 NamedPipe::open_sender(String::from("FileOpen0")),
                 NamedPipe::open_sender(String::from("FileOpen1")),
@@ -97,84 +104,13 @@ impl ProjectViewer {
                                 res_ref.write().unwrap().fs.showing_start += 1;
                             }
                         }
-                        KeyCode::Enter => {
-                            let content = res_ref
-                                .read()
-                                .unwrap()
-                                .fs
-                                .iter(contsize.1 - 1)
-                                .collect::<Vec<_>>();
-                            let meta = &content[res_ref.read().unwrap().at_line];
-                            if meta.1 == PathType::Directory {
-                                // 展开目录
-                                res_ref.write().unwrap().fs.fold_unfold(&meta.0, None);
-                            } else {
-                                // 打开文件
-                                let file_path = res_ref.read().unwrap().path.clone();
-                                let mut file_path = file_path
-                                    .split("/")
-                                    .map(|s| s.to_string())
-                                    .collect::<Vec<String>>();
-                                file_path.append(&mut meta.0.clone());
-                                // editor stack是否存在这个文件
-                                let ind = res_ref
-                                    .read()
-                                    .unwrap()
-                                    .editor_stack
-                                    .iter()
-                                    .enumerate()
-                                    .filter_map(|(i, edi)| {
-                                        if *edi.blocking_read().path() == file_path {
-                                            Some(i)
-                                        } else {
-                                            None
-                                        }
-                                    })
-                                    .collect::<Vec<usize>>();
-                                let editing = if ind.is_empty() {
-                                    // 不存在则构造一个
-                                    let editing = Editing::new(file_path);
-                                    let editing = Arc::new(AsyncRwLock::new(editing));
-                                    editing
-                                } else {
-                                    let ind = ind[0];
-                                    let ed = res_ref.write().unwrap().editor_stack.remove(ind);
-                                    ed
-                                };
-                                if let Some(ed) = res_ref.read().unwrap().editor_stack.last() {
-                                    res_ref.read().unwrap().file_open_sender[1]
-                                        .blocking_write()
-                                        .try_send(PipeObject::Editing(Arc::clone(ed)))
-                                        .unwrap();
-                                }
-                                res_ref
-                                    .write()
-                                    .unwrap()
-                                    .editor_stack
-                                    .push(Arc::clone(&editing));
-                                res_ref.read().unwrap().file_open_sender[0]
-                                    .blocking_read()
-                                    .try_send(PipeObject::Editing(editing))
-                                    .unwrap();
-                                res_ref
-                                    .read()
-                                    .unwrap()
-                                    .move_focus_sender
-                                    .blocking_read()
-                                    .try_send(PipeObject::MoveFocus)
-                                    .unwrap();
-                            }
-                        }
                         _ => (),
                     },
-                    Event::Resize(..) => {
-                        if contsize.1 > 1 {
-                            let at_line = res_ref.read().unwrap().at_line;
-                            if at_line >= contsize.1 - 1 {
-                                res_ref.write().unwrap().fs.showing_start +=
-                                    at_line + 2 - contsize.1;
-                                res_ref.write().unwrap().at_line = contsize.1 - 2;
-                            }
+                    Event::Resize(..) if contsize.1 > 1 => {
+                        let at_line = res_ref.read().unwrap().at_line;
+                        if at_line >= contsize.1 - 1 {
+                            res_ref.write().unwrap().fs.showing_start += at_line + 2 - contsize.1;
+                            res_ref.write().unwrap().at_line = contsize.1 - 2;
                         }
                     }
                     _ => (),
@@ -182,6 +118,170 @@ impl ProjectViewer {
             }));
         res
     }
+
+    pub fn register_commands(this: &Arc<RwLock<Self>>, registry: &mut Registry) {
+        let viewer = Arc::clone(this);
+        registry.register("ProjectViewer: Collapse All", move |framework| {
+            viewer.write().unwrap().collapse_all();
+            framework.set_focus("/WorkArea/SideBar/ProjectViewer");
+        });
+        let viewer = Arc::clone(this);
+        registry.register("ProjectViewer: Save All Files", move |_| {
+            viewer.read().unwrap().save_all()
+        });
+        let viewer = Arc::clone(this);
+        registry.register("ProjectViewer: Open Selected", move |framework| {
+            framework.set_focus("/WorkArea/SideBar/ProjectViewer");
+            viewer.write().unwrap().open_selected();
+        });
+    }
+
+    /// 展开或折叠选中的目录，选中文件时打开它
+    fn open_selected(&mut self) {
+        let height = self.container.read().unwrap().get_size().1;
+        let content = self.fs.iter(height.saturating_sub(1)).collect::<Vec<_>>();
+        let Some(meta) = content.get(self.at_line) else {
+            return;
+        };
+        if meta.1 == PathType::Directory {
+            self.fs.fold_unfold(&meta.0, None);
+        } else {
+            self.open_file(meta.0.clone(), None);
+        }
+    }
+
+    /// 折叠所有展开的目录并回到列表顶部
+    fn collapse_all(&mut self) {
+        collapse(&self.fs.path_cache);
+        self.fs.showing_start = 0;
+        self.at_line = 0;
+    }
+
+    /// 在Editor0中打开项目中的文件，原来的文件移到Editor1
+    ///
+    /// path是相对于项目根目录的路径，at是打开后光标所在的(行号, 字符下标)
+    pub fn open_file(&mut self, path: Vec<String>, at: Option<(usize, usize)>) {
+        // editor stack是否存在这个文件
+        let editing = match self.find_editing(&path) {
+            Some(ind) => self.editor_stack.remove(ind),
+            // 不存在则构造一个
+            None => self.load(&path),
+        };
+        if let Some(at) = at {
+            editing.blocking_write().request_jump(at);
+        }
+        if let Some(ed) = self.editor_stack.last() {
+            self.file_open_sender[1]
+                .blocking_write()
+                .try_send(PipeObject::Editing(Arc::clone(ed)))
+                .unwrap();
+        }
+        self.editor_stack.push(Arc::clone(&editing));
+        self.file_open_sender[0]
+            .blocking_read()
+            .try_send(PipeObject::Editing(editing))
+            .unwrap();
+        self.move_focus_sender
+            .blocking_read()
+            .try_send(PipeObject::MoveFocus)
+            .unwrap();
+    }
+
+    /// 相对路径为path的文件在editor stack中的位置
+    fn find_editing(&self, path: &[String]) -> Option<usize> {
+        let prefix = self.path.split("/").count();
+        self.editor_stack
+            .iter()
+            .position(|edi| edi.blocking_read().path().get(prefix..) == Some(path))
+    }
+
+    /// 从磁盘读出文件，加上已有的诊断并交给对应的语言服务器
+    fn load(&mut self, path: &[String]) -> Arc<AsyncRwLock<Editing>> {
+        let mut file_path = self
+            .path
+            .split("/")
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        file_path.extend(path.iter().cloned());
+        let mut editing = Editing::new(file_path);
+        // 刚从磁盘读出，诊断的位置仍然有效
+        for (&source, files) in &self.diagnostics {
+            if let Some((_, markers)) = files.iter().find(|(p, _)| p == path) {
+                editing.set_markers(source, markers.clone());
+            }
+        }
+        let server = path.last().and_then(|name| lsp::server_for(name));
+        if let Some((server, language)) = server {
+            let root = &self.path;
+            let client = self
+                .servers
+                .entry(server.name)
+                .or_insert_with(|| Client::spawn(server, root).ok().map(Arc::new));
+            if let Some(client) = client {
+                editing.attach(Arc::clone(client), language);
+            }
+        }
+        Arc::new(AsyncRwLock::new(editing))
+    }
+
+    /// 应用语言服务器给出的修改，没有打开的文件载入后放到editor stack底部
+    pub fn apply_edits(&mut self, files: Vec<(Vec<String>, Vec<TextEdit>)>) {
+        for (path, edits) in files {
+            let editing = match self.find_editing(&path) {
+                Some(ind) => Arc::clone(&self.editor_stack[ind]),
+                None => {
+                    let editing = self.load(&path);
+                    self.editor_stack.insert(0, Arc::clone(&editing));
+                    editing
+                }
+            };
+            editing.blocking_write().apply_edits(&edits);
+        }
+    }
+
+    /// 用source的新诊断替换旧的，已打开的文件立即更新
+    ///
+    /// 每项的路径相对于项目根目录
+    pub fn set_diagnostics(&mut self, source: &'static str, diagnostics: FileMarkers) {
+        let prefix = self.path.split("/").count();
+        for editing in &self.editor_stack {
+            let mut editing = editing.blocking_write();
+            let markers = diagnostics
+                .iter()
+                .find(|(path, _)| editing.path().get(prefix..) == Some(path.as_slice()))
+                .map(|(_, markers)| markers.clone())
+                .unwrap_or_default();
+            editing.set_markers(source, markers);
+        }
+        self.diagnostics.insert(source, diagnostics);
+    }
+
+    /// editor stack中所有打开的文件
+    pub fn editings(&self) -> Vec<Arc<AsyncRwLock<Editing>>> {
+        self.editor_stack.clone()
+    }
+
+    pub fn root(&self) -> &str {
+        &self.fs.root
+    }
+
+    /// 项目中所有文件相对于根目录的路径
+    pub fn files(&self) -> Vec<Vec<String>> {
+        let mut res = Vec::new();
+        collect_files(&self.fs.path_cache, &mut Vec::new(), &mut res);
+        res
+    }
+
+    /// 保存editor stack中所有被修改过的文件
+    pub fn save_all(&self) {
+        for editing in &self.editor_stack {
+            let mut editing = editing.blocking_write();
+            if editing.dirty() {
+                // 失败信息记录在Editing中并显示在标题栏
+                let _ = editing.save();
+            }
+        }
+    }
 }
 
 impl Component for ProjectViewer {
@@ -189,7 +289,7 @@ impl Component for ProjectViewer {
         &mut self,
         framework: &mut Framework,
     ) -> Result<(), Box<dyn FnOnce(Framework) -> !>> {
-        match framework.add_container("/WorkArea", Arc::clone(&self.container)) {
+        match framework.add_container("/WorkArea/SideBar", Arc::clone(&self.container)) {
             Ok(()) => Ok(()),
             Err(s) => Err(Box::new(move |fw| {
                 drop(fw);
@@ -213,97 +313,96 @@ impl Component for ProjectViewer {
             title
         };
         title.append(
-            &mut iter::repeat(' ')
-                .take(if size.0 == 1 { size.1 } else { size.0 } - title.len())
+            &mut iter::repeat_n(' ', if size.0 == 1 { size.1 } else { size.0 } - title.len())
                 .collect::<Vec<_>>(),
         );
         let mut titlev = title;
         let title = String::from_iter(titlev.iter());
-        if !focused {
-            if size.0 == 1 {
-                let mut title = String::from("ProjViewer | ").chars().collect::<Vec<_>>();
-                title.append(&mut titlev);
-                for i in 0..title.len() {
-                    renderer.set(0, i, title[i].white().on_dark_grey());
-                }
-            } else {
-                renderer.set_section(0, 0, title.white().on_dark_grey());
+        if !focused && size.0 == 1 {
+            let mut title = String::from("ProjViewer | ").chars().collect::<Vec<_>>();
+            title.append(&mut titlev);
+            for (i, ch) in title.iter().take(size.1).enumerate() {
+                renderer.set(0, i, ch.white().on_dark_grey());
             }
-        } else {
-            // 绘制标题
+            return (false, (0, 0));
+        }
+        // 绘制标题
+        if focused {
             renderer.set_section(0, 0, title.dark_red().on_dark_blue());
-            // 绘制主体
-            let mut linen = 1;
-            for (path, ptype, open, depth, endflg_path) in self.fs.iter(size.1 - 1) {
-                let mut s = String::new();
-                for i in 0..depth {
-                    s += if i == depth - 1 {
-                        if ptype == PathType::Directory {
-                            if *endflg_path.last().unwrap() {
-                                "┕━"
-                            } else {
-                                "┝━"
-                            }
+        } else {
+            renderer.set_section(0, 0, title.white().on_dark_grey());
+        }
+        // 绘制主体，没有焦点时也画，不缩放时能看到
+        let mut linen = 1;
+        for (path, ptype, open, depth, endflg_path) in self.fs.iter(size.1.saturating_sub(1)) {
+            let mut s = String::new();
+            for i in 0..depth {
+                s += if i == depth - 1 {
+                    if ptype == PathType::Directory {
+                        if *endflg_path.last().unwrap() {
+                            "┕━"
                         } else {
-                            if *endflg_path.last().unwrap() {
-                                "╰─"
-                            } else {
-                                "├─"
-                            }
+                            "┝━"
                         }
                     } else {
-                        if endflg_path[i + 1] {
-                            "  "
+                        if *endflg_path.last().unwrap() {
+                            "╰─"
                         } else {
-                            "│ "
+                            "├─"
                         }
-                    };
-                }
-                s += if ptype == PathType::Directory {
-                    if open {
-                        "┭ "
-                    } else {
-                        "╾ "
                     }
-                } else if ptype == PathType::None {
-                    "──"
                 } else {
-                    "─ "
-                };
-                s += &path.last().unwrap();
-                let mut s = if s.chars().collect::<Vec<_>>().len() > size.0 {
-                    s.chars().collect::<Vec<_>>().split_at(size.0).0.to_vec()
-                } else {
-                    s.chars().collect::<Vec<_>>()
-                };
-                s.append(&mut iter::repeat(' ').take(size.0 - s.len()).collect::<Vec<_>>());
-                let s = String::from_iter(s.iter());
-                renderer.set_section(
-                    0,
-                    linen,
-                    if linen - 1 == self.at_line {
-                        s.black().on_grey()
+                    if endflg_path[i + 1] {
+                        "  "
                     } else {
-                        s.reset()
-                    },
-                );
-                linen += 1;
-            }
-            // 覆盖不需要的
-            while linen < size.1 {
-                renderer.set_section(
-                    0,
-                    linen,
-                    iter::repeat(' ').take(size.0).collect::<String>().reset(),
-                );
-                linen += 1;
+                        "│ "
+                    }
+                };
             }
+            s += if ptype == PathType::Directory {
+                if open {
+                    "┭ "
+                } else {
+                    "╾ "
+                }
+            } else if ptype == PathType::None {
+                "──"
+            } else {
+                "─ "
+            };
+            s += path.last().unwrap();
+            let mut s = if s.chars().collect::<Vec<_>>().len() > size.0 {
+                s.chars().collect::<Vec<_>>().split_at(size.0).0.to_vec()
+            } else {
+                s.chars().collect::<Vec<_>>()
+            };
+            s.append(&mut iter::repeat_n(' ', size.0 - s.len()).collect::<Vec<_>>());
+            let s = String::from_iter(s.iter());
+            renderer.set_section(
+                0,
+                linen,
+                if linen - 1 == self.at_line {
+                    s.black().on_grey()
+                } else {
+                    s.reset()
+                },
+            );
+            linen += 1;
+        }
+        // 覆盖不需要的
+        while linen < size.1 {
+            renderer.set_section(
+                0,
+                linen,
+                iter::repeat_n(' ', size.0).collect::<String>().reset(),
+            );
+            linen += 1;
         }
         (false, (0, 0))
     }
 }
 
-#[derive(PartialEq, PartialOrd, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 enum PathType {
     File,
     Directory,
@@ -311,7 +410,7 @@ enum PathType {
     None,
 }
 
-struct Path(String, PathType, Rc<RefCell<bool>>, Vec<Box<Self>>);
+struct Path(String, PathType, Rc<RefCell<bool>>, Vec<Self>);
 
 impl PartialEq for Path {
     fn eq(&self, s: &Self) -> bool {
@@ -321,18 +420,7 @@ impl PartialEq for Path {
 
 impl PartialOrd for Path {
     fn partial_cmp(&self, s: &Self) -> Option<Ordering> {
-        if self.1 == PathType::None || s.1 == PathType::None {
-            None
-        } else {
-            match self.1.partial_cmp(&s.1) {
-                Some(o) => match o {
-                    Ordering::Less => Some(Ordering::Greater),
-                    Ordering::Equal => Some(self.0.cmp(&s.0)),
-                    Ordering::Greater => Some(Ordering::Less),
-                },
-                None => None,
-            }
-        }
+        Some(self.cmp(s))
     }
 }
 
@@ -340,18 +428,19 @@ impl Eq for Path {}
 
 impl Ord for Path {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
+        // 目录排在文件前面，同类按名称排序
+        other.1.cmp(&self.1).then_with(|| self.0.cmp(&other.0))
     }
 }
 
 struct Filesystem {
     root: String,
-    path_cache: Vec<Box<Path>>,
+    path_cache: Vec<Path>,
     showing_start: usize,
     last_max: Rc<RefCell<usize>>,
 }
 
-fn traverse(path: &str, path_local: &mut Vec<String>) -> Vec<Box<Path>> {
+fn traverse(path: &str, path_local: &mut Vec<String>) -> Vec<Path> {
     let mut res = Vec::new();
     let list = if let Ok(l) = fs::read_dir(path) {
         l
@@ -370,7 +459,7 @@ fn traverse(path: &str, path_local: &mut Vec<String>) -> Vec<Box<Path>> {
         } else {
             PathType::None
         };
-        res.push(Box::new(Path(
+        res.push(Path(
             entry.file_name().into_string().unwrap(),
             ptype,
             Rc::new(RefCell::new(false)),
@@ -378,7 +467,7 @@ fn traverse(path: &str, path_local: &mut Vec<String>) -> Vec<Box<Path>> {
                 &(path.to_string() + "/" + &entry.file_name().into_string().unwrap()),
                 path_local,
             ),
-        )));
+        ));
         let _ = path_local.pop();
     }
     res.sort();
@@ -393,13 +482,6 @@ impl Filesystem {
             showing_start: 0,
             last_max: Rc::new(RefCell::new(0)),
         };
-        queue!(
-            std::io::stdout(),
-            cursor::MoveTo(0, 0),
-            style::Print("加载项目...")
-        )
-        .unwrap();
-        std::io::stdout().flush().unwrap();
         res.traverse_fs();
         res.path_cache.sort();
         res
@@ -419,7 +501,7 @@ impl Filesystem {
             } else {
                 PathType::None
             };
-            self.path_cache.push(Box::new(Path(
+            self.path_cache.push(Path(
                 entry.file_name().into_string().unwrap(),
                 ptype,
                 Rc::new(RefCell::new(false)),
@@ -427,12 +509,12 @@ impl Filesystem {
                     &(self.root.clone() + "/" + &entry.file_name().into_string().unwrap()),
                     &mut path_local,
                 ),
-            )));
+            ));
             let _ = path_local.pop();
         }
     }
 
-    pub fn fold_unfold(&self, path: &[String], cache: Option<&Vec<Box<Path>>>) {
+    pub fn fold_unfold(&self, path: &[String], cache: Option<&Vec<Path>>) {
         let cache = if let Some(c) = cache {
             c
         } else {
@@ -467,16 +549,15 @@ impl Filesystem {
 }
 
 fn generate_meta_list(
-    paths: &Vec<Box<Path>>,
+    paths: &[Path],
     res: &mut Vec<PathMeta>,
     depth: usize,
     cur_path: &mut Vec<String>,
     endflg_path: &mut Vec<bool>,
 ) {
-    let mut c = 0;
     let l = paths.len();
-    for path in paths {
-        let Path(name, ptype, unfolded, directory) = path.as_ref();
+    for (c, path) in paths.iter().enumerate() {
+        let Path(name, ptype, unfolded, directory) = path;
         cur_path.push(name.clone());
         endflg_path.push(c + 1 == l);
         res.push((
@@ -491,7 +572,6 @@ fn generate_meta_list(
         }
         let _ = cur_path.pop();
         let _ = endflg_path.pop();
-        c += 1;
     }
     if l == 0 {
         cur_path.push("".to_string());
@@ -508,6 +588,25 @@ fn generate_meta_list(
     }
 }
 
+fn collapse(paths: &[Path]) {
+    for path in paths {
+        *path.2.borrow_mut() = false;
+        collapse(&path.3);
+    }
+}
+
+fn collect_files(paths: &[Path], cur_path: &mut Vec<String>, res: &mut Vec<Vec<String>>) {
+    for Path(name, ptype, _, directory) in paths {
+        cur_path.push(name.clone());
+        match ptype {
+            PathType::File => res.push(cur_path.clone()),
+            PathType::Directory => collect_files(directory, cur_path, res),
+            _ => (),
+        }
+        let _ = cur_path.pop();
+    }
+}
+
 type PathMeta = (Vec<String>, PathType, bool, usize, Vec<bool>);
 
 struct FilesystemIterator {
@@ -518,10 +617,66 @@ impl Iterator for FilesystemIterator {
     type Item = PathMeta;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.inner.len() == 0 {
+        if self.inner.is_empty() {
             None
         } else {
             Some(self.inner.remove(0))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::testing::{self, press};
+    use crossterm::style::Color;
+
+    #[test]
+    fn draws_tree_on_headless_backend() {
+        let root = testing::project(
+            "pv",
+            &[
+                ("src/b.txt", ""),
+                ("src/a.txt", "hello"),
+                ("Cargo.toml", ""),
+            ],
+        );
+        let mut framework = testing::framework(30, 8);
+        let viewer = ProjectViewer::new(root.clone());
+        testing::bind(&mut framework, &viewer);
+        let mut registry = Registry::default();
+        ProjectViewer::register_commands(&viewer, &mut registry);
+        testing::show(&mut framework, "/WorkArea/SideBar/ProjectViewer");
+        let name = root.rsplit('/').next().unwrap();
+        assert_eq!(
+            testing::lines(&framework)[..3],
+            [name, "╾ src", "─ Cargo.toml"]
+        );
+        assert_eq!(
+            testing::style(&framework, 0, 0).background_color,
+            Some(Color::DarkBlue)
+        );
+        assert_eq!(
+            testing::style(&framework, 0, 1).background_color,
+            Some(Color::Grey)
+        );
+        assert_eq!(framework.cursor(), None);
+
+        // 按键经过Framework::dispatch到达有焦点的组件
+        press(&mut framework, KeyCode::Down);
+        framework.render();
+        assert_eq!(
+            testing::style(&framework, 0, 2).background_color,
+            Some(Color::Grey)
+        );
+        assert_eq!(testing::style(&framework, 0, 1).background_color, None);
+
+        press(&mut framework, KeyCode::Up);
+        registry.run("ProjectViewer: Open Selected", &mut framework);
+        framework.render();
+        assert_eq!(
+            testing::lines(&framework)[1..5],
+            ["┭ src", "├── a.txt", "╰── b.txt", "─ Cargo.toml"]
+        );
+    }
+}
@@ -0,0 +1,528 @@
+use crossterm::{
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    style::{Color, StyledContent, Stylize},
+};
+use std::{
+    iter,
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, RwLock,
+    },
+    thread,
+};
+use tokio::sync::{mpsc::Sender, RwLock as AsyncRwLock};
+
+use crate::{
+    command::Registry,
+    components::component::Component,
+    named_pipe::{NamedPipe, PipeObject},
+    renderer::{fit, Renderer},
+    search::{grep_file, LineMatch, Matcher, SearchOptions},
+    text_buffer::char_width,
+    ui::{
+        container::{Container, ContainerType},
+        framework::Framework,
+    },
+};
+
+/// 最多保留的匹配行数，超过后停止搜索
+const MAX_MATCHES: usize = 10000;
+
+/// 一个文件中的所有匹配，路径相对于项目根目录
+struct FileMatches {
+    path: Vec<String>,
+    matches: Vec<LineMatch>,
+}
+
+enum SearchEvent {
+    Found(FileMatches),
+    Truncated,
+}
+
+/// 后台运行的搜索，放下时通知搜索线程停止
+struct Running {
+    receiver: Receiver<SearchEvent>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 在项目的所有文件中搜索，结果按文件分组
+pub struct SearchPanel {
+    container: Arc<RwLock<Container>>,
+    root: String,
+    files: Vec<Vec<String>>,
+    query: String,
+    options: SearchOptions,
+    error: Option<String>,
+    results: Vec<FileMatches>,
+    truncated: bool,
+    running: Option<Running>,
+    /// 选中的结果行，None表示在输入查询
+    selected: Option<usize>,
+    scroll: usize,
+    open_file_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+}
+
+impl SearchPanel {
+    /// files是项目中所有文件相对于root的路径
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(root: String, files: Vec<Vec<String>>) -> Arc<RwLock<Self>> {
+        let container = Container::new("SearchPanel", None);
+        let container = Arc::new(RwLock::new(container));
+        let res = Arc::new(RwLock::new(SearchPanel {
+            container,
+            root,
+            files,
+            query: String::new(),
+            options: SearchOptions::default(),
+            error: None,
+            results: Vec::new(),
+            truncated: false,
+            running: None,
+            selected: None,
+            scroll: 0,
+            open_file_sender: NamedPipe::open_sender(String::from("OpenFile")),
+        }));
+        res.read()
+            .unwrap()
+            .container
+            .write()
+            .unwrap()
+            .set_type(ContainerType::SearchPanel(Arc::clone(&res)));
+        let res_ref = Arc::clone(&res);
+        res.read()
+            .unwrap()
+            .container
+            .write()
+            .unwrap()
+            .set_handler(Box::new(move |event, _contsize| {
+                if let Event::Key(KeyEvent {
+                    code, modifiers, ..
+                }) = event
+                {
+                    let mut panel = res_ref.write().unwrap();
+                    match (code, modifiers) {
+                        (KeyCode::Char(ch), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                            panel.query.push(ch);
+                            panel.selected = None;
+                        }
+                        (KeyCode::Backspace, _) => {
+                            panel.query.pop();
+                            panel.selected = None;
+                        }
+                        (KeyCode::Enter, _) => match panel.selected {
+                            Some(row) => panel.open(row),
+                            None => panel.search(),
+                        },
+                        (KeyCode::Down, _) => {
+                            let rows = panel.rows().len();
+                            panel.selected = match panel.selected {
+                                None if rows > 0 => Some(0),
+                                Some(row) if row + 1 < rows => Some(row + 1),
+                                selected => selected,
+                            };
+                        }
+                        (KeyCode::Up, _) => {
+                            panel.selected = panel.selected.and_then(|row| row.checked_sub(1));
+                        }
+                        (KeyCode::Esc, _) => {
+                            // 停止正在进行的搜索
+                            panel.running = None;
+                            panel.selected = None;
+                        }
+                        _ => (),
+                    }
+                }
+            }));
+        res
+    }
+
+    pub fn register_commands(this: &Arc<RwLock<Self>>, registry: &mut Registry) {
+        type Toggle = fn(&mut SearchOptions) -> &mut bool;
+        let commands: [(&str, Toggle); 3] = [
+            ("Search: Toggle Case Sensitive", |options| {
+                &mut options.case_sensitive
+            }),
+            ("Search: Toggle Whole Word", |options| {
+                &mut options.whole_word
+            }),
+            ("Search: Toggle Regex", |options| &mut options.regex),
+        ];
+        for (name, option) in commands {
+            let panel = Arc::clone(this);
+            registry.register(name, move |framework| {
+                framework.set_focus("/WorkArea/SideBar/SearchPanel");
+                panel.write().unwrap().toggle(option);
+            });
+        }
+    }
+
+    /// 切换一个搜索选项并重新搜索
+    fn toggle(&mut self, option: fn(&mut SearchOptions) -> &mut bool) {
+        *option(&mut self.options) ^= true;
+        self.search();
+    }
+
+    /// 用当前的查询重新开始搜索
+    fn search(&mut self) {
+        self.running = None;
+        self.results.clear();
+        self.truncated = false;
+        self.selected = None;
+        self.scroll = 0;
+        let matcher = match Matcher::new(&self.query, self.options) {
+            Ok(Some(matcher)) => matcher,
+            Ok(None) => {
+                self.error = None;
+                return;
+            }
+            Err(e) => {
+                self.error = Some(e);
+                return;
+            }
+        };
+        self.error = None;
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let root = self.root.clone();
+        let files = self.files.clone();
+        let stop = Arc::clone(&cancel);
+        let redraw_sender = NamedPipe::open_sender(String::from("Redraw"));
+        thread::spawn(move || {
+            let mut found = 0;
+            for path in files {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                let file_path = root.clone() + "/" + &path.join("/");
+                let Some(matches) = grep_file(&file_path, &matcher) else {
+                    continue;
+                };
+                if matches.is_empty() {
+                    continue;
+                }
+                found += matches.len();
+                if sender
+                    .send(SearchEvent::Found(FileMatches { path, matches }))
+                    .is_err()
+                {
+                    return;
+                }
+                let _ = redraw_sender.blocking_read().try_send(PipeObject::Redraw);
+                if found >= MAX_MATCHES {
+                    let _ = sender.send(SearchEvent::Truncated);
+                    break;
+                }
+            }
+            drop(sender);
+            let _ = redraw_sender.blocking_read().try_send(PipeObject::Redraw);
+        });
+        self.running = Some(Running { receiver, cancel });
+    }
+
+    /// 收下搜索线程已经送来的结果
+    fn receive(&mut self) {
+        let Some(running) = &self.running else {
+            return;
+        };
+        loop {
+            match running.receiver.try_recv() {
+                Ok(SearchEvent::Found(file)) => self.results.push(file),
+                Ok(SearchEvent::Truncated) => self.truncated = true,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.running = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// 结果的每一行：(文件下标, 匹配行下标)，文件标题行没有匹配行下标
+    fn rows(&self) -> Vec<(usize, Option<usize>)> {
+        let mut rows = Vec::new();
+        for (i, file) in self.results.iter().enumerate() {
+            rows.push((i, None));
+            rows.extend((0..file.matches.len()).map(|j| (i, Some(j))));
+        }
+        rows
+    }
+
+    /// 打开选中的结果，选中文件标题时跳到第一个匹配
+    fn open(&self, row: usize) {
+        let Some(&(file, line)) = self.rows().get(row) else {
+            return;
+        };
+        let file = &self.results[file];
+        let m = &file.matches[line.unwrap_or(0)];
+        let _ = self
+            .open_file_sender
+            .blocking_read()
+            .try_send(PipeObject::OpenFile(
+                file.path.clone(),
+                Some((m.line, m.ranges[0].0)),
+            ));
+    }
+
+    fn status(&self) -> String {
+        let matches = self
+            .results
+            .iter()
+            .map(|f| f.matches.len())
+            .sum::<usize>();
+        if self.running.is_some() {
+            format!("{} in {} files…", matches, self.results.len())
+        } else if self.truncated {
+            format!("{}+ in {} files", matches, self.results.len())
+        } else if self.results.is_empty() {
+            String::new()
+        } else {
+            format!("{} in {} files", matches, self.results.len())
+        }
+    }
+}
+
+impl Component for SearchPanel {
+    fn bind_to(
+        &mut self,
+        framework: &mut Framework,
+    ) -> Result<(), Box<dyn FnOnce(Framework) -> !>> {
+        match framework.add_container("/WorkArea/SideBar", Arc::clone(&self.container)) {
+            Ok(()) => Ok(()),
+            Err(s) => Err(Box::new(move |fw| {
+                drop(fw);
+                println!("{}", s);
+                exit(-1)
+            })),
+        }
+    }
+
+    fn render(&mut self, renderer: &Renderer) -> (bool, (usize, usize)) {
+        self.receive();
+        let size = renderer.get_size();
+        let focused = self.container.read().unwrap().focused();
+        if size.0 == 1 {
+            let title = format!("Search | {}", self.query);
+            for (i, ch) in fit(title.chars(), size.1).chars().enumerate() {
+                renderer.set(0, i, ch.white().on_dark_grey());
+            }
+            return (false, (0, 0));
+        }
+        // 标题，右侧为搜索进度
+        let status = self.status();
+        let status_width = status.chars().map(char_width).sum::<usize>().min(size.0);
+        let title = fit(" Search".chars(), size.0 - status_width) + &status;
+        if focused {
+            renderer.set_section(0, 0, title.dark_red().on_dark_blue());
+        } else {
+            renderer.set_section(0, 0, title.white().on_dark_grey());
+        }
+        if size.1 < 2 {
+            return (false, (0, 0));
+        }
+        // 查询输入行，右侧为搜索选项
+        let flags = [
+            (self.options.case_sensitive, "[Aa]"),
+            (self.options.whole_word, "[W]"),
+            (self.options.regex, "[.*]"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, flag)| *flag)
+        .collect::<String>();
+        let flags_width = flags.len().min(size.0);
+        let input = format!("/{}", self.query);
+        let input_width = input.chars().map(char_width).sum::<usize>();
+        let input = match &self.error {
+            Some(e) => format!("{}  ✗ {}", input, e),
+            None => input,
+        };
+        renderer.set_section(
+            0,
+            1,
+            (fit(input.chars(), size.0 - flags_width) + &flags).reset(),
+        );
+        // 结果
+        let rows = self.rows();
+        let height = size.1 - 2;
+        if let Some(sel) = self.selected {
+            if sel < self.scroll {
+                self.scroll = sel;
+            } else if sel >= self.scroll + height {
+                self.scroll = sel + 1 - height;
+            }
+        }
+        let mut linen = 2;
+        for (i, &(file, line)) in rows.iter().enumerate().skip(self.scroll).take(height) {
+            let file = &self.results[file];
+            let selected = self.selected == Some(i);
+            match line {
+                None => {
+                    let s = fit(file.path.join("/").chars(), size.0);
+                    renderer.set_section(
+                        0,
+                        linen,
+                        if selected {
+                            s.black().on_grey()
+                        } else {
+                            s.dark_yellow()
+                        },
+                    );
+                }
+                Some(line) => {
+                    let m = &file.matches[line];
+                    let number = format!("{:>5} ", m.line + 1);
+                    // 去掉缩进，匹配区间跟着左移
+                    let indent = m.text.chars().take_while(|ch| ch.is_whitespace()).count();
+                    let text = m.text.chars().skip(indent);
+                    if selected {
+                        let s = fit(number.chars().chain(text), size.0);
+                        renderer.set_section(0, linen, s.black().on_grey());
+                    } else {
+                        let number = fit(number.chars(), number.len().min(size.0));
+                        let mut x = number.chars().map(char_width).sum::<usize>();
+                        renderer.set_section(0, linen, number.dark_grey());
+                        // 按是否在匹配中分段绘制
+                        let mut section = String::new();
+                        let mut in_match = false;
+                        let mut section_x = x;
+                        for (index, ch) in text.enumerate().filter(|(_, ch)| !ch.is_control()) {
+                            let index = index + indent;
+                            let w = char_width(ch);
+                            if x + w > size.0 {
+                                break;
+                            }
+                            let m = m.ranges.iter().any(|r| r.0 <= index && index < r.1);
+                            if m != in_match {
+                                renderer.set_section(section_x, linen, styled(section, in_match));
+                                section = String::new();
+                                section_x = x;
+                                in_match = m;
+                            }
+                            section.push(ch);
+                            x += w;
+                        }
+                        renderer.set_section(section_x, linen, styled(section, in_match));
+                        renderer.set_section(
+                            x,
+                            linen,
+                            iter::repeat_n(' ', size.0 - x).collect::<String>().reset(),
+                        );
+                    }
+                }
+            }
+            linen += 1;
+        }
+        // 覆盖不需要的
+        while linen < size.1 {
+            renderer.set_section(
+                0,
+                linen,
+                iter::repeat_n(' ', size.0).collect::<String>().reset(),
+            );
+            linen += 1;
+        }
+        // 没有选中结果时光标在输入行
+        match self.selected {
+            None if focused => (
+                true,
+                (
+                    renderer.x + input_width.min(size.0 - 1),
+                    renderer.y + 1,
+                ),
+            ),
+            _ => (false, (0, 0)),
+        }
+    }
+}
+
+fn styled(section: String, in_match: bool) -> StyledContent<String> {
+    if in_match {
+        section.on(Color::Rgb {
+            r: 0x5a,
+            g: 0x4a,
+            b: 0x14,
+        })
+    } else {
+        section.reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::testing::{self, press, render_until, type_text};
+
+    #[test]
+    fn searches_and_opens_result() {
+        let root = testing::project(
+            "search-panel",
+            &[
+                ("a.txt", "one\n  find me\n"),
+                ("b/c.txt", "also find\n"),
+                ("find.bin", "find\0"),
+            ],
+        );
+        let files = vec![
+            vec!["a.txt".to_string()],
+            vec!["b".to_string(), "c.txt".to_string()],
+            vec!["find.bin".to_string()],
+        ];
+        let mut framework = testing::framework(30, 8);
+        let panel = SearchPanel::new(root, files);
+        testing::bind(&mut framework, &panel);
+        testing::show(&mut framework, "/WorkArea/SideBar/SearchPanel");
+        type_text(&mut framework, "find");
+        press(&mut framework, KeyCode::Enter);
+        // 二进制文件被跳过
+        render_until(&mut framework, |lines| lines[0].ends_with("2 in 2 files"));
+        // 去掉了缩进
+        assert_eq!(
+            testing::lines(&framework)[1..6],
+            [
+                "/find",
+                "a.txt",
+                "    2 find me",
+                "b/c.txt",
+                "    1 also find"
+            ]
+        );
+        assert_eq!(framework.cursor(), Some((5, 1)));
+        assert_eq!(
+            testing::style(&framework, 0, 2).foreground_color,
+            Some(Color::DarkYellow)
+        );
+        let match_bg = Some(Color::Rgb {
+            r: 0x5a,
+            g: 0x4a,
+            b: 0x14,
+        });
+        assert_eq!(testing::style(&framework, 6, 3).background_color, match_bg);
+        assert_eq!(testing::style(&framework, 9, 3).background_color, match_bg);
+        assert_eq!(testing::style(&framework, 10, 3).background_color, None);
+
+        // 选中结果后光标不显示，Enter打开匹配的位置
+        let open_file = NamedPipe::open_receiver(String::from("OpenFile"));
+        press(&mut framework, KeyCode::Down);
+        press(&mut framework, KeyCode::Down);
+        framework.render();
+        assert_eq!(framework.cursor(), None);
+        assert_eq!(
+            testing::style(&framework, 0, 3).background_color,
+            Some(Color::Grey)
+        );
+        press(&mut framework, KeyCode::Enter);
+        let Ok(PipeObject::OpenFile(path, at)) = open_file.blocking_write().try_recv() else {
+            panic!("expected the match to be opened");
+        };
+        assert_eq!(path, ["a.txt"]);
+        assert_eq!(at, Some((1, 2)));
+    }
+}
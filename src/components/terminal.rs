@@ -1,32 +1,398 @@
 use std::{
-    iter,
+    fs, iter,
+    path::{Path, PathBuf},
     process::exit,
     sync::{Arc, RwLock},
 };
 
-use crossterm::style::Stylize;
+use crossterm::{
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    style::{StyledContent, Stylize},
+};
+use tokio::sync::{mpsc::Sender, RwLock as AsyncRwLock};
 
 use crate::{
+    clipboard,
+    command::Registry,
     components::component::Component,
-    renderer::Renderer,
+    named_pipe::{NamedPipe, PipeObject},
+    renderer::{truncate, Renderer},
+    term::{find_locations, key_bytes, Cell, Location, Pty, Screen, Style},
+    text_buffer::char_width,
     ui::{
-        container::{Container, ContainerType},
+        container::{Container, ContainerType, Size},
         framework::Framework,
     },
 };
 
+/// 默认保留的历史行数，可以用IDE_TERMUX_SCROLLBACK环境变量修改
+const SCROLLBACK: usize = 2000;
+
+/// 复制模式，位置都是(行号, 列)，行号包括历史
+struct CopyMode {
+    cursor: (usize, usize),
+    /// 选择的起点，还没开始选择时为None
+    anchor: Option<(usize, usize)>,
+}
+
+/// 一个shell会话，有自己的伪终端、屏幕和历史
+struct Session {
+    name: String,
+    /// 启动shell失败时为None
+    pty: Option<Pty>,
+    screen: Screen,
+    /// shell退出或启动失败的原因
+    status: Option<String>,
+    /// 向上回看的行数，为0时显示最新的输出
+    scroll: usize,
+    /// 复制模式下暂停接收输出，保证行号不变
+    copy: Option<CopyMode>,
+}
+
 pub struct Terminal {
     container: Arc<RwLock<Container>>,
     shell: String,
+    scrollback: usize,
+    /// 伪终端的(列, 行)，新会话按这个大小启动
+    size: (usize, usize),
+    sessions: Vec<Session>,
+    current: usize,
+    /// 正在输入的新会话名
+    renaming: Option<String>,
+    /// 项目根目录，只打开其中的文件
+    root: String,
+    /// 显示在标签页后面的提示，下次按键时清除
+    notice: Option<String>,
+    open_file_sender: Arc<AsyncRwLock<Sender<PipeObject>>>,
+}
+
+impl Session {
+    fn spawn(shell: &str, (cols, rows): (usize, usize), scrollback: usize) -> Self {
+        let (pty, status) = match Pty::spawn(shell, cols, rows) {
+            Ok(pty) => (Some(pty), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Session {
+            name: shell.rsplit('/').next().unwrap_or(shell).to_string(),
+            pty,
+            screen: Screen::new(cols, rows, scrollback),
+            status,
+            scroll: 0,
+            copy: None,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if let Some(pty) = &mut self.pty {
+            let _ = pty.write(bytes);
+        }
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        self.screen.resize(cols, rows);
+        self.scroll = self.scroll.min(self.screen.history_len());
+        self.copy = None;
+        if let Some(pty) = &self.pty {
+            let _ = pty.resize(cols, rows);
+        }
+    }
+
+    /// 把shell的新输出交给screen
+    fn receive(&mut self) {
+        if self.copy.is_some() {
+            return;
+        }
+        let Some(pty) = &self.pty else {
+            return;
+        };
+        match pty.read() {
+            Some(bytes) => {
+                let scrolled = self.screen.feed(&bytes);
+                // 回看时保持显示的内容不动
+                if self.scroll > 0 {
+                    self.scroll = (self.scroll + scrolled).min(self.screen.history_len());
+                }
+                // 回复光标位置报告等查询
+                let responses = self.screen.take_responses();
+                if !responses.is_empty() {
+                    self.write(&responses);
+                }
+            }
+            None => {
+                self.pty = None;
+                self.status = Some("exited".to_string());
+            }
+        }
+    }
+
+    fn scroll_by(&mut self, lines: isize) {
+        self.scroll = self
+            .scroll
+            .saturating_add_signed(lines)
+            .min(self.screen.history_len());
+    }
+
+    /// 显示区域第一行的行号
+    fn view_top(&self) -> usize {
+        self.screen.history_len() - self.scroll
+    }
+
+    fn line_count(&self) -> usize {
+        self.screen.history_len() + self.screen.size().1
+    }
+
+    fn enter_copy(&mut self, height: usize) {
+        // 从光标处开始，回看时从显示区域的最后一行开始
+        let cursor = match self.screen.cursor() {
+            Some((col, row)) if self.scroll == 0 => (self.view_top() + row, col),
+            _ => (self.view_top() + height.min(self.screen.size().1) - 1, 0),
+        };
+        self.copy = Some(CopyMode {
+            cursor,
+            anchor: None,
+        });
+    }
+
+    fn copy_key(&mut self, key: KeyEvent, height: usize) {
+        let Some(copy) = &mut self.copy else {
+            return;
+        };
+        let last_line = self.screen.history_len() + self.screen.size().1 - 1;
+        let last_col = self.screen.size().0 - 1;
+        let (line, col) = &mut copy.cursor;
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => *line = line.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => *line = (*line + 1).min(last_line),
+            KeyCode::Left | KeyCode::Char('h') => *col = col.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => *col = (*col + 1).min(last_col),
+            KeyCode::PageUp => *line = line.saturating_sub(height),
+            KeyCode::PageDown => *line = (*line + height).min(last_line),
+            KeyCode::Home | KeyCode::Char('0') => *col = 0,
+            KeyCode::End | KeyCode::Char('$') => *col = last_col,
+            KeyCode::Char('g') => *line = 0,
+            KeyCode::Char('G') => *line = last_line,
+            KeyCode::Char(' ') | KeyCode::Char('v') => {
+                copy.anchor = match copy.anchor {
+                    Some(_) => None,
+                    None => Some(copy.cursor),
+                };
+            }
+            KeyCode::Enter | KeyCode::Char('y') => {
+                // 没有选择时复制光标所在行
+                let (start, end) = match copy.anchor {
+                    Some(anchor) => (anchor.min(copy.cursor), anchor.max(copy.cursor)),
+                    None => ((copy.cursor.0, 0), (copy.cursor.0, last_col)),
+                };
+                clipboard::copy(self.selected_text(start, end));
+                self.copy = None;
+                return;
+            }
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.copy = None;
+                return;
+            }
+            _ => (),
+        }
+        // 让光标保持在显示区域内
+        let line = copy.cursor.0;
+        let history = self.screen.history_len();
+        let height = height.min(self.screen.size().1);
+        if line < self.view_top() {
+            self.scroll = history - line;
+        } else if line >= self.view_top() + height {
+            self.scroll = (history + height).saturating_sub(line + 1);
+        }
+    }
+
+    /// start到end(含)之间的文本，去掉每行末尾的空白
+    fn selected_text(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        (start.0..=end.0)
+            .map(|line| {
+                let cells = self.screen.line(line);
+                let mut from = if line == start.0 { start.1 } else { 0 };
+                // 从宽字符的后半开始时带上前半
+                if from > 0 && cells.get(from).is_some_and(|cell| cell.wide_tail) {
+                    from -= 1;
+                }
+                let to = if line == end.0 {
+                    end.1 + 1
+                } else {
+                    cells.len()
+                };
+                cells
+                    .iter()
+                    .take(to)
+                    .skip(from)
+                    .filter(|cell| !cell.wide_tail)
+                    .map(|cell| cell.ch)
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// 第line行的文字和每个字符所在的列
+    fn line_text(&self, line: usize) -> (String, Vec<usize>) {
+        self.screen
+            .line(line)
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| !cell.wide_tail)
+            .map(|(col, cell)| (cell.ch, col))
+            .unzip()
+    }
+
+    /// 第line行中的文件位置和它们所占的列范围
+    fn locations(&self, line: usize) -> Vec<(Location, (usize, usize))> {
+        let (text, columns) = self.line_text(line);
+        find_locations(&text)
+            .into_iter()
+            .map(|location| {
+                let start = columns[location.range.0];
+                let end = columns[location.range.1 - 1] + 1;
+                (location, (start, end))
+            })
+            .collect()
+    }
+
+    /// 复制模式光标处的位置，光标不在位置上时取这一行的第一个
+    fn location_at_cursor(&self) -> Option<Location> {
+        let (line, col) = self.copy.as_ref()?.cursor;
+        let mut locations = self.locations(line);
+        let index = locations
+            .iter()
+            .position(|(_, (start, end))| (*start..*end).contains(&col))
+            .unwrap_or(0);
+        (index < locations.len()).then(|| locations.swap_remove(index).0)
+    }
+
+    fn selected(&self, line: usize, col: usize) -> bool {
+        match &self.copy {
+            Some(CopyMode {
+                cursor,
+                anchor: Some(anchor),
+            }) => {
+                let (start, end) = (*anchor.min(cursor), *anchor.max(cursor));
+                (line, col) >= start && (line, col) <= end
+            }
+            _ => false,
+        }
+    }
+
+    /// 绘制一行，相同样式的连续单元格合成一段
+    fn render_line(&self, renderer: &Renderer, linen: usize, line: usize, cells: &[Cell]) {
+        let width = renderer.get_size().0;
+        let mut x = 0;
+        let mut start = 0;
+        let mut text = String::new();
+        let mut style: Option<Style> = None;
+        // 复制模式下给可以打开的位置加下划线
+        let locations = if self.copy.is_some() {
+            self.locations(line)
+        } else {
+            Vec::new()
+        };
+        for (col, cell) in cells.iter().enumerate() {
+            if cell.wide_tail {
+                continue;
+            }
+            let w = char_width(cell.ch);
+            if x + w > width {
+                break;
+            }
+            let mut cell_style = cell.style;
+            if self.selected(line, col) {
+                cell_style.reverse ^= true;
+            }
+            if locations
+                .iter()
+                .any(|(_, (start, end))| (*start..*end).contains(&col))
+            {
+                cell_style.underline = true;
+            }
+            if style.is_some_and(|style| style != cell_style) {
+                let content = StyledContent::new(style.unwrap().content_style(), text);
+                renderer.set_section(start, linen, content);
+                text = String::new();
+                start = x;
+            }
+            style = Some(cell_style);
+            text.push(cell.ch);
+            x += w;
+        }
+        if let Some(style) = style {
+            renderer.set_section(
+                start,
+                linen,
+                StyledContent::new(style.content_style(), text),
+            );
+        }
+        if x < width {
+            renderer.set_section(
+                x,
+                linen,
+                iter::repeat_n(' ', width - x).collect::<String>().reset(),
+            );
+        }
+    }
+
+    /// 绘制标题栏以下的部分，返回光标在容器内的(列, 行)
+    fn render(&self, renderer: &Renderer) -> Option<(usize, usize)> {
+        let size = renderer.get_size();
+        let top = self.view_top();
+        let mut linen = 1;
+        for line in top..self.line_count() {
+            if linen >= size.1 {
+                break;
+            }
+            self.render_line(renderer, linen, line, self.screen.line(line));
+            linen += 1;
+        }
+        // 覆盖不需要的
+        while linen < size.1 {
+            renderer.set_section(
+                0,
+                linen,
+                iter::repeat_n(' ', size.0).collect::<String>().reset(),
+            );
+            linen += 1;
+        }
+        let cursor = match &self.copy {
+            Some(copy) => Some((copy.cursor.1, copy.cursor.0 - top)),
+            None if self.scroll == 0 && self.pty.is_some() => self.screen.cursor(),
+            None => None,
+        };
+        cursor
+            .filter(|&(_, row)| row + 1 < size.1)
+            .map(|(col, row)| (col.min(size.0 - 1), row + 1))
+    }
 }
 
 impl Terminal {
-    pub fn new() -> Arc<RwLock<Self>> {
-        let container = Container::new("Terminal", None);
+    /// 每个会话运行shell，root为项目根目录
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(shell: String, root: String) -> Arc<RwLock<Self>> {
+        let mut container = Container::new("Terminal", None);
+        container.set_split(Size::Weight(2), 1);
         let container = Arc::new(RwLock::new(container));
+        let scrollback = std::env::var("IDE_TERMUX_SCROLLBACK")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(SCROLLBACK);
+        let size = (80, 24);
         let res = Arc::new(RwLock::new(Self {
             container,
-            shell: std::env::var("SHELL").unwrap(),
+            sessions: vec![Session::spawn(&shell, size, scrollback)],
+            shell,
+            scrollback,
+            size,
+            current: 0,
+            renaming: None,
+            root,
+            notice: None,
+            open_file_sender: NamedPipe::open_sender(String::from("OpenFile")),
         }));
         res.read()
             .unwrap()
@@ -40,9 +406,250 @@ impl Terminal {
             .container
             .write()
             .unwrap()
-            .set_handler(Box::new(move |event, contsize| {}));
+            .set_handler(Box::new(move |event, contsize| match event {
+                Event::Key(key) => {
+                    let height = contsize.1.saturating_sub(1).max(1);
+                    res_ref.write().unwrap().key(key, height);
+                }
+                Event::Resize(width, height) => {
+                    let mut terminal = res_ref.write().unwrap();
+                    // 失去焦点时只剩标题行，保持原来的行数
+                    let rows = if height > 1 {
+                        height as usize - 1
+                    } else {
+                        terminal.size.1
+                    };
+                    terminal.size = (width.max(1) as usize, rows);
+                    for session in &mut terminal.sessions {
+                        session.resize(width.max(1) as usize, rows);
+                    }
+                }
+                Event::Paste(text) => {
+                    let mut terminal = res_ref.write().unwrap();
+                    let session = terminal.session();
+                    let text = if session.screen.bracketed_paste() {
+                        format!("\x1b[200~{}\x1b[201~", text)
+                    } else {
+                        text
+                    };
+                    session.write(text.as_bytes());
+                }
+                _ => (),
+            }));
         res
     }
+
+    pub fn register_commands(this: &Arc<RwLock<Self>>, registry: &mut Registry) {
+        type Command = fn(&mut Terminal, usize);
+        let commands: [(&str, Command); 6] = [
+            ("Terminal: New Session", |terminal, _| terminal.open()),
+            ("Terminal: Close Session", |terminal, _| terminal.close()),
+            ("Terminal: Rename Session", |terminal, _| {
+                terminal.renaming = Some(String::new())
+            }),
+            ("Terminal: Next Session", |terminal, _| terminal.switch(1)),
+            ("Terminal: Previous Session", |terminal, _| {
+                terminal.switch(terminal.sessions.len() - 1)
+            }),
+            ("Terminal: Copy Mode", |terminal, height| {
+                terminal.session().enter_copy(height)
+            }),
+        ];
+        for (name, command) in commands {
+            let terminal = Arc::clone(this);
+            registry.register(name, move |framework| {
+                framework.set_focus("/BottomArea/Terminal");
+                let mut terminal = terminal.write().unwrap();
+                terminal.notice = None;
+                let height = terminal.container.read().unwrap().get_size().1;
+                command(&mut terminal, height.saturating_sub(1).max(1));
+            });
+        }
+    }
+
+    /// 按键绑定的上下文，重命名或复制模式中按键都交给它们
+    pub fn key_context(&self) -> Option<&'static str> {
+        if self.renaming.is_some() || self.sessions[self.current].copy.is_some() {
+            return None;
+        }
+        Some("terminal")
+    }
+
+    /// 切换到后面第offset个会话
+    fn switch(&mut self, offset: usize) {
+        self.current = (self.current + offset) % self.sessions.len();
+    }
+
+    fn session(&mut self) -> &mut Session {
+        &mut self.sessions[self.current]
+    }
+
+    fn key(&mut self, key: KeyEvent, height: usize) {
+        self.notice = None;
+        if let Some(name) = &mut self.renaming {
+            match key.code {
+                KeyCode::Char(ch) => name.push(ch),
+                KeyCode::Backspace => {
+                    name.pop();
+                }
+                KeyCode::Enter => {
+                    let name = self.renaming.take().unwrap();
+                    if !name.is_empty() {
+                        self.session().name = name;
+                    }
+                }
+                KeyCode::Esc => self.renaming = None,
+                _ => (),
+            }
+            return;
+        }
+        if self.session().copy.is_some() {
+            if key.code == KeyCode::Char('o') {
+                self.open_location();
+            } else {
+                self.session().copy_key(key, height);
+            }
+            return;
+        }
+        match (key.code, key.modifiers) {
+            (KeyCode::PageUp, KeyModifiers::SHIFT) => self.session().scroll_by(height as isize),
+            (KeyCode::PageDown, KeyModifiers::SHIFT) => {
+                self.session().scroll_by(-(height as isize))
+            }
+            (KeyCode::Up, KeyModifiers::SHIFT) => self.session().scroll_by(1),
+            (KeyCode::Down, KeyModifiers::SHIFT) => self.session().scroll_by(-1),
+            (KeyCode::Char(ch @ '1'..='9'), KeyModifiers::ALT) => {
+                let index = ch as usize - '1' as usize;
+                if index < self.sessions.len() {
+                    self.current = index;
+                }
+            }
+            _ => {
+                let session = self.session();
+                // 输入时回到最新的输出
+                session.scroll = 0;
+                let bytes = key_bytes(&key, session.screen.application_cursor());
+                session.write(&bytes);
+            }
+        }
+    }
+
+    /// 在编辑器中打开复制模式光标处的文件位置
+    fn open_location(&mut self) {
+        let session = &self.sessions[self.current];
+        let Some(location) = session.location_at_cursor() else {
+            self.notice = Some("no location on this line".to_string());
+            return;
+        };
+        let cwd = session.pty.as_ref().and_then(Pty::cwd);
+        let Some(path) = self.resolve(cwd, &location.path) else {
+            self.notice = Some(format!("{} is not a file in the project", location.path));
+            return;
+        };
+        let _ = self
+            .open_file_sender
+            .blocking_read()
+            .try_send(PipeObject::OpenFile(
+                path,
+                Some((location.line - 1, location.column - 1)),
+            ));
+        self.session().copy = None;
+    }
+
+    /// 把输出中的路径转成相对于项目根目录的路径
+    ///
+    /// 相对路径先按shell的当前目录解析，找不到再按项目根目录解析，
+    /// 因为cargo输出的路径相对于workspace
+    fn resolve(&self, cwd: Option<PathBuf>, path: &str) -> Option<Vec<String>> {
+        let root = fs::canonicalize(&self.root).ok()?;
+        let path = Path::new(path);
+        let path = cwd
+            .iter()
+            .chain(iter::once(&root))
+            .map(|dir| dir.join(path))
+            .filter_map(|path| fs::canonicalize(path).ok())
+            .find(|path| path.is_file())?;
+        Some(
+            path.strip_prefix(&root)
+                .ok()?
+                .iter()
+                .map(|s| s.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+
+    /// 在当前会话后面打开新会话并切换过去
+    fn open(&mut self) {
+        let session = Session::spawn(&self.shell, self.size, self.scrollback);
+        self.current += 1;
+        self.sessions.insert(self.current, session);
+    }
+
+    /// 关闭当前会话，关掉最后一个时重新打开一个
+    fn close(&mut self) {
+        self.sessions.remove(self.current);
+        if self.sessions.is_empty() {
+            self.sessions
+                .push(Session::spawn(&self.shell, self.size, self.scrollback));
+        }
+        self.current = self.current.min(self.sessions.len() - 1);
+    }
+
+    /// 绘制标题栏：各会话的标签页，或者重命名的输入
+    fn render_title(&self, renderer: &Renderer, focused: bool) -> Option<(usize, usize)> {
+        let width = renderer.get_size().0;
+        if let Some(name) = &self.renaming {
+            let (title, used) = truncate(format!(" Rename: {}", name).chars(), width);
+            renderer.set_section(
+                0,
+                0,
+                (title + &" ".repeat(width - used))
+                    .dark_red()
+                    .on_dark_blue(),
+            );
+            return Some((used.min(width - 1), 0));
+        }
+        let mut x = 0;
+        for (i, session) in self.sessions.iter().enumerate() {
+            let mut label = format!(" {}:{}", i + 1, session.name);
+            if i == self.current {
+                if let Some(status) = &session.status {
+                    label += &format!(" [{}]", status);
+                }
+                if session.copy.is_some() {
+                    label += " [copy]";
+                } else if session.scroll > 0 {
+                    label += &format!(" [-{}]", session.scroll);
+                }
+            }
+            label.push(' ');
+            let (label, used) = truncate(label.chars(), width - x);
+            let label = match (focused, i == self.current) {
+                (true, true) => label.white().on_blue(),
+                (true, false) => label.dark_red().on_dark_blue(),
+                (false, true) => label.black().on_grey(),
+                (false, false) => label.white().on_dark_grey(),
+            };
+            renderer.set_section(x, 0, label);
+            x += used;
+        }
+        if let Some(notice) = &self.notice {
+            let (notice, used) = truncate(format!(" {}", notice).chars(), width - x);
+            renderer.set_section(x, 0, notice.yellow().on_dark_blue());
+            x += used;
+        }
+        let rest = " ".repeat(width - x);
+        renderer.set_section(
+            x,
+            0,
+            if focused {
+                rest.on_dark_blue()
+            } else {
+                rest.on_dark_grey()
+            },
+        );
+        None
+    }
 }
 
 impl Component for Terminal {
@@ -50,7 +657,7 @@ impl Component for Terminal {
         &mut self,
         framework: &mut Framework,
     ) -> Result<(), Box<dyn FnOnce(Framework) -> !>> {
-        match framework.add_container("/", Arc::clone(&self.container)) {
+        match framework.add_container("/BottomArea", Arc::clone(&self.container)) {
             Ok(()) => Ok(()),
             Err(s) => Err(Box::new(move |fw| {
                 drop(fw);
@@ -61,37 +668,85 @@ impl Component for Terminal {
     }
 
     fn render(&mut self, renderer: &Renderer) -> (bool, (usize, usize)) {
-        let size = renderer.get_size();
-        let title = format!("Terminal {}", self.shell);
-        let title = title.chars().collect::<Vec<_>>();
-        let mut title = if title.len() > size.0 {
-            title.split_at(size.0).0.to_vec()
-        } else {
-            title
-        };
-        title.append(
-            &mut iter::repeat(' ')
-                .take(size.0 - title.len())
-                .collect::<Vec<_>>(),
+        // 后台会话也要接收输出，历史才完整
+        for session in &mut self.sessions {
+            session.receive();
+        }
+        let focused = self.container.read().unwrap().focused();
+        let title_cursor = self.render_title(renderer, focused);
+        // 没有焦点时也画出内容，不缩放时能看到输出
+        let body_cursor = self.sessions[self.current].render(renderer);
+        match title_cursor.or(body_cursor) {
+            Some((col, row)) if focused => (true, (renderer.x + col, renderer.y + row)),
+            _ => (false, (0, 0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::testing::{self, press, press_with, render_until, type_text};
+    use crossterm::style::Color;
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    /// 代替shell的脚本，输出一行文字后读两行输入
+    const STUB: &str = "#!/bin/sh
+printf 'hello\\n'
+read line
+printf 'got %s\\n' \"$line\"
+read line || echo eof
+";
+
+    /// 在临时项目中放一个STUB，返回(脚本路径, 项目根目录)
+    fn stub(name: &str) -> (String, String) {
+        let root = testing::project(name, &[("stub.sh", STUB)]);
+        let stub = format!("{}/stub.sh", root);
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        (stub, root)
+    }
+
+    #[test]
+    fn runs_shell_on_pty() {
+        let (stub, root) = stub("terminal");
+        let mut framework = testing::framework(40, 8);
+        let terminal = Terminal::new(stub, root);
+        testing::bind(&mut framework, &terminal);
+        testing::show(&mut framework, "/BottomArea/Terminal");
+        // 缩放时WorkArea只剩第一行，第二行是标题
+        render_until(&mut framework, |lines| lines[2] == "hello");
+        assert_eq!(framework.cursor(), Some((0, 3)));
+
+        // 按键写进伪终端，由终端回显
+        type_text(&mut framework, "abc");
+        press(&mut framework, KeyCode::Enter);
+        render_until(&mut framework, |lines| lines[4] == "got abc");
+        assert_eq!(testing::lines(&framework)[3], "abc");
+        // Ctrl-D是EOF，shell退出后标题中标出
+        press_with(&mut framework, KeyCode::Char('d'), KeyModifiers::CONTROL);
+        render_until(&mut framework, |lines| lines[1].contains("[exited]"));
+        assert_eq!(testing::lines(&framework)[5], "eof");
+    }
+
+    #[test]
+    fn draws_body_without_focus() {
+        let (stub, root) = stub("terminal-unfocused");
+        let mut framework = testing::framework(40, 12);
+        let terminal = Terminal::new(stub, root);
+        testing::bind(&mut framework, &terminal);
+        testing::show(&mut framework, "/BottomArea/Terminal");
+        // 不缩放时焦点移走后仍然画出输出
+        framework.toggle_zoom();
+        framework.set_focus("/WorkArea");
+        render_until(&mut framework, |lines| lines.contains(&"hello".to_string()));
+        let title = testing::lines(&framework)
+            .iter()
+            .position(|line| line == " 1:stub.sh")
+            .unwrap();
+        assert_eq!(
+            testing::style(&framework, 1, title).background_color,
+            Some(Color::Grey)
         );
-        let title = String::from_iter(title.iter());
-        if !self.container.read().unwrap().focused() {
-            renderer.set_section(0, 0, title.white().on_dark_grey());
-        } else {
-            // 绘制标题
-            renderer.set_section(0, 0, title.dark_red().on_dark_blue());
-            // 绘制主体
-            let mut linen = 1;
-            // 覆盖不需要的
-            while linen < size.1 {
-                renderer.set_section(
-                    0,
-                    linen,
-                    iter::repeat(' ').take(size.0).collect::<String>().reset(),
-                );
-                linen += 1;
-            }
-        }
-        (false, (0, 0))
+        assert_eq!(framework.cursor(), None);
     }
 }
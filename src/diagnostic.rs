@@ -0,0 +1,179 @@
+use crossterm::style::Color;
+
+/// 诊断的严重程度，越严重越靠前
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl Severity {
+    pub fn sign(&self) -> char {
+        match self {
+            Severity::Error => '✗',
+            Severity::Warning => '⚠',
+            Severity::Note | Severity::Help => 'ℹ',
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+            Severity::Note | Severity::Help => Color::Cyan,
+        }
+    }
+
+    /// 标出诊断范围的背景色
+    pub fn tint(&self) -> Color {
+        match self {
+            Severity::Error => Color::Rgb {
+                r: 0x58,
+                g: 0x1c,
+                b: 0x24,
+            },
+            Severity::Warning => Color::Rgb {
+                r: 0x48,
+                g: 0x40,
+                b: 0x14,
+            },
+            Severity::Note | Severity::Help => Color::Rgb {
+                r: 0x14,
+                g: 0x38,
+                b: 0x48,
+            },
+        }
+    }
+}
+
+/// 按文件分组的诊断，路径相对于项目根目录
+pub type FileMarkers = Vec<(Vec<String>, Vec<Marker>)>;
+
+/// 缓冲区中的一条诊断，位置随编辑移动
+#[derive(Clone)]
+pub struct Marker {
+    /// 产生诊断的来源，例如cargo，同一来源的新诊断会替换旧的
+    pub source: &'static str,
+    pub severity: Severity,
+    /// 起止(行号, 字符下标)
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub message: String,
+}
+
+impl Marker {
+    /// start到end之间的内容被替换成了到inserted_end为止的新内容
+    pub fn shift(
+        &mut self,
+        start: (usize, usize),
+        end: (usize, usize),
+        inserted_end: (usize, usize),
+    ) {
+        let shift = |pos: (usize, usize)| {
+            if pos < start {
+                pos
+            } else if pos < end {
+                // 所在的内容被删除了
+                start
+            } else if pos.0 == end.0 {
+                (inserted_end.0, inserted_end.1 + pos.1 - end.1)
+            } else {
+                (pos.0 + inserted_end.0 - end.0, pos.1)
+            }
+        };
+        self.start = shift(self.start);
+        self.end = shift(self.end).max(self.start);
+    }
+
+    pub fn contains(&self, pos: (usize, usize)) -> bool {
+        self.start <= pos && pos < self.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(start: (usize, usize), end: (usize, usize)) -> Marker {
+        Marker {
+            source: "cargo",
+            severity: Severity::Warning,
+            start,
+            end,
+            message: String::new(),
+        }
+    }
+
+    fn shifted(
+        m: (usize, usize),
+        n: (usize, usize),
+        start: (usize, usize),
+        end: (usize, usize),
+        inserted_end: (usize, usize),
+    ) -> ((usize, usize), (usize, usize)) {
+        let mut marker = marker(m, n);
+        marker.shift(start, end, inserted_end);
+        (marker.start, marker.end)
+    }
+
+    #[test]
+    fn edits_after_the_marker_do_nothing() {
+        assert_eq!(
+            shifted((2, 1), (2, 4), (3, 0), (3, 0), (3, 5)),
+            ((2, 1), (2, 4))
+        );
+        assert_eq!(
+            shifted((2, 1), (2, 4), (2, 5), (2, 5), (2, 7)),
+            ((2, 1), (2, 4))
+        );
+    }
+
+    #[test]
+    fn inserted_lines_move_the_marker_down() {
+        assert_eq!(
+            shifted((2, 1), (2, 4), (0, 3), (0, 3), (2, 0)),
+            ((4, 1), (4, 4))
+        );
+        // 同一行前面插入字符时列号后移
+        assert_eq!(
+            shifted((2, 1), (2, 4), (2, 0), (2, 0), (2, 3)),
+            ((2, 4), (2, 7))
+        );
+    }
+
+    #[test]
+    fn deleted_lines_move_the_marker_up() {
+        assert_eq!(
+            shifted((5, 2), (6, 1), (1, 0), (3, 0), (1, 0)),
+            ((3, 2), (4, 1))
+        );
+        // 删除了上一行的换行符，标记并到上一行末尾之后
+        assert_eq!(
+            shifted((2, 1), (2, 4), (1, 7), (2, 0), (1, 7)),
+            ((1, 8), (1, 11))
+        );
+    }
+
+    #[test]
+    fn deleted_content_collapses_to_the_edit() {
+        assert_eq!(
+            shifted((2, 1), (2, 4), (1, 0), (2, 3), (1, 0)),
+            ((1, 0), (1, 1))
+        );
+        assert_eq!(
+            shifted((2, 1), (2, 4), (2, 0), (3, 0), (2, 0)),
+            ((2, 0), (2, 0))
+        );
+    }
+
+    #[test]
+    fn contains_is_half_open() {
+        let m = marker((1, 2), (1, 4));
+        assert!(!m.contains((1, 1)));
+        assert!(m.contains((1, 2)));
+        assert!(m.contains((1, 3)));
+        assert!(!m.contains((1, 4)));
+    }
+}
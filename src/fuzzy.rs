@@ -0,0 +1,131 @@
+/// 每个匹配字符的基础分
+const MATCH: i32 = 16;
+/// 匹配字符之间每隔一个字符扣的分
+const GAP: i32 = 1;
+/// 紧接着上一个匹配字符时的加分
+const CONSECUTIVE: i32 = 8;
+/// 匹配在单词开头时的加分
+const BOUNDARY: i32 = 8;
+/// 匹配在路径分隔符之后时的额外加分
+const AFTER_SLASH: i32 = 4;
+
+fn bonus(text: &[char], j: usize) -> i32 {
+    if j == 0 {
+        return BOUNDARY + AFTER_SLASH;
+    }
+    let (prev, ch) = (text[j - 1], text[j]);
+    if prev == '/' {
+        BOUNDARY + AFTER_SLASH
+    } else if "_-. ".contains(prev) || (prev.is_lowercase() && ch.is_uppercase()) {
+        BOUNDARY
+    } else {
+        0
+    }
+}
+
+/// 用pattern模糊匹配text，pattern的字符必须按顺序出现在text中
+///
+/// 返回分数和匹配到的字符下标，分数越高越好；pattern中没有大写字母时忽略大小写
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<(i32, Vec<usize>)> {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    if pattern.is_empty() {
+        return Some((0, Vec::new()));
+    }
+    let ignore_case = !pattern.iter().any(|ch| ch.is_uppercase());
+    let eq = |p: char, t: char| {
+        if ignore_case {
+            t.to_lowercase().eq(p.to_lowercase())
+        } else {
+            p == t
+        }
+    };
+    let (m, n) = (pattern.len(), text.len());
+    // score[i][j]：pattern[..=i]匹配完并且pattern[i]落在text[j]时的最高分
+    let mut score = vec![vec![None; n]; m];
+    let mut back = vec![vec![0; n]; m];
+    for i in 0..m {
+        // 之前所有可以接上的位置中，按间隔扣分后最好的一个
+        let mut best: Option<(i32, usize)> = None;
+        for j in 0..n {
+            if i > 0 && j > 0 {
+                if let Some(s) = score[i - 1][j - 1] {
+                    let s = s + GAP * (j as i32 - 1);
+                    if best.is_none_or(|(b, _)| s > b) {
+                        best = Some((s, j - 1));
+                    }
+                }
+            }
+            if !eq(pattern[i], text[j]) {
+                continue;
+            }
+            let base = MATCH + bonus(&text, j);
+            if i == 0 {
+                score[i][j] = Some(base);
+                continue;
+            }
+            let consecutive = (j > 0)
+                .then(|| score[i - 1][j - 1])
+                .flatten()
+                .map(|s| (s + CONSECUTIVE, j - 1));
+            let gapped = best.map(|(s, k)| (s - GAP * j as i32, k));
+            if let Some((s, k)) = match (consecutive, gapped) {
+                (Some(c), Some(g)) => Some(if c.0 >= g.0 { c } else { g }),
+                (c, g) => c.or(g),
+            } {
+                score[i][j] = Some(base + s);
+                back[i][j] = k;
+            }
+        }
+    }
+    let (mut j, total) = (0..n)
+        .filter_map(|j| score[m - 1][j].map(|s| (j, s)))
+        .max_by_key(|&(_, s)| s)?;
+    let mut positions = vec![0; m];
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = back[i][j];
+    }
+    Some((total, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(pattern: &str, text: &str) -> i32 {
+        fuzzy_match(pattern, text).unwrap().0
+    }
+
+    #[test]
+    fn characters_must_appear_in_order() {
+        assert!(fuzzy_match("abc", "a_b_c").is_some());
+        assert!(fuzzy_match("acb", "a_b_c").is_none());
+        assert!(fuzzy_match("abcd", "abc").is_none());
+        assert_eq!(fuzzy_match("", "abc"), Some((0, Vec::new())));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(fuzzy_match("main", "src/Main.rs").is_some());
+        assert!(fuzzy_match("Main", "src/main.rs").is_none());
+        assert!(fuzzy_match("Main", "src/Main.rs").is_some());
+    }
+
+    #[test]
+    fn positions_prefer_word_starts() {
+        assert_eq!(fuzzy_match("mr", "src/main.rs").unwrap().1, [4, 9]);
+        assert_eq!(fuzzy_match("fb", "foo_bar").unwrap().1, [0, 4]);
+        assert_eq!(fuzzy_match("fb", "fooBar").unwrap().1, [0, 3]);
+    }
+
+    #[test]
+    fn ranking() {
+        // 连续匹配好于分散匹配
+        assert!(score("main", "src/main.rs") > score("main", "src/m_a_i_n.rs"));
+        // 文件名开头好于单词中间
+        assert!(score("lib", "src/lib.rs") > score("lib", "src/calibrate.rs"));
+        // 间隔越小越好
+        assert!(score("ab", "a_b") > score("ab", "a____b"));
+    }
+}
@@ -0,0 +1,143 @@
+use super::{LineState, Span, Spans, TokenKind};
+
+fn is_fence(line: &[char]) -> bool {
+    let trimmed = line
+        .iter()
+        .skip_while(|ch| **ch == ' ')
+        .take(3)
+        .collect::<String>();
+    trimmed == "```" || trimmed == "~~~"
+}
+
+pub fn tokenize(line: &[char], state: LineState) -> (Vec<Span>, LineState) {
+    let mut spans = Spans::default();
+    // 代码块整行高亮
+    if state == LineState::Fence {
+        spans.push(0, TokenKind::Code);
+        let state = if is_fence(line) {
+            LineState::Normal
+        } else {
+            LineState::Fence
+        };
+        return (spans.0, state);
+    }
+    if is_fence(line) {
+        spans.push(0, TokenKind::Code);
+        return (spans.0, LineState::Fence);
+    }
+    let n = line.len();
+    let first = line.iter().position(|&ch| ch != ' ').unwrap_or(n);
+    match line.get(first) {
+        Some('#') => {
+            spans.push(0, TokenKind::Heading);
+            return (spans.0, LineState::Normal);
+        }
+        Some('>') => {
+            spans.push(0, TokenKind::Comment);
+            return (spans.0, LineState::Normal);
+        }
+        _ => (),
+    }
+    let mut i = 0;
+    // 列表标记
+    if matches!(line.get(first), Some('-') | Some('*') | Some('+'))
+        && line.get(first + 1) == Some(&' ')
+    {
+        spans.push(0, TokenKind::Keyword);
+        i = first + 1;
+    } else {
+        let digits = line[first..]
+            .iter()
+            .take_while(|ch| ch.is_ascii_digit())
+            .count();
+        if digits > 0 && line.get(first + digits) == Some(&'.') {
+            spans.push(0, TokenKind::Keyword);
+            i = first + digits + 1;
+        }
+    }
+    while i < n {
+        let ch = line[i];
+        let closing = |delim: char, from: usize| {
+            line[from..]
+                .iter()
+                .position(|&c| c == delim)
+                .map(|p| from + p)
+        };
+        match ch {
+            '`' => {
+                spans.push(i, TokenKind::Code);
+                i = closing('`', i + 1).map(|p| p + 1).unwrap_or(n);
+            }
+            '*' | '_' if i + 1 < n && !line[i + 1].is_whitespace() => {
+                spans.push(i, TokenKind::Emphasis);
+                let from = i + 1 + usize::from(line[i + 1] == ch);
+                i = match closing(ch, from) {
+                    Some(p) if line.get(p + 1) == Some(&ch) => p + 2,
+                    Some(p) => p + 1,
+                    None => n,
+                };
+            }
+            '[' => match closing(']', i + 1) {
+                Some(p) if line.get(p + 1) == Some(&'(') => {
+                    spans.push(i, TokenKind::Link);
+                    i = closing(')', p + 1).map(|q| q + 1).unwrap_or(n);
+                }
+                _ => {
+                    spans.push(i, TokenKind::Plain);
+                    i += 1;
+                }
+            },
+            _ => {
+                spans.push(i, TokenKind::Plain);
+                i += 1;
+            }
+        }
+    }
+    (spans.0, LineState::Normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TokenKind::*;
+
+    fn spans(line: &str, state: LineState) -> (Vec<Span>, LineState) {
+        tokenize(&line.chars().collect::<Vec<_>>(), state)
+    }
+
+    #[test]
+    fn inline_tokens() {
+        assert_eq!(spans("# Title", LineState::Normal).0, [(0, Heading)]);
+        assert_eq!(spans("> quote", LineState::Normal).0, [(0, Comment)]);
+        assert_eq!(
+            spans("- a `code` and **b** [l](u)", LineState::Normal).0,
+            [
+                (0, Keyword),
+                (1, Plain),
+                (4, Code),
+                (10, Plain),
+                (15, Emphasis),
+                (20, Plain),
+                (21, Link),
+            ]
+        );
+        assert_eq!(
+            spans("12. item", LineState::Normal).0,
+            [(0, Keyword), (3, Plain)]
+        );
+    }
+
+    #[test]
+    fn fences_span_lines() {
+        let (tokens, state) = spans("```rust", LineState::Normal);
+        assert_eq!(tokens, [(0, Code)]);
+        assert_eq!(state, LineState::Fence);
+        // 代码块里的#不是标题
+        let (tokens, state) = spans("# let x", state);
+        assert_eq!(tokens, [(0, Code)]);
+        assert_eq!(state, LineState::Fence);
+        let (tokens, state) = spans("```", state);
+        assert_eq!(tokens, [(0, Code)]);
+        assert_eq!(state, LineState::Normal);
+    }
+}
@@ -0,0 +1,271 @@
+use crossterm::style::Color;
+use std::iter;
+
+use crate::text_buffer::TextBuffer;
+
+mod markdown;
+mod syntax;
+
+use syntax::Syntax;
+
+/// 一行中从某个字符下标开始的记号种类，直到下一个span开始
+pub type Span = (usize, TokenKind);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    Type,
+    Function,
+    String,
+    Number,
+    Comment,
+    Attribute,
+    Variable,
+    Heading,
+    Code,
+    Emphasis,
+    Link,
+}
+
+impl TokenKind {
+    pub fn color(self) -> Color {
+        match self {
+            TokenKind::Plain => Color::Reset,
+            TokenKind::Keyword => Color::Rgb {
+                r: 0xc6,
+                g: 0x78,
+                b: 0xdd,
+            },
+            TokenKind::Type | TokenKind::Emphasis => Color::Rgb {
+                r: 0xe5,
+                g: 0xc0,
+                b: 0x7b,
+            },
+            TokenKind::Function | TokenKind::Link => Color::Rgb {
+                r: 0x61,
+                g: 0xaf,
+                b: 0xef,
+            },
+            TokenKind::String | TokenKind::Code => Color::Rgb {
+                r: 0x98,
+                g: 0xc3,
+                b: 0x79,
+            },
+            TokenKind::Number => Color::Rgb {
+                r: 0xd1,
+                g: 0x9a,
+                b: 0x66,
+            },
+            TokenKind::Comment => Color::Rgb {
+                r: 0x7f,
+                g: 0x84,
+                b: 0x8e,
+            },
+            TokenKind::Attribute => Color::Rgb {
+                r: 0x56,
+                g: 0xb6,
+                b: 0xc2,
+            },
+            TokenKind::Variable | TokenKind::Heading => Color::Rgb {
+                r: 0xe0,
+                g: 0x6c,
+                b: 0x75,
+            },
+        }
+    }
+}
+
+/// 行首的词法状态，跨行的注释、字符串和代码块依靠它延续
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum LineState {
+    #[default]
+    Normal,
+    /// 块注释，记录嵌套深度
+    Comment(u8),
+    /// 跨行字符串，记录定界符在Syntax::strings中的下标
+    Str(u8),
+    /// markdown的代码块
+    Fence,
+}
+
+enum Language {
+    Syntax(&'static Syntax),
+    Markdown,
+}
+
+impl Language {
+    fn from_file_name(name: &str) -> Option<Self> {
+        let ext = name.rsplit_once('.').map(|(_, ext)| ext)?;
+        Some(match ext {
+            "rs" => Language::Syntax(&syntax::RUST),
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "hh" => Language::Syntax(&syntax::C),
+            "py" | "pyi" => Language::Syntax(&syntax::PYTHON),
+            "sh" | "bash" | "zsh" => Language::Syntax(&syntax::SHELL),
+            "toml" => Language::Syntax(&syntax::TOML),
+            "md" | "markdown" => Language::Markdown,
+            _ => return None,
+        })
+    }
+
+    fn tokenize(&self, line: &[char], state: LineState) -> (Vec<Span>, LineState) {
+        match self {
+            Language::Syntax(syntax) => syntax.tokenize(line, state),
+            Language::Markdown => markdown::tokenize(line, state),
+        }
+    }
+}
+
+struct LineCache {
+    start: LineState,
+    end: LineState,
+    spans: Vec<Span>,
+}
+
+/// 按文件扩展名选择语言的高亮器
+///
+/// 每行缓存行首、行尾状态和记号，编辑只作废被改动的行；
+/// 之后的行如果行首状态与缓存一致就直接复用，不再重新分析
+pub struct Highlighter {
+    language: Option<Language>,
+    lines: Vec<Option<LineCache>>,
+    /// 前checked行的行首状态都已和上一行的行尾状态核对过
+    checked: usize,
+}
+
+impl Highlighter {
+    pub fn new(file_name: &str) -> Self {
+        Highlighter {
+            language: Language::from_file_name(file_name),
+            lines: Vec::new(),
+            checked: 0,
+        }
+    }
+
+    /// 从line开始的removed + 1行被替换成了inserted + 1行
+    pub fn edited(&mut self, line: usize, removed: usize, inserted: usize) {
+        self.checked = self.checked.min(line);
+        if line >= self.lines.len() {
+            return;
+        }
+        let end = (line + removed + 1).min(self.lines.len());
+        self.lines
+            .splice(line..end, iter::repeat_with(|| None).take(inserted + 1));
+    }
+
+    /// 第line行的记号
+    pub fn spans(&mut self, buffer: &TextBuffer, line: usize) -> Vec<Span> {
+        let Some(language) = &self.language else {
+            return vec![(0, TokenKind::Plain)];
+        };
+        self.lines.resize_with(buffer.line_count(), || None);
+        if line >= self.lines.len() {
+            return Vec::new();
+        }
+        for i in self.checked..=line {
+            let start = if i == 0 {
+                LineState::Normal
+            } else {
+                self.lines[i - 1].as_ref().unwrap().end
+            };
+            if self.lines[i].as_ref().is_some_and(|c| c.start == start) {
+                continue;
+            }
+            let chars = buffer
+                .line(i)
+                .map(|l| l.chars().collect::<Vec<_>>())
+                .unwrap_or_default();
+            let (spans, end) = language.tokenize(&chars, start);
+            self.lines[i] = Some(LineCache { start, end, spans });
+        }
+        self.checked = self.checked.max(line + 1);
+        self.lines[line].as_ref().unwrap().spans.clone()
+    }
+}
+
+/// 逐个追加span，相邻同类的合并
+#[derive(Default)]
+struct Spans(Vec<Span>);
+
+impl Spans {
+    fn push(&mut self, start: usize, kind: TokenKind) {
+        if self.0.last().is_some_and(|last| last.0 == start) {
+            self.0.pop();
+        }
+        if self.0.last().is_some_and(|last| last.1 == kind) {
+            return;
+        }
+        self.0.push((start, kind));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> TextBuffer {
+        TextBuffer::from_reader(text.as_bytes()).unwrap()
+    }
+
+    /// 把line行的缓存换成一个分析不出来的记号，用来判断这行有没有被重新分析
+    fn mark(highlighter: &mut Highlighter, line: usize) {
+        highlighter.lines[line].as_mut().unwrap().spans = vec![(0, TokenKind::Link)];
+    }
+
+    #[test]
+    fn unknown_language_is_plain() {
+        let mut highlighter = Highlighter::new("notes.txt");
+        assert_eq!(
+            highlighter.spans(&buffer("fn x\n"), 0),
+            [(0, TokenKind::Plain)]
+        );
+    }
+
+    #[test]
+    fn edit_in_comment_retokenizes_until_state_converges() {
+        let mut buf = buffer("/* start\nmiddle\nend */ x\nlet y\nlet z\n");
+        let mut highlighter = Highlighter::new("a.rs");
+        assert_eq!(highlighter.spans(&buf, 1), [(0, TokenKind::Comment)]);
+        assert_eq!(
+            highlighter.spans(&buf, 4),
+            [(0, TokenKind::Keyword), (3, TokenKind::Plain)]
+        );
+        for line in 2..5 {
+            mark(&mut highlighter, line);
+        }
+
+        // 在注释中间提前结束注释，第2行的行首状态变了，之后又回到普通状态
+        buf.splice((1, 3), (1, 3), &['*', '/']);
+        highlighter.edited(1, 0, 0);
+        assert_eq!(
+            highlighter.spans(&buf, 1),
+            [(0, TokenKind::Comment), (5, TokenKind::Plain)]
+        );
+        assert_eq!(highlighter.spans(&buf, 2), [(0, TokenKind::Plain)]);
+        assert_eq!(highlighter.spans(&buf, 4), [(0, TokenKind::Link)]);
+        assert_eq!(highlighter.spans(&buf, 3), [(0, TokenKind::Link)]);
+    }
+
+    #[test]
+    fn edit_opening_string_retokenizes_following_lines() {
+        let mut buf = buffer("x = 1\ny = 2\nz = 3\n");
+        let mut highlighter = Highlighter::new("a.py");
+        assert_eq!(highlighter.spans(&buf, 2)[1], (4, TokenKind::Number));
+        mark(&mut highlighter, 1);
+        mark(&mut highlighter, 2);
+
+        buf.splice((0, 4), (0, 4), &['"', '"', '"']);
+        highlighter.edited(0, 0, 0);
+        assert_eq!(highlighter.spans(&buf, 2), [(0, TokenKind::String)]);
+        assert_eq!(highlighter.spans(&buf, 1), [(0, TokenKind::String)]);
+
+        // 删掉跨行的内容后，被接起来的行和之后的行都按新的状态分析
+        buf.splice((0, 7), (1, 2), &[]);
+        highlighter.edited(0, 1, 0);
+        assert_eq!(
+            highlighter.spans(&buf, 0),
+            [(0, TokenKind::Plain), (4, TokenKind::String)]
+        );
+        assert_eq!(highlighter.spans(&buf, 1), [(0, TokenKind::String)]);
+    }
+}
@@ -0,0 +1,564 @@
+use super::{LineState, Span, Spans, TokenKind};
+
+/// 类C语言的词法描述，由同一个分析器处理
+pub struct Syntax {
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    constants: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    nested_comments: bool,
+    /// 字符串定界符和是否可以跨行，较长的定界符排在前面
+    strings: &'static [(&'static str, bool)],
+    /// 大写字母开头的标识符视为类型
+    uppercase_types: bool,
+    /// rust的生命周期和字符字面量
+    lifetimes: bool,
+    /// rust的#[...]
+    attributes: bool,
+    /// C的预处理行
+    preprocessor: bool,
+    /// python的@装饰器
+    decorators: bool,
+    /// shell的$变量，并且#只在词首才开始注释
+    variables: bool,
+    /// toml的[表头]和键
+    tables: bool,
+}
+
+pub static RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "type",
+        "unsafe", "use", "where", "while", "yield",
+    ],
+    types: &[
+        "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32",
+        "i64", "i128", "isize", "f32", "f64",
+    ],
+    constants: &["true", "false", "None", "Some", "Ok", "Err"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    nested_comments: true,
+    strings: &[("\"", true)],
+    uppercase_types: true,
+    lifetimes: true,
+    attributes: true,
+    preprocessor: false,
+    decorators: false,
+    variables: false,
+    tables: false,
+};
+
+pub static C: Syntax = Syntax {
+    keywords: &[
+        "auto",
+        "break",
+        "case",
+        "class",
+        "const",
+        "constexpr",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "enum",
+        "extern",
+        "for",
+        "goto",
+        "if",
+        "inline",
+        "namespace",
+        "new",
+        "private",
+        "protected",
+        "public",
+        "register",
+        "return",
+        "sizeof",
+        "static",
+        "struct",
+        "switch",
+        "template",
+        "this",
+        "typedef",
+        "typename",
+        "union",
+        "using",
+        "virtual",
+        "volatile",
+        "while",
+    ],
+    types: &[
+        "bool", "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void",
+        "size_t", "ssize_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t",
+        "uint32_t", "uint64_t",
+    ],
+    constants: &["true", "false", "NULL", "nullptr"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    nested_comments: false,
+    strings: &[("\"", false), ("'", false)],
+    uppercase_types: false,
+    lifetimes: false,
+    attributes: false,
+    preprocessor: true,
+    decorators: false,
+    variables: false,
+    tables: false,
+};
+
+pub static PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+        "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with",
+        "yield", "self",
+    ],
+    types: &[
+        "int", "float", "str", "bytes", "bool", "list", "dict", "set", "tuple", "object",
+    ],
+    constants: &["True", "False", "None"],
+    line_comments: &["#"],
+    block_comment: None,
+    nested_comments: false,
+    strings: &[("\"\"\"", true), ("'''", true), ("\"", false), ("'", false)],
+    uppercase_types: true,
+    lifetimes: false,
+    attributes: false,
+    preprocessor: false,
+    decorators: true,
+    variables: false,
+    tables: false,
+};
+
+pub static SHELL: Syntax = Syntax {
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "in", "function", "return", "local", "export", "readonly", "shift", "exit", "break",
+        "continue", "source",
+    ],
+    types: &[],
+    constants: &["true", "false"],
+    line_comments: &["#"],
+    block_comment: None,
+    nested_comments: false,
+    strings: &[("\"", true), ("'", true)],
+    uppercase_types: false,
+    lifetimes: false,
+    attributes: false,
+    preprocessor: false,
+    decorators: false,
+    variables: true,
+    tables: false,
+};
+
+pub static TOML: Syntax = Syntax {
+    keywords: &[],
+    types: &[],
+    constants: &["true", "false", "inf", "nan"],
+    line_comments: &["#"],
+    block_comment: None,
+    nested_comments: false,
+    strings: &[("\"\"\"", true), ("'''", true), ("\"", false), ("'", false)],
+    uppercase_types: false,
+    lifetimes: false,
+    attributes: false,
+    preprocessor: false,
+    decorators: false,
+    variables: false,
+    tables: true,
+};
+
+fn starts_with(line: &[char], at: usize, pat: &str) -> bool {
+    (at..)
+        .zip(pat.chars())
+        .all(|(i, p)| line.get(i) == Some(&p))
+}
+
+fn is_ident_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
+}
+
+fn is_ident(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// 从at开始跳过标识符字符
+fn skip_ident(line: &[char], mut at: usize) -> usize {
+    while at < line.len() && is_ident(line[at]) {
+        at += 1;
+    }
+    at
+}
+
+impl Syntax {
+    pub fn tokenize(&self, line: &[char], state: LineState) -> (Vec<Span>, LineState) {
+        let mut spans = Spans::default();
+        let mut state = state;
+        let n = line.len();
+        let mut i = 0;
+        if state == LineState::Normal {
+            i = self.line_prefix(line, &mut spans);
+        }
+        while i < n {
+            match state {
+                LineState::Comment(depth) => {
+                    spans.push(i, TokenKind::Comment);
+                    let (open, close) = self.block_comment.unwrap();
+                    let mut depth = depth;
+                    while i < n && depth > 0 {
+                        if starts_with(line, i, close) {
+                            i += close.chars().count();
+                            depth -= 1;
+                        } else if self.nested_comments && starts_with(line, i, open) {
+                            i += open.chars().count();
+                            depth = depth.saturating_add(1);
+                        } else {
+                            i += 1;
+                        }
+                    }
+                    state = if depth == 0 {
+                        LineState::Normal
+                    } else {
+                        LineState::Comment(depth)
+                    };
+                }
+                LineState::Str(k) => {
+                    spans.push(i, TokenKind::String);
+                    let (delim, multiline) = self.strings[k as usize];
+                    let mut closed = false;
+                    while i < n {
+                        if line[i] == '\\' {
+                            i += 2;
+                        } else if starts_with(line, i, delim) {
+                            i += delim.chars().count();
+                            closed = true;
+                            break;
+                        } else {
+                            i += 1;
+                        }
+                    }
+                    i = i.min(n);
+                    state = if closed || !multiline {
+                        LineState::Normal
+                    } else {
+                        LineState::Str(k)
+                    };
+                }
+                LineState::Normal | LineState::Fence => {
+                    state = LineState::Normal;
+                    i = self.normal(line, i, &mut spans, &mut state);
+                }
+            }
+        }
+        (spans.0, state)
+    }
+
+    /// 处理只能出现在行首的结构，返回之后开始分析的位置
+    fn line_prefix(&self, line: &[char], spans: &mut Spans) -> usize {
+        let first = line
+            .iter()
+            .position(|ch| !ch.is_whitespace())
+            .unwrap_or(line.len());
+        if self.preprocessor && line.get(first) == Some(&'#') {
+            spans.push(0, TokenKind::Attribute);
+            return line.len();
+        }
+        if self.tables {
+            if line.get(first) == Some(&'[') {
+                spans.push(0, TokenKind::Heading);
+                let end = line[first..]
+                    .iter()
+                    .position(|&ch| ch == ']')
+                    .map(|p| first + p + 1)
+                    .unwrap_or(line.len());
+                return end;
+            }
+            if let Some(eq) = line.iter().position(|&ch| ch == '=') {
+                let key = &line[first..eq];
+                if !key.is_empty() && key.iter().all(|&ch| is_ident(ch) || "-.\"' ".contains(ch)) {
+                    spans.push(0, TokenKind::Variable);
+                    spans.push(eq, TokenKind::Plain);
+                    return eq;
+                }
+            }
+        }
+        0
+    }
+
+    /// 分析普通状态下从i开始的一个记号，返回下一个记号的开始位置
+    fn normal(&self, line: &[char], i: usize, spans: &mut Spans, state: &mut LineState) -> usize {
+        let n = line.len();
+        let ch = line[i];
+        for comment in self.line_comments {
+            let at_word = !self.variables || i == 0 || line[i - 1].is_whitespace();
+            if at_word && starts_with(line, i, comment) {
+                spans.push(i, TokenKind::Comment);
+                return n;
+            }
+        }
+        if let Some((open, _)) = self.block_comment {
+            if starts_with(line, i, open) {
+                spans.push(i, TokenKind::Comment);
+                *state = LineState::Comment(1);
+                return i + open.chars().count();
+            }
+        }
+        for (k, (delim, _)) in self.strings.iter().enumerate() {
+            if starts_with(line, i, delim) {
+                spans.push(i, TokenKind::String);
+                *state = LineState::Str(k as u8);
+                return i + delim.chars().count();
+            }
+        }
+        if self.lifetimes && ch == '\'' {
+            // 'a'、'\n'是字符，'a是生命周期
+            if line.get(i + 1) == Some(&'\\') {
+                spans.push(i, TokenKind::String);
+                let end = line[i + 2..]
+                    .iter()
+                    .position(|&ch| ch == '\'')
+                    .map(|p| i + 2 + p + 1)
+                    .unwrap_or(n);
+                return end;
+            }
+            if line.get(i + 2) == Some(&'\'') {
+                spans.push(i, TokenKind::String);
+                return i + 3;
+            }
+            spans.push(i, TokenKind::Type);
+            return skip_ident(line, i + 1);
+        }
+        if self.variables && ch == '$' {
+            spans.push(i, TokenKind::Variable);
+            return match line.get(i + 1) {
+                Some('{') => line[i..]
+                    .iter()
+                    .position(|&ch| ch == '}')
+                    .map(|p| i + p + 1)
+                    .unwrap_or(n),
+                Some(&c) if is_ident(c) => skip_ident(line, i + 1),
+                Some(_) => i + 2,
+                None => n,
+            };
+        }
+        if self.decorators && ch == '@' {
+            spans.push(i, TokenKind::Attribute);
+            let mut end = i + 1;
+            while end < n && (is_ident(line[end]) || line[end] == '.') {
+                end += 1;
+            }
+            return end;
+        }
+        if self.attributes && ch == '#' && matches!(line.get(i + 1), Some('[') | Some('!')) {
+            spans.push(i, TokenKind::Attribute);
+            let mut depth = 0;
+            for (j, &c) in line.iter().enumerate().skip(i) {
+                if c == '[' {
+                    depth += 1;
+                } else if c == ']' {
+                    depth -= 1;
+                    if depth == 0 {
+                        return j + 1;
+                    }
+                }
+            }
+            return n;
+        }
+        if ch.is_ascii_digit() {
+            spans.push(i, TokenKind::Number);
+            let mut end = i + 1;
+            while end < n && (is_ident(line[end]) || line[end] == '.') {
+                end += 1;
+            }
+            return end;
+        }
+        if is_ident_start(ch) {
+            let end = skip_ident(line, i);
+            let word = line[i..end].iter().collect::<String>();
+            let next = line[end..].iter().find(|ch| !ch.is_whitespace());
+            let kind = if self.keywords.contains(&word.as_str()) {
+                TokenKind::Keyword
+            } else if self.types.contains(&word.as_str()) {
+                TokenKind::Type
+            } else if self.constants.contains(&word.as_str()) {
+                TokenKind::Number
+            } else if next == Some(&'(') || (self.lifetimes && line.get(end) == Some(&'!')) {
+                TokenKind::Function
+            } else if self.uppercase_types && ch.is_uppercase() {
+                TokenKind::Type
+            } else {
+                TokenKind::Plain
+            };
+            spans.push(i, kind);
+            return end;
+        }
+        spans.push(i, TokenKind::Plain);
+        i + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TokenKind::*;
+
+    fn tokenize(syntax: &Syntax, line: &str, state: LineState) -> (Vec<Span>, LineState) {
+        syntax.tokenize(&line.chars().collect::<Vec<_>>(), state)
+    }
+
+    fn spans(syntax: &Syntax, line: &str) -> Vec<Span> {
+        let (spans, state) = tokenize(syntax, line, LineState::Normal);
+        assert_eq!(state, LineState::Normal, "{line}");
+        spans
+    }
+
+    #[test]
+    fn rust_tokens() {
+        assert_eq!(
+            spans(&RUST, "let x: u32 = foo(1); // hi"),
+            [
+                (0, Keyword),
+                (3, Plain),
+                (7, Type),
+                (10, Plain),
+                (13, Function),
+                (16, Plain),
+                (17, Number),
+                (18, Plain),
+                (21, Comment),
+            ]
+        );
+        assert_eq!(
+            spans(&RUST, "#[derive(Debug)] struct A<'a>;"),
+            [
+                (0, Attribute),
+                (16, Plain),
+                (17, Keyword),
+                (23, Plain),
+                (24, Type),
+                (25, Plain),
+                (26, Type),
+                (28, Plain),
+            ]
+        );
+        assert_eq!(spans(&RUST, "'\\n'"), [(0, String)]);
+    }
+
+    #[test]
+    fn rust_nested_comments_and_strings_span_lines() {
+        let (spans, state) = tokenize(&RUST, "a /* b /* c */", LineState::Normal);
+        assert_eq!(spans, [(0, Plain), (2, Comment)]);
+        assert_eq!(state, LineState::Comment(1));
+        let (spans, state) = tokenize(&RUST, "d */ e", state);
+        assert_eq!(spans, [(0, Comment), (4, Plain)]);
+        assert_eq!(state, LineState::Normal);
+
+        let (spans, state) = tokenize(&RUST, "let s = \"ab", LineState::Normal);
+        assert_eq!(spans, [(0, Keyword), (3, Plain), (8, String)]);
+        assert_eq!(state, LineState::Str(0));
+        let (spans, state) = tokenize(&RUST, "c\\\"d\";", state);
+        assert_eq!(spans, [(0, String), (5, Plain)]);
+        assert_eq!(state, LineState::Normal);
+    }
+
+    #[test]
+    fn c_tokens() {
+        assert_eq!(spans(&C, "  #include <stdio.h>"), [(0, Attribute)]);
+        assert_eq!(
+            spans(&C, "int main(void) { return 'a'; }"),
+            [
+                (0, Type),
+                (3, Plain),
+                (4, Function),
+                (8, Plain),
+                (9, Type),
+                (13, Plain),
+                (17, Keyword),
+                (23, Plain),
+                (24, String),
+                (27, Plain),
+            ]
+        );
+        // 块注释不嵌套，字符串不跨行
+        assert_eq!(spans(&C, "/* a /* b */ c"), [(0, Comment), (12, Plain)]);
+        assert_eq!(spans(&C, "\"ab"), [(0, String)]);
+    }
+
+    #[test]
+    fn python_tokens() {
+        assert_eq!(
+            spans(&PYTHON, "@app.route(\"/\")"),
+            [(0, Attribute), (10, Plain), (11, String), (14, Plain)]
+        );
+        assert_eq!(
+            spans(&PYTHON, "def f(self): # x"),
+            [
+                (0, Keyword),
+                (3, Plain),
+                (4, Function),
+                (5, Plain),
+                (6, Keyword),
+                (10, Plain),
+                (13, Comment),
+            ]
+        );
+        let (spans, state) = tokenize(&PYTHON, "x = \"\"\"doc", LineState::Normal);
+        assert_eq!(spans, [(0, Plain), (4, String)]);
+        assert_eq!(state, LineState::Str(0));
+        let (spans, state) = tokenize(&PYTHON, "end\"\"\" + 1", state);
+        assert_eq!(spans, [(0, String), (6, Plain), (9, Number)]);
+        assert_eq!(state, LineState::Normal);
+    }
+
+    #[test]
+    fn shell_tokens() {
+        // 词中间的#不是注释
+        assert_eq!(
+            spans(&SHELL, "echo $HOME ${X}#no # yes"),
+            [
+                (0, Plain),
+                (5, Variable),
+                (10, Plain),
+                (11, Variable),
+                (15, Plain),
+                (19, Comment),
+            ]
+        );
+        assert_eq!(
+            spans(&SHELL, "if true; then"),
+            [
+                (0, Keyword),
+                (2, Plain),
+                (3, Number),
+                (7, Plain),
+                (9, Keyword)
+            ]
+        );
+        let (spans, state) = tokenize(&SHELL, "echo 'a", LineState::Normal);
+        assert_eq!(spans, [(0, Plain), (5, String)]);
+        assert_eq!(state, LineState::Str(1));
+    }
+
+    #[test]
+    fn toml_tokens() {
+        assert_eq!(spans(&TOML, "[package]"), [(0, Heading)]);
+        assert_eq!(
+            spans(&TOML, "name = \"x\" # c"),
+            [
+                (0, Variable),
+                (5, Plain),
+                (7, String),
+                (10, Plain),
+                (11, Comment)
+            ]
+        );
+        assert_eq!(
+            spans(&TOML, "enabled = true"),
+            [(0, Variable), (8, Plain), (10, Number)]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::time::{Duration, Instant};
+
+/// 同一组连续输入之间允许的最长停顿
+const BURST_GAP: Duration = Duration::from_millis(1000);
+
+/// 一次替换：在start处删除removed，再插入inserted
+///
+/// 位置都是(行号, 字符下标)，文本中的'\n'表示换行
+#[derive(Clone)]
+pub struct Change {
+    pub start: (usize, usize),
+    pub removed: Vec<char>,
+    pub inserted: Vec<char>,
+}
+
+impl Change {
+    pub fn inserted_end(&self) -> (usize, usize) {
+        end_of(self.start, &self.inserted)
+    }
+
+    pub fn removed_end(&self) -> (usize, usize) {
+        end_of(self.start, &self.removed)
+    }
+
+    /// 键入的单个字符
+    fn typed(&self) -> Option<char> {
+        match (self.removed.as_slice(), self.inserted.as_slice()) {
+            ([], &[ch]) if ch != '\n' => Some(ch),
+            _ => None,
+        }
+    }
+
+    /// 退格或删除的单个字符
+    fn erased(&self) -> Option<char> {
+        match (self.removed.as_slice(), self.inserted.as_slice()) {
+            (&[ch], []) if ch != '\n' => Some(ch),
+            _ => None,
+        }
+    }
+}
+
+/// 从start开始写入text后的结束位置
+pub fn end_of(start: (usize, usize), text: &[char]) -> (usize, usize) {
+    let mut end = start;
+    for &ch in text {
+        if ch == '\n' {
+            end = (end.0 + 1, 0);
+        } else {
+            end.1 += 1;
+        }
+    }
+    end
+}
+
+struct Group {
+    id: usize,
+    changes: Vec<Change>,
+}
+
+/// 撤销/重做栈，一次撤销回退一组编辑
+///
+/// 连续键入同一个单词、连续退格或删除会并入同一组；
+/// 光标跳转、停顿过久、换行和粘贴之类的整段替换都会开始新的一组
+pub struct History {
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    next_id: usize,
+    sealed: bool,
+    /// 批量编辑中，所有编辑并入同一组
+    batch: bool,
+    last_edit: Option<Instant>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            next_id: 1,
+            sealed: true,
+            batch: false,
+            last_edit: None,
+        }
+    }
+
+    pub fn record(&mut self, change: Change) {
+        self.redo.clear();
+        let now = Instant::now();
+        let in_burst = self
+            .last_edit
+            .is_some_and(|t| now.duration_since(t) < BURST_GAP);
+        let last = self
+            .undo
+            .last_mut()
+            .filter(|_| !self.sealed && (in_burst || self.batch));
+        match last {
+            Some(group) if self.batch || continues(group.changes.last().unwrap(), &change) => {
+                group.changes.push(change);
+            }
+            _ => {
+                self.undo.push(Group {
+                    id: self.next_id,
+                    changes: vec![change],
+                });
+                self.next_id += 1;
+            }
+        }
+        self.sealed = false;
+        self.last_edit = Some(now);
+    }
+
+    /// 之后的编辑不再并入当前组
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    /// 开始一次批量编辑，例如全部替换，之后到end_batch为止的编辑一起撤销
+    pub fn begin_batch(&mut self) {
+        self.sealed = true;
+        self.batch = true;
+    }
+
+    pub fn end_batch(&mut self) {
+        self.sealed = true;
+        self.batch = false;
+    }
+
+    /// 弹出最近一组编辑，按记录顺序返回
+    pub fn undo(&mut self) -> Option<Vec<Change>> {
+        let group = self.undo.pop()?;
+        let changes = group.changes.clone();
+        self.redo.push(group);
+        self.sealed = true;
+        Some(changes)
+    }
+
+    pub fn redo(&mut self) -> Option<Vec<Change>> {
+        let group = self.redo.pop()?;
+        let changes = group.changes.clone();
+        self.undo.push(group);
+        self.sealed = true;
+        Some(changes)
+    }
+
+    /// 标识当前内容的版本，用于判断是否与保存时一致
+    pub fn state(&self) -> usize {
+        self.undo.last().map(|g| g.id).unwrap_or(0)
+    }
+}
+
+fn continues(last: &Change, next: &Change) -> bool {
+    if let (Some(prev), Some(ch)) = (last.typed(), next.typed()) {
+        // 空白之后开始一个新单词时断开
+        next.start == last.inserted_end() && !(prev.is_whitespace() && !ch.is_whitespace())
+    } else if let (Some(_), Some(_)) = (last.erased(), next.erased()) {
+        // 向前删除起点不变，退格的终点是上一次的起点
+        next.start == last.start || next.removed_end() == last.start
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(line: usize, col: usize, ch: char) -> Change {
+        Change {
+            start: (line, col),
+            removed: Vec::new(),
+            inserted: vec![ch],
+        }
+    }
+
+    fn erased(line: usize, col: usize, ch: char) -> Change {
+        Change {
+            start: (line, col),
+            removed: vec![ch],
+            inserted: Vec::new(),
+        }
+    }
+
+    fn type_str(history: &mut History, line: usize, col: usize, text: &str) {
+        for (i, ch) in text.chars().enumerate() {
+            history.record(typed(line, col + i, ch));
+        }
+    }
+
+    #[test]
+    fn typing_a_word_is_one_group() {
+        let mut history = History::new();
+        type_str(&mut history, 0, 0, "hello");
+        assert_eq!(history.undo().unwrap().len(), 5);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn a_new_word_starts_a_new_group() {
+        let mut history = History::new();
+        type_str(&mut history, 0, 0, "ab cd");
+        let changes = history.undo().unwrap();
+        assert_eq!(
+            changes.iter().map(|c| c.inserted[0]).collect::<String>(),
+            "cd"
+        );
+        assert_eq!(history.undo().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn newline_and_cursor_jump_break_groups() {
+        let mut history = History::new();
+        type_str(&mut history, 0, 0, "ab");
+        history.record(typed(0, 2, '\n'));
+        type_str(&mut history, 1, 0, "c");
+        // 不在上一次输入的末尾
+        history.record(typed(5, 0, 'd'));
+        assert_eq!(history.undo().unwrap()[0].inserted, vec!['d']);
+        assert_eq!(history.undo().unwrap()[0].inserted, vec!['c']);
+        assert_eq!(history.undo().unwrap()[0].inserted, vec!['\n']);
+        assert_eq!(history.undo().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn backspace_and_delete_coalesce() {
+        let mut history = History::new();
+        history.record(erased(0, 4, 'd'));
+        history.record(erased(0, 3, 'c'));
+        history.record(erased(0, 2, 'b'));
+        assert_eq!(history.undo().unwrap().len(), 3);
+
+        history.record(erased(0, 2, 'x'));
+        history.record(erased(0, 2, 'y'));
+        assert_eq!(history.undo().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn seal_starts_a_new_group() {
+        let mut history = History::new();
+        type_str(&mut history, 0, 0, "ab");
+        history.seal();
+        type_str(&mut history, 0, 2, "cd");
+        assert_eq!(history.undo().unwrap().len(), 2);
+        assert_eq!(history.undo().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn batch_is_one_group() {
+        let mut history = History::new();
+        history.begin_batch();
+        history.record(typed(3, 0, 'x'));
+        history.record(erased(7, 1, 'y'));
+        history.end_batch();
+        history.record(typed(3, 1, 'z'));
+        assert_eq!(history.undo().unwrap().len(), 1);
+        assert_eq!(history.undo().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn redo_replays_and_new_edit_clears_it() {
+        let mut history = History::new();
+        type_str(&mut history, 0, 0, "a");
+        let saved = history.state();
+        history.seal();
+        type_str(&mut history, 0, 1, "b");
+        assert_ne!(history.state(), saved);
+
+        history.undo().unwrap();
+        assert_eq!(history.state(), saved);
+        assert_eq!(history.redo().unwrap()[0].inserted, vec!['b']);
+        history.undo().unwrap();
+
+        type_str(&mut history, 0, 1, "c");
+        assert!(history.redo().is_none());
+        assert_ne!(history.state(), saved);
+    }
+
+    #[test]
+    fn end_of_multiline_text() {
+        assert_eq!(end_of((2, 3), &['a', '\n', 'b', 'c']), (3, 2));
+        assert_eq!(end_of((2, 3), &[]), (2, 3));
+    }
+}
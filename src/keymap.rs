@@ -0,0 +1,546 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{collections::HashMap, fmt, fs, path::PathBuf};
+
+/// 默认的按键绑定，格式与用户的keys.toml相同
+const DEFAULTS: &str = r#"
+[global]
+"ctrl+d" = "IDE: Quit"
+"f1" = "IDE: Command Palette"
+"ctrl+up" = "Focus: Up"
+"ctrl+down" = "Focus: Down"
+"ctrl+left" = "Focus: Left"
+"ctrl+right" = "Focus: Right"
+"ctrl+a" = "ProjectViewer: Save All Files"
+"ctrl+f" = "Focus: Search Panel"
+"ctrl+p" = "File: Go to File"
+"alt+z" = "Layout: Toggle Zoom"
+"alt+=" = "Layout: Grow Pane Height"
+"alt+-" = "Layout: Shrink Pane Height"
+"alt+." = "Layout: Grow Pane Width"
+"alt+," = "Layout: Shrink Pane Width"
+
+[editor]
+"ctrl+s" = "Editor: Save"
+"ctrl+z" = "Editor: Undo"
+"ctrl+y" = "Editor: Redo"
+"ctrl+v" = "Editor: Paste"
+"e" = "Editor: Edit Mode"
+"/" = "Editor: Find"
+"r" = "Editor: Find and Replace"
+"n" = "Editor: Find Next"
+"N" = "Editor: Find Previous"
+"K" = "Editor: Show Hover"
+"d" = "Editor: Go to Definition"
+"u" = "Editor: Find References"
+"R" = "Editor: Rename Symbol"
+
+[editor_edit]
+"ctrl+s" = "Editor: Save"
+"ctrl+z" = "Editor: Undo"
+"ctrl+y" = "Editor: Redo"
+"ctrl+v" = "Editor: Paste"
+"ctrl+space" = "Editor: Trigger Completion"
+"esc" = "Editor: Command Mode"
+
+[project_viewer]
+"enter" = "ProjectViewer: Open Selected"
+
+[terminal]
+"alt+t" = "Terminal: New Session"
+"alt+w" = "Terminal: Close Session"
+"alt+r" = "Terminal: Rename Session"
+"alt+left" = "Terminal: Previous Session"
+"alt+right" = "Terminal: Next Session"
+"alt+v" = "Terminal: Copy Mode"
+
+[problems]
+"b" = "Problems: Cargo Build"
+"t" = "Problems: Cargo Test"
+"c" = "Problems: Cargo Clippy"
+"esc" = "Problems: Stop"
+
+[search_panel]
+"alt+c" = "Search: Toggle Case Sensitive"
+"alt+w" = "Search: Toggle Whole Word"
+"alt+r" = "Search: Toggle Regex"
+"#;
+
+/// keys.toml中可以使用的节，global在任何组件有焦点时都生效
+const CONTEXTS: [&str; 7] = [
+    "global",
+    "editor",
+    "editor_edit",
+    "project_viewer",
+    "terminal",
+    "problems",
+    "search_panel",
+];
+
+/// 组件要原样收到Ctrl加字符键的上下文，例如终端中的Ctrl-D和Ctrl-C
+///
+/// 这些上下文有焦点时，global中这样的绑定不生效，功能键、Ctrl+方向键和Alt组合键仍然可用
+const RAW_CONTEXTS: [&str; 1] = ["terminal"];
+
+/// 一次按键，字符键的Shift体现在大小写中
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Chord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mask = match code {
+            KeyCode::Char(_) => KeyModifiers::CONTROL | KeyModifiers::ALT,
+            _ => KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT,
+        };
+        Chord {
+            code,
+            modifiers: modifiers & mask,
+        }
+    }
+
+    /// 解析"ctrl+d"、"alt+left"、"K"这样的按键
+    fn parse(s: &str) -> Result<Self, String> {
+        // 最后一个+之后是键名，"ctrl++"表示Ctrl和+
+        let (modifiers, key) = match s.strip_suffix('+') {
+            Some(rest) if rest.is_empty() || rest.ends_with('+') => (rest, "+"),
+            _ => s.rsplit_once('+').unwrap_or(("", s)),
+        };
+        let mut mods = KeyModifiers::NONE;
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            mods |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier `{}`", modifier)),
+            };
+        }
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(ch), None) if mods.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(ch.to_ascii_uppercase())
+            }
+            (Some(ch), None) => KeyCode::Char(ch),
+            _ => match key.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key `{}`", key)),
+                },
+            },
+        };
+        Ok(Chord::new(code, mods))
+    }
+
+    /// Ctrl加字符键，在终端中会变成控制字符
+    fn is_control_char(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_)) && self.modifiers.contains(KeyModifiers::CONTROL)
+    }
+}
+
+impl From<&KeyEvent> for Chord {
+    fn from(key: &KeyEvent) -> Self {
+        Chord::new(key.code, key.modifiers)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl+"),
+            (KeyModifiers::ALT, "alt+"),
+            (KeyModifiers::SHIFT, "shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(ch) => write!(f, "{}", ch),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::Esc => f.write_str("esc"),
+            KeyCode::PageUp => f.write_str("pageup"),
+            KeyCode::PageDown => f.write_str("pagedown"),
+            code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+        }
+    }
+}
+
+/// 按下一个键后的结果
+pub enum Lookup {
+    /// 组成了完整的按键序列，执行这个命令
+    Run(String),
+    /// 是某个按键序列的开头，等待下一个键
+    Pending,
+    /// 没有绑定，这些键照常交给组件
+    Unbound(Vec<KeyEvent>),
+}
+
+/// 每个上下文中按键序列到命令名的映射
+pub struct Keymap {
+    bindings: HashMap<&'static str, Vec<(Vec<Chord>, String)>>,
+    /// 已经按下、还没有组成完整按键序列的键
+    pending: Vec<KeyEvent>,
+}
+
+impl Keymap {
+    /// 载入默认绑定，再用keys.toml中的绑定覆盖
+    ///
+    /// commands是所有可以绑定的命令，返回的错误信息指出配置中被忽略的项
+    pub fn load(commands: &[String]) -> (Self, Vec<String>) {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+            pending: Vec::new(),
+        };
+        let mut errors = Vec::new();
+        keymap.merge(DEFAULTS, commands, &mut errors);
+        if let Some(path) = config_path() {
+            match fs::read_to_string(&path) {
+                Ok(text) => {
+                    let mut file_errors = Vec::new();
+                    keymap.merge(&text, commands, &mut file_errors);
+                    errors.extend(
+                        file_errors
+                            .into_iter()
+                            .map(|e| format!("{}: {}", path.display(), e)),
+                    );
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        (keymap, errors)
+    }
+
+    /// 把text中的绑定加进来，命令为空字符串时取消这个按键序列的绑定
+    fn merge(&mut self, text: &str, commands: &[String], errors: &mut Vec<String>) {
+        let table = match text.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(e) => {
+                errors.push(e.message().to_string());
+                return;
+            }
+        };
+        for (context, bindings) in table {
+            let Some(&context) = CONTEXTS.iter().find(|&&c| c == context) else {
+                errors.push(format!("unknown section [{}]", context));
+                continue;
+            };
+            let Some(bindings) = bindings.as_table() else {
+                errors.push(format!("[{}] is not a table", context));
+                continue;
+            };
+            for (keys, command) in bindings {
+                let Some(command) = command.as_str() else {
+                    errors.push(format!(
+                        "[{}] \"{}\": command is not a string",
+                        context, keys
+                    ));
+                    continue;
+                };
+                if !command.is_empty() && !commands.iter().any(|c| c == command) {
+                    errors.push(format!(
+                        "[{}] \"{}\": unknown command `{}`",
+                        context, keys, command
+                    ));
+                    continue;
+                }
+                let sequence = keys
+                    .split_whitespace()
+                    .map(Chord::parse)
+                    .collect::<Result<Vec<_>, _>>();
+                match sequence {
+                    Ok(sequence) if !sequence.is_empty() => {
+                        let bindings = self.bindings.entry(context).or_default();
+                        bindings.retain(|(s, _)| *s != sequence);
+                        if !command.is_empty() {
+                            bindings.push((sequence, command.to_string()));
+                        }
+                    }
+                    Ok(_) => errors.push(format!("[{}]: empty key sequence", context)),
+                    Err(e) => errors.push(format!("[{}] \"{}\": {}", context, keys, e)),
+                }
+            }
+        }
+    }
+
+    /// 在contexts中查找按下key后的按键序列，前面的上下文优先
+    ///
+    /// contexts中有RAW_CONTEXTS时，global中以Ctrl加字符键开头的绑定被跳过
+    pub fn press(&mut self, key: KeyEvent, contexts: &[&str]) -> Lookup {
+        self.pending.push(key);
+        let sequence = self.pending.iter().map(Chord::from).collect::<Vec<_>>();
+        let raw = contexts
+            .iter()
+            .any(|context| RAW_CONTEXTS.contains(context));
+        let bindings = contexts
+            .iter()
+            .filter_map(|&context| Some((context, self.bindings.get(context)?)))
+            .flat_map(|(context, bindings)| {
+                bindings.iter().filter(move |(keys, _)| {
+                    !(raw && context == "global" && keys[0].is_control_char())
+                })
+            });
+        let mut prefix = false;
+        for (keys, command) in bindings {
+            if *keys == sequence {
+                self.pending.clear();
+                return Lookup::Run(command.clone());
+            }
+            prefix |= keys.starts_with(&sequence);
+        }
+        if prefix {
+            Lookup::Pending
+        } else {
+            Lookup::Unbound(std::mem::take(&mut self.pending))
+        }
+    }
+
+    /// 执行command的按键序列，用于在命令面板中显示，global中的优先
+    pub fn keys_for(&self, command: &str) -> Option<String> {
+        CONTEXTS
+            .iter()
+            .filter_map(|context| self.bindings.get(context))
+            .flatten()
+            .find(|(_, c)| c == command)
+            .map(|(keys, _)| {
+                keys.iter()
+                    .map(Chord::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+    }
+}
+
+/// $XDG_CONFIG_HOME/ide-termux/keys.toml，没有设置时为~/.config/ide-termux/keys.toml
+fn config_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("ide-termux").join("keys.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> KeyEvent {
+        let chord = Chord::parse(s).unwrap();
+        KeyEvent::new(chord.code, chord.modifiers)
+    }
+
+    fn keymap(text: &str) -> (Keymap, Vec<String>) {
+        let commands = ["A", "B", "C"].map(String::from);
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+            pending: Vec::new(),
+        };
+        let mut errors = Vec::new();
+        keymap.merge(text, &commands, &mut errors);
+        (keymap, errors)
+    }
+
+    fn run(lookup: Lookup) -> Option<String> {
+        match lookup {
+            Lookup::Run(command) => Some(command),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_chords() {
+        let chord = |code, modifiers| Chord::new(code, modifiers);
+        assert!(
+            Chord::parse("ctrl+d").unwrap() == chord(KeyCode::Char('d'), KeyModifiers::CONTROL)
+        );
+        assert!(
+            Chord::parse("ctrl++").unwrap() == chord(KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+        assert!(Chord::parse("+").unwrap() == chord(KeyCode::Char('+'), KeyModifiers::NONE));
+        // 字符键的Shift体现在大小写中
+        assert!(Chord::parse("shift+k").unwrap() == Chord::parse("K").unwrap());
+        assert!(Chord::parse("f12").unwrap() == chord(KeyCode::F(12), KeyModifiers::NONE));
+        assert!(
+            Chord::parse("Alt+Shift+Left").unwrap()
+                == chord(KeyCode::Left, KeyModifiers::ALT | KeyModifiers::SHIFT)
+        );
+        assert_eq!(Chord::parse("f13").err().unwrap(), "unknown key `f13`");
+        assert_eq!(Chord::parse("ctrl+foo").err().unwrap(), "unknown key `foo`");
+        assert_eq!(
+            Chord::parse("hyper+a").err().unwrap(),
+            "unknown modifier `hyper`"
+        );
+    }
+
+    #[test]
+    fn displays_chords() {
+        for s in [
+            "ctrl+d",
+            "alt+shift+left",
+            "ctrl+space",
+            "f1",
+            "K",
+            "pageup",
+        ] {
+            assert_eq!(Chord::parse(s).unwrap().to_string(), s);
+        }
+    }
+
+    /// 只有默认绑定的Keymap
+    fn defaults() -> (Keymap, Vec<String>) {
+        let mut commands = Vec::new();
+        for table in DEFAULTS.parse::<toml::Table>().unwrap().values() {
+            for command in table.as_table().unwrap().values() {
+                commands.push(command.as_str().unwrap().to_string());
+            }
+        }
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+            pending: Vec::new(),
+        };
+        let mut errors = Vec::new();
+        keymap.merge(DEFAULTS, &commands, &mut errors);
+        (keymap, errors)
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let (_, errors) = defaults();
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn later_bindings_override_and_unbind() {
+        let (mut keymap, errors) = keymap("[global]\n\"ctrl+a\" = \"A\"\n\"ctrl+b\" = \"B\"\n");
+        assert!(errors.is_empty());
+        let mut errors = Vec::new();
+        let commands = ["A", "B", "C"].map(String::from);
+        keymap.merge(
+            "[global]\n\"ctrl+a\" = \"C\"\n\"ctrl+b\" = \"\"\n",
+            &commands,
+            &mut errors,
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            run(keymap.press(key("ctrl+a"), &["global"])).as_deref(),
+            Some("C")
+        );
+        assert!(matches!(
+            keymap.press(key("ctrl+b"), &["global"]),
+            Lookup::Unbound(_)
+        ));
+        assert_eq!(keymap.keys_for("C").as_deref(), Some("ctrl+a"));
+        assert_eq!(keymap.keys_for("B"), None);
+    }
+
+    #[test]
+    fn reports_invalid_entries() {
+        let (_, errors) = keymap(
+            "[nowhere]\n\"a\" = \"A\"\n[editor]\n\"a\" = \"Z\"\n\"b\" = 1\n\"hyper+c\" = \"A\"\n",
+        );
+        assert_eq!(
+            errors,
+            [
+                "[editor] \"a\": unknown command `Z`",
+                "[editor] \"b\": command is not a string",
+                "[editor] \"hyper+c\": unknown modifier `hyper`",
+                "unknown section [nowhere]",
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_for_key_sequences() {
+        let (mut keymap, _) = keymap("[editor]\n\"g g\" = \"A\"\n");
+        assert!(matches!(
+            keymap.press(key("g"), &["editor"]),
+            Lookup::Pending
+        ));
+        assert_eq!(
+            run(keymap.press(key("g"), &["editor"])).as_deref(),
+            Some("A")
+        );
+        // 不能组成序列时，之前按下的键一起交给组件
+        assert!(matches!(
+            keymap.press(key("g"), &["editor"]),
+            Lookup::Pending
+        ));
+        match keymap.press(key("x"), &["editor"]) {
+            Lookup::Unbound(keys) => assert_eq!(keys, [key("g"), key("x")]),
+            _ => panic!("expected unbound keys"),
+        }
+        assert!(
+            matches!(keymap.press(key("x"), &["editor"]), Lookup::Unbound(keys) if keys.len() == 1)
+        );
+    }
+
+    #[test]
+    fn earlier_contexts_take_precedence() {
+        let (mut keymap, _) =
+            keymap("[global]\n\"ctrl+s\" = \"A\"\n\"f1\" = \"C\"\n[editor]\n\"ctrl+s\" = \"B\"\n");
+        let contexts = ["editor", "global"];
+        assert_eq!(
+            run(keymap.press(key("ctrl+s"), &contexts)).as_deref(),
+            Some("B")
+        );
+        assert_eq!(
+            run(keymap.press(key("f1"), &contexts)).as_deref(),
+            Some("C")
+        );
+        assert_eq!(
+            run(keymap.press(key("ctrl+s"), &["problems", "global"])).as_deref(),
+            Some("A")
+        );
+    }
+
+    #[test]
+    fn terminal_gets_control_chars() {
+        let (mut keymap, _) = defaults();
+        let contexts = ["terminal", "global"];
+        // Ctrl-D是终端的EOF，不能退出IDE
+        match keymap.press(key("ctrl+d"), &contexts) {
+            Lookup::Unbound(keys) => assert_eq!(keys, [key("ctrl+d")]),
+            _ => panic!("ctrl+d should go to the terminal"),
+        }
+        for chord in ["ctrl+a", "ctrl+f", "ctrl+p"] {
+            assert!(matches!(
+                keymap.press(key(chord), &contexts),
+                Lookup::Unbound(_)
+            ));
+        }
+        // 仍然可以移走焦点和打开命令面板
+        assert_eq!(
+            run(keymap.press(key("ctrl+up"), &contexts)).as_deref(),
+            Some("Focus: Up")
+        );
+        assert_eq!(
+            run(keymap.press(key("f1"), &contexts)).as_deref(),
+            Some("IDE: Command Palette")
+        );
+        assert_eq!(
+            run(keymap.press(key("alt+t"), &contexts)).as_deref(),
+            Some("Terminal: New Session")
+        );
+        assert_eq!(
+            run(keymap.press(key("ctrl+d"), &["editor", "global"])).as_deref(),
+            Some("IDE: Quit")
+        );
+    }
+}
@@ -1,31 +1,76 @@
 #![feature(never_type)]
-#![feature(bufread_skip_until)]
 
-mod components;
+mod clipboard;
+mod command;
+pub mod components;
+mod diagnostic;
+mod fuzzy;
+mod highlight;
+mod history;
+mod keymap;
+mod lsp;
 mod named_pipe;
-mod renderer;
-mod ui;
+pub mod renderer;
+mod search;
+mod task;
+mod term;
+mod text_buffer;
+pub mod ui;
 
 use components::{
-    areas::{EditorArea, WorkArea},
+    areas::{BottomArea, EditorArea, SideBar, WorkArea},
+    command_palette::CommandPalette,
+    completion::Completion,
     component::Component,
     editor::Editor,
+    file_finder::FileFinder,
+    problems::Problems,
     project_viewer::ProjectViewer,
+    search_panel::SearchPanel,
     terminal::Terminal,
 };
 use named_pipe::{NamedPipe, PipeObject};
 use ui::{
+    backend::TerminalBackend,
     container::{Container, ContainerType},
     framework::Framework,
+    overlay::Placement,
     ChangeFocusEvent,
 };
 
-use crossterm::event::{read, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use command::Registry;
+use crossterm::{
+    cursor,
+    event::{poll, read, Event, KeyEvent, KeyEventKind},
+    queue, style,
+};
+use keymap::{Keymap, Lookup};
+use std::{cell::Cell, io::Write, rc::Rc, sync::Arc, time::Duration};
 
 pub fn run() -> std::io::Result<()> {
-    let mut framework = Framework::new();
+    let mut framework = Framework::new(Box::new(TerminalBackend::enter()));
+    // 大项目的目录树要加载一会
+    queue!(
+        std::io::stdout(),
+        cursor::MoveTo(0, 0),
+        style::Print("加载项目...")
+    )?;
+    std::io::stdout().flush()?;
+
+    let root = std::env::var("PWD").unwrap();
+    let project_viewer = ProjectViewer::new(root.clone());
 
-    let terminal = Terminal::new();
+    let bottom_area = BottomArea::new();
+    if let Err(f) = bottom_area.write().unwrap().bind_to(&mut framework) {
+        f(framework);
+    }
+
+    let problems = Problems::new(project_viewer.read().unwrap().root().to_string());
+    if let Err(f) = problems.write().unwrap().bind_to(&mut framework) {
+        f(framework);
+    }
+
+    let terminal = Terminal::new(std::env::var("SHELL").unwrap(), root);
     if let Err(f) = terminal.write().unwrap().bind_to(&mut framework) {
         f(framework);
     }
@@ -40,65 +85,189 @@ pub fn run() -> std::io::Result<()> {
         f(framework);
     }
 
-    let project_viewer = ProjectViewer::new();
+    let side_bar = SideBar::new();
+    if let Err(f) = side_bar.write().unwrap().bind_to(&mut framework) {
+        f(framework);
+    }
+
+    let (search_panel, file_finder) = {
+        let project_viewer = project_viewer.read().unwrap();
+        (
+            SearchPanel::new(project_viewer.root().to_string(), project_viewer.files()),
+            FileFinder::new(project_viewer.files()),
+        )
+    };
+    // 先加入的在下面
+    if let Err(f) = search_panel.write().unwrap().bind_to(&mut framework) {
+        f(framework);
+    }
     if let Err(f) = project_viewer.write().unwrap().bind_to(&mut framework) {
         f(framework);
     }
 
-    let editor0 = Editor::new(0);
+    // 两个Editor共用一个补全列表，没有语言服务器时从editor stack中的文件收集单词
+    let completion = Completion::new();
+    let pv = Arc::clone(&project_viewer);
+    completion
+        .write()
+        .unwrap()
+        .set_buffers(Box::new(move || pv.read().unwrap().editings()));
+    framework.open_overlay(
+        completion.read().unwrap().container(),
+        Placement::Cursor,
+        false,
+    );
+
+    let editor0 = Editor::new(0, Arc::clone(&completion));
     if let Err(f) = editor0.write().unwrap().bind_to(&mut framework) {
         f(framework);
     }
 
-    let editor1 = Editor::new(1);
+    let editor1 = Editor::new(1, Arc::clone(&completion));
     if let Err(f) = editor1.write().unwrap().bind_to(&mut framework) {
         f(framework);
     }
 
-    framework.set_adjacy(
-        "/Terminal".to_string(),
-        (
-            Some("/WorkArea/ProjectViewer".to_string()),
-            None,
-            None,
-            None,
-        ),
-    );
-    framework.set_adjacy(
-        "/WorkArea/ProjectViewer".to_string(),
-        (
-            None,
-            Some("/Terminal".to_string()),
-            None,
-            Some("/WorkArea/EditorArea/Editor0".to_string()),
-        ),
-    );
-    framework.set_adjacy(
-        "/WorkArea/EditorArea/Editor0".to_string(),
-        (
-            Some("/WorkArea/EditorArea/Editor1".to_string()),
-            Some("/Terminal".to_string()),
-            Some("/WorkArea/ProjectViewer".to_string()),
-            None,
-        ),
-    );
-    framework.set_adjacy(
-        "/WorkArea/EditorArea/Editor1".to_string(),
-        (
-            None,
-            Some("/WorkArea/EditorArea/Editor0".to_string()),
-            Some("/WorkArea/ProjectViewer".to_string()),
-            None,
-        ),
-    );
-
     let fsize = framework.get_size();
     framework.set_size(fsize.0, fsize.1);
 
+    // 所有可以通过命令面板或快捷键执行的命令
+    let mut registry = Registry::default();
+    let command_palette = CommandPalette::new();
+    let quit = Rc::new(Cell::new(false));
+    {
+        let quit = Rc::clone(&quit);
+        registry.register("IDE: Quit", move |_| quit.set(true));
+    }
+    let palette_ref = Arc::clone(&command_palette);
+    registry.register("IDE: Command Palette", move |framework| {
+        palette_ref.write().unwrap().reset();
+        framework.open_overlay(
+            palette_ref.read().unwrap().container(),
+            Placement::Center,
+            true,
+        );
+    });
+    let file_finder_ref = Arc::clone(&file_finder);
+    registry.register("File: Go to File", move |framework| {
+        file_finder_ref.write().unwrap().reset();
+        framework.open_overlay(
+            file_finder_ref.read().unwrap().container(),
+            Placement::Center,
+            true,
+        );
+    });
+    for (name, direction) in [
+        ("Focus: Up", ChangeFocusEvent::Up),
+        ("Focus: Down", ChangeFocusEvent::Down),
+        ("Focus: Left", ChangeFocusEvent::Left),
+        ("Focus: Right", ChangeFocusEvent::Right),
+    ] {
+        registry.register(name, move |framework| {
+            framework.dispatch(ui::Event::ChangeFocus(direction))
+        });
+    }
+    registry.register("Layout: Toggle Zoom", |framework| framework.toggle_zoom());
+    for (name, delta, vertical) in [
+        ("Layout: Grow Pane Height", 2, true),
+        ("Layout: Shrink Pane Height", -2, true),
+        ("Layout: Grow Pane Width", 2, false),
+        ("Layout: Shrink Pane Width", -2, false),
+    ] {
+        registry.register(name, move |framework| {
+            framework.resize_focused(delta, vertical)
+        });
+    }
+    for (name, path) in [
+        ("Focus: Project Viewer", "/WorkArea/SideBar/ProjectViewer"),
+        ("Focus: Search Panel", "/WorkArea/SideBar/SearchPanel"),
+        ("Focus: Editor", "/WorkArea/EditorArea/Editor0"),
+        ("Focus: Terminal", "/BottomArea/Terminal"),
+        ("Focus: Problems", "/BottomArea/Problems"),
+    ] {
+        registry.register(name, move |framework| framework.set_focus(path));
+    }
+    Editor::register_commands([editor0, editor1], &mut registry);
+    ProjectViewer::register_commands(&project_viewer, &mut registry);
+    SearchPanel::register_commands(&search_panel, &mut registry);
+    Terminal::register_commands(&terminal, &mut registry);
+    Problems::register_commands(&problems, &mut registry);
+    // 配置中有错误时忽略出错的项，退出后再显示错误
+    let (mut keymap, keymap_errors) = Keymap::load(&registry.names());
+    command_palette.write().unwrap().set_commands(
+        registry
+            .names()
+            .into_iter()
+            .map(|name| {
+                let keys = keymap.keys_for(&name);
+                (name, keys)
+            })
+            .collect(),
+    );
+
     // 这个receiver接收的是project viewer在收到打开文件事件时发送的更改focus的信号
-    let mut move_focus_recver = NamedPipe::open_receiver(String::from("MoveFocusToEditor"));
+    let move_focus_recver = NamedPipe::open_receiver(String::from("MoveFocusToEditor"));
+    // 其他组件要求打开文件
+    let open_file_recver = NamedPipe::open_receiver(String::from("OpenFile"));
+    let close_popup_recver = NamedPipe::open_receiver(String::from("ClosePopup"));
+    // 命令面板中选中的命令
+    let run_command_recver = NamedPipe::open_receiver(String::from("RunCommand"));
+    // 后台任务要求重绘
+    let redraw_recver = NamedPipe::open_receiver(String::from("Redraw"));
+    // 诊断来源发来的诊断，交给打开的文件
+    let diagnostics_recver = NamedPipe::open_receiver(String::from("Diagnostics"));
+    // 语言服务器给出的修改，例如重命名
+    let apply_edits_recver = NamedPipe::open_receiver(String::from("ApplyEdits"));
+    let mut redraw = true;
     loop {
-        framework.render();
+        while let Ok(PipeObject::Diagnostics(source, diagnostics)) =
+            diagnostics_recver.blocking_write().try_recv()
+        {
+            project_viewer
+                .write()
+                .unwrap()
+                .set_diagnostics(source, diagnostics);
+            redraw = true;
+        }
+        while let Ok(PipeObject::ApplyEdits(files)) = apply_edits_recver.blocking_write().try_recv()
+        {
+            project_viewer.write().unwrap().apply_edits(files);
+            redraw = true;
+        }
+        // 组件在处理输入或渲染时发出的请求
+        if let Ok(PipeObject::ClosePopup) = close_popup_recver.blocking_write().try_recv() {
+            framework.close_modal();
+            redraw = true;
+        }
+        while let Ok(PipeObject::RunCommand(name)) = run_command_recver.blocking_write().try_recv()
+        {
+            registry.run(&name, &mut framework);
+            redraw = true;
+        }
+        if quit.get() {
+            break;
+        }
+        while let Ok(PipeObject::OpenFile(path, at)) = open_file_recver.blocking_write().try_recv()
+        {
+            project_viewer.write().unwrap().open_file(path, at);
+            redraw = true;
+        }
+        if let Ok(PipeObject::MoveFocus) = move_focus_recver.blocking_write().try_recv() {
+            framework.set_focus("/WorkArea/EditorArea/Editor0");
+            redraw = true;
+        }
+        if redraw {
+            framework.render();
+        }
+        // 没有输入时只在后台任务有了新结果时重绘
+        if !poll(Duration::from_millis(50))? {
+            redraw = false;
+            while let Ok(PipeObject::Redraw) = redraw_recver.blocking_write().try_recv() {
+                redraw = true;
+            }
+            continue;
+        }
+        redraw = true;
         match read()? {
             Event::Key(KeyEvent {
                 kind: KeyEventKind::Press | KeyEventKind::Repeat,
@@ -106,40 +275,40 @@ pub fn run() -> std::io::Result<()> {
                 code,
                 state,
             }) => {
-                if modifiers.contains(KeyModifiers::CONTROL) {
-                    match code {
-                        KeyCode::Char('d') => {
+                let key = KeyEvent {
+                    code,
+                    modifiers,
+                    kind: KeyEventKind::Press,
+                    state,
+                };
+                // 有焦点的组件的绑定优先于全局绑定
+                let contexts = framework
+                    .key_context()
+                    .into_iter()
+                    .chain(["global"])
+                    .collect::<Vec<_>>();
+                match keymap.press(key, &contexts) {
+                    Lookup::Run(name) => {
+                        registry.run(&name, &mut framework);
+                        if quit.get() {
                             break;
                         }
-                        KeyCode::Up => {
-                            framework.dispatch(ui::Event::ChangeFocus(ChangeFocusEvent::Up))
-                        }
-                        KeyCode::Down => {
-                            framework.dispatch(ui::Event::ChangeFocus(ChangeFocusEvent::Down))
-                        }
-                        KeyCode::Left => {
-                            framework.dispatch(ui::Event::ChangeFocus(ChangeFocusEvent::Left))
-                        }
-                        KeyCode::Right => {
-                            framework.dispatch(ui::Event::ChangeFocus(ChangeFocusEvent::Right))
+                    }
+                    Lookup::Pending => (),
+                    Lookup::Unbound(keys) => {
+                        for key in keys {
+                            framework.dispatch(ui::Event::Crossterm(Event::Key(key)));
                         }
-                        _ => (),
                     }
-                } else {
-                    framework.dispatch(ui::Event::Crossterm(Event::Key(KeyEvent {
-                        code,
-                        modifiers,
-                        kind: KeyEventKind::Press,
-                        state,
-                    })));
                 }
             }
             Event::Resize(width, height) => framework.set_size(width as usize, height as usize),
             event => framework.dispatch(ui::Event::Crossterm(event)),
         }
-        if let Ok(PipeObject::MoveFocus) = move_focus_recver.blocking_write().try_recv() {
-            framework.dispatch(ui::Event::ChangeFocus(ChangeFocusEvent::Right));
-        }
+    }
+    drop(framework);
+    for error in keymap_errors {
+        eprintln!("{}", error);
     }
     Ok(())
 }
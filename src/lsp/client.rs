@@ -0,0 +1,1139 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use ropey::Rope;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem,
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc::Sender as PipeSender, RwLock as AsyncRwLock};
+
+use super::{rpc, server::Server};
+use crate::{
+    diagnostic::{FileMarkers, Marker, Severity},
+    named_pipe::{NamedPipe, PipeObject},
+};
+
+/// 关闭时等待服务器回复shutdown和自行退出的时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    // 片段中的占位符，例如${1:x}、${2}和$0
+    static ref PLACEHOLDER: Regex = Regex::new(r"\$\{\d+:([^}]*)\}|\$\{\d+\}|\$\d+").unwrap();
+}
+
+/// 服务器计算列号时使用的编码单位
+#[derive(Clone, Copy)]
+enum Encoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+impl Encoding {
+    fn units(&self, ch: char) -> usize {
+        match self {
+            Encoding::Utf8 => ch.len_utf8(),
+            Encoding::Utf16 => ch.len_utf16(),
+            Encoding::Utf32 => 1,
+        }
+    }
+}
+
+/// 文件中的位置，(行号, 字符下标)
+#[derive(Clone)]
+pub struct Location {
+    pub path: PathBuf,
+    pub start: (usize, usize),
+}
+
+/// 把start到end之间的内容替换成text
+#[derive(Clone)]
+pub struct TextEdit {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub text: String,
+}
+
+/// 补全的候选
+#[derive(Clone)]
+pub struct CompletionItem {
+    pub label: String,
+    /// 类型或签名等简短说明
+    pub detail: Option<String>,
+    pub documentation: Option<String>,
+    /// 选中后替换正在输入的单词的文本
+    pub insert: String,
+}
+
+/// 发给服务器的文档内容，用来在字符下标和服务器的列号之间换算
+struct Document {
+    text: Rope,
+    version: i64,
+}
+
+/// 初始化完成之前要发送的消息先排队
+struct Outgoing {
+    writer: Box<dyn Write + Send>,
+    ready: bool,
+    queue: Vec<Value>,
+}
+
+/// Client和读线程共享的状态
+struct Shared {
+    name: &'static str,
+    root: PathBuf,
+    outgoing: Mutex<Outgoing>,
+    /// 等待回复的请求
+    pending: Mutex<HashMap<u64, Sender<Result<Value, String>>>>,
+    documents: Mutex<HashMap<PathBuf, Document>>,
+    diagnostics: Mutex<HashMap<PathBuf, Vec<Marker>>>,
+    encoding: Mutex<Encoding>,
+    /// textDocumentSync的change，0为不同步，1为发送全文，2为增量
+    sync: Mutex<u64>,
+    diagnostics_sender: Arc<AsyncRwLock<PipeSender<PipeObject>>>,
+    redraw_sender: Arc<AsyncRwLock<PipeSender<PipeObject>>>,
+}
+
+/// 通过JSON-RPC与一个语言服务器通信
+///
+/// 请求的结果通过Pending在渲染时取回，服务器给出的诊断通过Diagnostics管道发出
+pub struct Client {
+    shared: Arc<Shared>,
+    next_id: AtomicU64,
+    child: Option<Child>,
+}
+
+/// 还没收到回复的请求
+pub struct Pending<T> {
+    receiver: Receiver<Result<Value, String>>,
+    shared: Arc<Shared>,
+    parse: fn(&Shared, Value) -> T,
+}
+
+impl<T> Pending<T> {
+    /// 回复还没到时返回None
+    pub fn poll(&self) -> Option<Result<T, String>> {
+        match self.receiver.try_recv() {
+            Ok(res) => Some(res.map(|value| (self.parse)(&self.shared, value))),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("language server exited".to_string())),
+        }
+    }
+}
+
+impl Client {
+    /// 在项目根目录启动server并通过stdio通信
+    pub fn spawn(server: &Server, root: &str) -> io::Result<Self> {
+        let command = server.command();
+        let mut child = Command::new(&command[0])
+            .args(&command[1..])
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let mut client = Client::new(server.name, stdout, stdin, root);
+        client.child = Some(child);
+        Ok(client)
+    }
+
+    /// 通过reader和writer与服务器通信，并发送initialize请求
+    pub fn new(
+        name: &'static str,
+        reader: impl Read + Send + 'static,
+        writer: impl Write + Send + 'static,
+        root: &str,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            name,
+            root: PathBuf::from(root),
+            outgoing: Mutex::new(Outgoing {
+                writer: Box::new(BufWriter::new(writer)),
+                ready: false,
+                queue: Vec::new(),
+            }),
+            pending: Mutex::new(HashMap::new()),
+            documents: Mutex::new(HashMap::new()),
+            diagnostics: Mutex::new(HashMap::new()),
+            encoding: Mutex::new(Encoding::Utf16),
+            sync: Mutex::new(2),
+            diagnostics_sender: NamedPipe::open_sender(String::from("Diagnostics")),
+            redraw_sender: NamedPipe::open_sender(String::from("Redraw")),
+        });
+        let reader_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            while let Ok(message) = rpc::read_message(&mut reader) {
+                reader_shared.handle(message);
+            }
+            // 服务器退出，等待中的请求都会失败
+            reader_shared.pending.lock().unwrap().clear();
+            let _ = reader_shared
+                .redraw_sender
+                .blocking_read()
+                .try_send(PipeObject::Redraw);
+        });
+        // initialize的id为0，不经过队列
+        shared.write(&json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "processId": process::id(),
+                "rootPath": root,
+                "rootUri": uri(Path::new(root)),
+                "workspaceFolders": [{
+                    "uri": uri(Path::new(root)),
+                    "name": root.rsplit('/').next().unwrap_or(root),
+                }],
+                "capabilities": {
+                    "general": { "positionEncodings": ["utf-32", "utf-16"] },
+                    "textDocument": {
+                        "synchronization": { "didSave": true },
+                        "hover": { "contentFormat": ["plaintext", "markdown"] },
+                        "completion": { "completionItem": { "snippetSupport": false } },
+                        "definition": { "linkSupport": true },
+                        "references": {},
+                        "rename": {},
+                        "publishDiagnostics": {},
+                    },
+                    "workspace": {
+                        "configuration": true,
+                        "workspaceEdit": { "documentChanges": true },
+                    },
+                },
+                // clangd在positionEncodings之前的扩展
+                "offsetEncoding": ["utf-32", "utf-16"],
+            },
+        }));
+        Client {
+            shared,
+            next_id: AtomicU64::new(1),
+            child: None,
+        }
+    }
+
+    /// 把path转成相对于项目根目录的路径，不在项目中时为None
+    pub fn relative(&self, path: &Path) -> Option<Vec<String>> {
+        self.shared.relative(path)
+    }
+
+    pub fn did_open(&self, path: &Path, language: &str, text: String) {
+        self.shared.documents.lock().unwrap().insert(
+            path.to_path_buf(),
+            Document {
+                text: Rope::from_str(&text),
+                version: 0,
+            },
+        );
+        self.shared.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri(path),
+                    "languageId": language,
+                    "version": 0,
+                    "text": text,
+                },
+            }),
+        );
+    }
+
+    /// 文档中start到end之间的内容被替换成了text
+    pub fn did_change(
+        &self,
+        path: &Path,
+        start: (usize, usize),
+        end: (usize, usize),
+        text: &[char],
+    ) {
+        let encoding = *self.shared.encoding.lock().unwrap();
+        let sync = *self.shared.sync.lock().unwrap();
+        let mut documents = self.shared.documents.lock().unwrap();
+        let Some(document) = documents.get_mut(path) else {
+            return;
+        };
+        // 先按修改前的内容换算范围
+        let range = json!({
+            "start": to_lsp(Some(&document.text), start, encoding),
+            "end": to_lsp(Some(&document.text), end, encoding),
+        });
+        let text = text.iter().collect::<String>();
+        let from = char_offset(&document.text, start);
+        let to = char_offset(&document.text, end);
+        document.text.remove(from..to);
+        document.text.insert(from, &text);
+        document.version += 1;
+        let change = match sync {
+            1 => json!({ "text": document.text.to_string() }),
+            2 => json!({ "range": range, "text": text }),
+            _ => return,
+        };
+        let version = document.version;
+        drop(documents);
+        self.shared.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri(path), "version": version },
+                "contentChanges": [change],
+            }),
+        );
+    }
+
+    pub fn did_save(&self, path: &Path) {
+        self.shared.notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": uri(path) } }),
+        );
+    }
+
+    /// 光标处符号的说明
+    pub fn hover(&self, path: &Path, at: (usize, usize)) -> Pending<Option<String>> {
+        let params = self.shared.position_params(path, at);
+        self.request("textDocument/hover", params, parse_hover)
+    }
+
+    pub fn completion(&self, path: &Path, at: (usize, usize)) -> Pending<Vec<CompletionItem>> {
+        let mut params = self.shared.position_params(path, at);
+        params["context"] = json!({ "triggerKind": 1 });
+        self.request("textDocument/completion", params, parse_completion)
+    }
+
+    pub fn definition(&self, path: &Path, at: (usize, usize)) -> Pending<Vec<Location>> {
+        let params = self.shared.position_params(path, at);
+        self.request("textDocument/definition", params, parse_locations)
+    }
+
+    pub fn references(&self, path: &Path, at: (usize, usize)) -> Pending<Vec<Location>> {
+        let mut params = self.shared.position_params(path, at);
+        params["context"] = json!({ "includeDeclaration": true });
+        self.request("textDocument/references", params, parse_locations)
+    }
+
+    /// 重命名光标处的符号，结果为每个文件要做的修改
+    pub fn rename(
+        &self,
+        path: &Path,
+        at: (usize, usize),
+        new_name: &str,
+    ) -> Pending<Vec<(PathBuf, Vec<TextEdit>)>> {
+        let mut params = self.shared.position_params(path, at);
+        params["newName"] = json!(new_name);
+        self.request("textDocument/rename", params, parse_workspace_edit)
+    }
+
+    fn request<T>(
+        &self,
+        method: &str,
+        params: Value,
+        parse: fn(&Shared, Value) -> T,
+    ) -> Pending<T> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel();
+        self.shared.pending.lock().unwrap().insert(id, sender);
+        self.shared.send(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }));
+        Pending {
+            receiver,
+            shared: Arc::clone(&self.shared),
+            parse,
+        }
+    }
+}
+
+impl Drop for Client {
+    /// 先请求shutdown再通知exit，服务器没有及时退出时杀掉
+    fn drop(&mut self) {
+        let Some(mut child) = self.child.take() else {
+            return;
+        };
+        // 还没初始化完的服务器收不到排队的消息
+        if self.shared.outgoing.lock().unwrap().ready {
+            let shutdown = self.request("shutdown", Value::Null, |_, _| ());
+            let _ = shutdown.receiver.recv_timeout(SHUTDOWN_TIMEOUT);
+            self.shared.notify("exit", Value::Null);
+            let start = Instant::now();
+            while start.elapsed() < SHUTDOWN_TIMEOUT {
+                if let Ok(Some(_)) = child.try_wait() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+impl Shared {
+    /// 直接写出消息，忽略写入错误，服务器退出后由读线程收尾
+    fn write(&self, message: &Value) {
+        let _ = rpc::write_message(&mut self.outgoing.lock().unwrap().writer, message);
+    }
+
+    fn send(&self, message: Value) {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        if outgoing.ready {
+            let _ = rpc::write_message(&mut outgoing.writer, &message);
+        } else {
+            outgoing.queue.push(message);
+        }
+    }
+
+    fn notify(&self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn handle(&self, message: Value) {
+        let method = message["method"].as_str();
+        match (method, message.get("id")) {
+            // 回复
+            (None, Some(id)) => {
+                let result = match message.get("error") {
+                    Some(error) => Err(error["message"]
+                        .as_str()
+                        .unwrap_or("request failed")
+                        .to_string()),
+                    None => Ok(message["result"].clone()),
+                };
+                let Some(id) = id.as_u64() else {
+                    return;
+                };
+                if id == 0 {
+                    self.initialized(result);
+                } else if let Some(sender) = self.pending.lock().unwrap().remove(&id) {
+                    let _ = sender.send(result);
+                    let _ = self
+                        .redraw_sender
+                        .blocking_read()
+                        .try_send(PipeObject::Redraw);
+                }
+            }
+            // 服务器发来的请求，只回答配置，其余都回复null
+            (Some(method), Some(id)) => {
+                let result = match method {
+                    "workspace/configuration" => Value::Array(vec![
+                        Value::Null;
+                        message["params"]["items"]
+                            .as_array()
+                            .map_or(0, Vec::len)
+                    ]),
+                    _ => Value::Null,
+                };
+                self.write(&json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+            }
+            (Some("textDocument/publishDiagnostics"), None) => {
+                self.publish_diagnostics(&message["params"]);
+            }
+            _ => (),
+        }
+    }
+
+    /// 收到initialize的回复，记下服务器的能力并发出排队的消息
+    fn initialized(&self, result: Result<Value, String>) {
+        if let Ok(result) = &result {
+            let capabilities = &result["capabilities"];
+            let encoding = capabilities["positionEncoding"]
+                .as_str()
+                .or(result["offsetEncoding"].as_str());
+            *self.encoding.lock().unwrap() = match encoding {
+                Some("utf-8") => Encoding::Utf8,
+                Some("utf-32") => Encoding::Utf32,
+                _ => Encoding::Utf16,
+            };
+            let sync = &capabilities["textDocumentSync"];
+            *self.sync.lock().unwrap() = sync.as_u64().or(sync["change"].as_u64()).unwrap_or(0);
+        }
+        let mut outgoing = self.outgoing.lock().unwrap();
+        let queue = mem::take(&mut outgoing.queue);
+        let _ = rpc::write_message(
+            &mut outgoing.writer,
+            &json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+        );
+        for message in queue {
+            let _ = rpc::write_message(&mut outgoing.writer, &message);
+        }
+        outgoing.ready = true;
+    }
+
+    /// 替换一个文件的诊断，并发出这个服务器的全部诊断
+    fn publish_diagnostics(&self, params: &Value) {
+        let Some(path) = params["uri"].as_str().and_then(path_of) else {
+            return;
+        };
+        let mut texts = Texts::new(self);
+        let markers = params["diagnostics"]
+            .as_array()
+            .map(|diagnostics| {
+                diagnostics
+                    .iter()
+                    .filter_map(|diagnostic| {
+                        let (start, end) = texts.range(&path, &diagnostic["range"]);
+                        Some(Marker {
+                            source: self.name,
+                            severity: match diagnostic["severity"].as_u64() {
+                                Some(2) => Severity::Warning,
+                                Some(3) => Severity::Note,
+                                Some(4) => Severity::Help,
+                                _ => Severity::Error,
+                            },
+                            start,
+                            end,
+                            message: diagnostic["message"].as_str()?.to_string(),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let files = {
+            let mut diagnostics = self.diagnostics.lock().unwrap();
+            if markers.is_empty() {
+                diagnostics.remove(&path);
+            } else {
+                diagnostics.insert(path, markers);
+            }
+            diagnostics
+                .iter()
+                .filter_map(|(path, markers)| Some((self.relative(path)?, markers.clone())))
+                .collect::<FileMarkers>()
+        };
+        // 每次都发出全部诊断，不能丢
+        let _ = self
+            .diagnostics_sender
+            .blocking_read()
+            .blocking_send(PipeObject::Diagnostics(self.name, files));
+        let _ = self
+            .redraw_sender
+            .blocking_read()
+            .try_send(PipeObject::Redraw);
+    }
+
+    fn relative(&self, path: &Path) -> Option<Vec<String>> {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative.to_path_buf(),
+            // 服务器可能给出解析过符号链接的路径
+            Err(_) => path
+                .strip_prefix(fs::canonicalize(&self.root).ok()?)
+                .ok()?
+                .to_path_buf(),
+        };
+        Some(
+            relative
+                .iter()
+                .map(|s| s.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+
+    fn position_params(&self, path: &Path, at: (usize, usize)) -> Value {
+        let encoding = *self.encoding.lock().unwrap();
+        let documents = self.documents.lock().unwrap();
+        let text = documents.get(path).map(|document| &document.text);
+        json!({
+            "textDocument": { "uri": uri(path) },
+            "position": to_lsp(text, at, encoding),
+        })
+    }
+}
+
+/// 换算服务器给出的范围时用到的文件内容，没有打开的文件从磁盘读取
+struct Texts<'a> {
+    shared: &'a Shared,
+    encoding: Encoding,
+    cache: HashMap<PathBuf, Option<Rope>>,
+}
+
+impl<'a> Texts<'a> {
+    fn new(shared: &'a Shared) -> Self {
+        Texts {
+            shared,
+            encoding: *shared.encoding.lock().unwrap(),
+            cache: HashMap::new(),
+        }
+    }
+
+    fn range(&mut self, path: &Path, range: &Value) -> ((usize, usize), (usize, usize)) {
+        let shared = self.shared;
+        let text = self.cache.entry(path.to_path_buf()).or_insert_with(|| {
+            match shared.documents.lock().unwrap().get(path) {
+                Some(document) => Some(document.text.clone()),
+                None => File::open(path)
+                    .and_then(|file| Rope::from_reader(BufReader::new(file)))
+                    .ok(),
+            }
+        });
+        (
+            from_lsp(text.as_ref(), &range["start"], self.encoding),
+            from_lsp(text.as_ref(), &range["end"], self.encoding),
+        )
+    }
+}
+
+fn parse_hover(_shared: &Shared, value: Value) -> Option<String> {
+    fn flatten(contents: &Value, res: &mut Vec<String>) {
+        match contents {
+            Value::String(s) => res.push(s.clone()),
+            Value::Array(items) => items.iter().for_each(|item| flatten(item, res)),
+            Value::Object(object) => {
+                if let Some(Value::String(s)) = object.get("value") {
+                    res.push(s.clone());
+                }
+            }
+            _ => (),
+        }
+    }
+    let mut res = Vec::new();
+    flatten(&value["contents"], &mut res);
+    let res = res.join("\n");
+    (!res.trim().is_empty()).then_some(res)
+}
+
+/// CompletionItem[]或CompletionList
+fn parse_completion(_shared: &Shared, value: Value) -> Vec<CompletionItem> {
+    let items = match &value {
+        Value::Array(items) => items,
+        value => match value["items"].as_array() {
+            Some(items) => items,
+            None => return Vec::new(),
+        },
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let label = item["label"].as_str()?.to_string();
+            let insert = item["textEdit"]["newText"]
+                .as_str()
+                .or(item["insertText"].as_str())
+                .unwrap_or(&label);
+            // 不支持片段，去掉其中的占位符
+            let insert = if item["insertTextFormat"] == 2 {
+                PLACEHOLDER.replace_all(insert, "$1").into_owned()
+            } else {
+                insert.to_string()
+            };
+            let documentation = match &item["documentation"] {
+                Value::String(s) => Some(s.clone()),
+                doc => doc["value"].as_str().map(str::to_string),
+            };
+            Some(CompletionItem {
+                detail: item["detail"].as_str().map(str::to_string),
+                documentation,
+                insert,
+                label,
+            })
+        })
+        .collect()
+}
+
+/// Location、Location[]或LocationLink[]
+fn parse_locations(shared: &Shared, value: Value) -> Vec<Location> {
+    let items = match value {
+        Value::Array(items) => items,
+        Value::Null => Vec::new(),
+        item => vec![item],
+    };
+    let mut texts = Texts::new(shared);
+    items
+        .iter()
+        .filter_map(|item| {
+            let (uri, range) = match item.get("targetUri") {
+                Some(uri) => (uri, &item["targetSelectionRange"]),
+                None => (&item["uri"], &item["range"]),
+            };
+            let path = path_of(uri.as_str()?)?;
+            let (start, _) = texts.range(&path, range);
+            Some(Location { path, start })
+        })
+        .collect()
+}
+
+/// WorkspaceEdit中的changes或documentChanges，忽略创建、重命名文件等操作
+fn parse_workspace_edit(shared: &Shared, value: Value) -> Vec<(PathBuf, Vec<TextEdit>)> {
+    let mut files = Vec::new();
+    if let Some(changes) = value["documentChanges"].as_array() {
+        for change in changes {
+            if let Some(uri) = change["textDocument"]["uri"].as_str() {
+                files.push((uri, &change["edits"]));
+            }
+        }
+    } else if let Some(changes) = value["changes"].as_object() {
+        for (uri, edits) in changes {
+            files.push((uri.as_str(), edits));
+        }
+    }
+    let mut texts = Texts::new(shared);
+    files
+        .into_iter()
+        .filter_map(|(uri, edits)| {
+            let path = path_of(uri)?;
+            let edits = edits
+                .as_array()?
+                .iter()
+                .filter_map(|edit| {
+                    let (start, end) = texts.range(&path, &edit["range"]);
+                    Some(TextEdit {
+                        start,
+                        end,
+                        text: edit["newText"].as_str()?.to_string(),
+                    })
+                })
+                .collect();
+            Some((path, edits))
+        })
+        .collect()
+}
+
+fn char_offset(text: &Rope, (line, index): (usize, usize)) -> usize {
+    if line >= text.len_lines() {
+        return text.len_chars();
+    }
+    (text.line_to_char(line) + index).min(text.len_chars())
+}
+
+/// 把(行号, 字符下标)换算成服务器的Position
+fn to_lsp(text: Option<&Rope>, (line, index): (usize, usize), encoding: Encoding) -> Value {
+    let character = match text.and_then(|text| text.get_line(line)) {
+        Some(l) => l.chars().take(index).map(|ch| encoding.units(ch)).sum(),
+        None => index,
+    };
+    json!({ "line": line, "character": character })
+}
+
+/// 把服务器的Position换算成(行号, 字符下标)，超出行尾时停在行尾
+fn from_lsp(text: Option<&Rope>, position: &Value, encoding: Encoding) -> (usize, usize) {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    let index = match text.and_then(|text| text.get_line(line)) {
+        Some(l) => {
+            let mut units = 0;
+            l.chars()
+                .take_while(|&ch| {
+                    let inside = units < character && ch != '\n';
+                    units += encoding.units(ch);
+                    inside
+                })
+                .count()
+        }
+        None => character,
+    };
+    (line, index)
+}
+
+/// file://开头的URI，路径中的特殊字符按百分号编码
+fn uri(path: &Path) -> String {
+    let mut res = String::from("file://");
+    for &byte in path.to_string_lossy().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            res.push(byte as char);
+        } else {
+            res += &format!("%{:02X}", byte);
+        }
+    }
+    res
+}
+
+fn path_of(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%' && i + 2 < encoded.len() {
+            let hex = std::str::from_utf8(&encoded[i + 1..i + 3]).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            bytes.push(encoded[i]);
+            i += 1;
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lsp::server::server_for, ui::testing};
+    use std::{
+        env,
+        os::unix::{fs::PermissionsExt, net::UnixStream},
+        time::{Duration, Instant},
+    };
+
+    /// 按脚本收发消息的假服务器
+    struct FakeServer {
+        reader: BufReader<UnixStream>,
+        writer: UnixStream,
+    }
+
+    impl FakeServer {
+        fn recv(&mut self) -> Value {
+            rpc::read_message(&mut self.reader).unwrap()
+        }
+
+        fn send(&mut self, message: Value) {
+            rpc::write_message(&mut self.writer, &message).unwrap();
+        }
+
+        fn reply(&mut self, request: &Value, result: Value) {
+            self.send(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }));
+        }
+
+        /// 回复initialize并收到initialized
+        fn initialize(&mut self, capabilities: Value) {
+            let request = self.recv();
+            assert_eq!(request["method"], "initialize");
+            self.reply(&request, json!({ "capabilities": capabilities }));
+            assert_eq!(self.recv()["method"], "initialized");
+        }
+    }
+
+    fn connect() -> (Client, FakeServer) {
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        theirs
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let client = Client::new("fake", ours.try_clone().unwrap(), ours, "/proj");
+        let server = FakeServer {
+            reader: BufReader::new(theirs.try_clone().unwrap()),
+            writer: theirs,
+        };
+        (client, server)
+    }
+
+    fn wait<T>(pending: Pending<T>) -> Result<T, String> {
+        let start = Instant::now();
+        loop {
+            if let Some(res) = pending.poll() {
+                return res;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "no reply");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn queues_messages_until_initialized() {
+        let (client, mut server) = connect();
+        let path = Path::new("/proj/src/main.rs");
+        client.did_open(path, "rust", "fn main() {}\n".to_string());
+        let request = server.recv();
+        assert_eq!(request["id"], 0);
+        assert_eq!(request["params"]["rootUri"], "file:///proj");
+        // 回复之前didOpen不能发出
+        server.reply(&request, json!({ "capabilities": {} }));
+        assert_eq!(server.recv()["method"], "initialized");
+        let open = server.recv();
+        assert_eq!(open["method"], "textDocument/didOpen");
+        assert_eq!(
+            open["params"]["textDocument"]["uri"],
+            "file:///proj/src/main.rs"
+        );
+        assert_eq!(open["params"]["textDocument"]["text"], "fn main() {}\n");
+    }
+
+    #[test]
+    fn syncs_documents_in_order() {
+        let (client, mut server) = connect();
+        server.initialize(json!({ "textDocumentSync": 2 }));
+        let path = Path::new("/proj/a.rs");
+        client.did_open(path, "rust", "let 名 = 1;\n".to_string());
+        client.did_change(path, (0, 6), (0, 7), &['2']);
+        client.did_save(path);
+        assert_eq!(server.recv()["method"], "textDocument/didOpen");
+        let change = server.recv();
+        assert_eq!(change["method"], "textDocument/didChange");
+        assert_eq!(change["params"]["textDocument"]["version"], 1);
+        // 默认按UTF-16换算，'名'占一个单位
+        assert_eq!(
+            change["params"]["contentChanges"][0],
+            json!({
+                "range": {
+                    "start": { "line": 0, "character": 6 },
+                    "end": { "line": 0, "character": 7 },
+                },
+                "text": "2",
+            })
+        );
+        assert_eq!(server.recv()["method"], "textDocument/didSave");
+    }
+
+    #[test]
+    fn sends_full_text_when_server_wants_it() {
+        let (client, mut server) = connect();
+        server.initialize(json!({ "textDocumentSync": { "change": 1 } }));
+        let path = Path::new("/proj/a.rs");
+        client.did_open(path, "rust", "ab\ncd\n".to_string());
+        client.did_change(path, (1, 0), (1, 2), &['x']);
+        server.recv();
+        let change = server.recv();
+        assert_eq!(
+            change["params"]["contentChanges"],
+            json!([{ "text": "ab\nx\n" }])
+        );
+    }
+
+    #[test]
+    fn converts_positions_with_server_encoding() {
+        let (client, mut server) = connect();
+        server.initialize(json!({ "positionEncoding": "utf-8" }));
+        let path = Path::new("/proj/a.rs");
+        client.did_open(path, "rust", "名字 x\n".to_string());
+        server.recv();
+        let pending = client.hover(path, (0, 3));
+        let request = server.recv();
+        assert_eq!(request["method"], "textDocument/hover");
+        assert_eq!(
+            request["params"]["position"],
+            json!({ "line": 0, "character": 7 })
+        );
+        server.reply(
+            &request,
+            json!({ "contents": [{ "language": "rust", "value": "x: i32" }, "doc"] }),
+        );
+        assert_eq!(wait(pending).unwrap().as_deref(), Some("x: i32\ndoc"));
+    }
+
+    #[test]
+    fn parses_definition_and_references() {
+        let (client, mut server) = connect();
+        server.initialize(json!({}));
+        let path = Path::new("/proj/a.rs");
+        client.did_open(path, "rust", "fn f() {}\nf();\n".to_string());
+        server.recv();
+
+        let pending = client.definition(path, (1, 0));
+        let request = server.recv();
+        assert_eq!(request["method"], "textDocument/definition");
+        // LocationLink
+        server.reply(
+            &request,
+            json!([{
+                "targetUri": "file:///proj/a.rs",
+                "targetRange": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 9 } },
+                "targetSelectionRange": { "start": { "line": 0, "character": 3 }, "end": { "line": 0, "character": 4 } },
+            }]),
+        );
+        let locations = wait(pending).unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].path, path);
+        assert_eq!(locations[0].start, (0, 3));
+
+        let pending = client.references(path, (0, 3));
+        let request = server.recv();
+        assert_eq!(request["method"], "textDocument/references");
+        assert_eq!(request["params"]["context"]["includeDeclaration"], true);
+        let range = |line, character| {
+            json!({
+                "start": { "line": line, "character": character },
+                "end": { "line": line, "character": character + 1 },
+            })
+        };
+        server.reply(
+            &request,
+            json!([
+                { "uri": "file:///proj/a.rs", "range": range(0, 3) },
+                { "uri": "file:///proj/a.rs", "range": range(1, 0) },
+            ]),
+        );
+        let starts = wait(pending)
+            .unwrap()
+            .iter()
+            .map(|location| location.start)
+            .collect::<Vec<_>>();
+        assert_eq!(starts, [(0, 3), (1, 0)]);
+    }
+
+    #[test]
+    fn parses_rename_workspace_edit() {
+        let (client, mut server) = connect();
+        server.initialize(json!({}));
+        let path = Path::new("/proj/src/a b.rs");
+        client.did_open(path, "rust", "let x = x;\n".to_string());
+        server.recv();
+        let pending = client.rename(path, (0, 4), "y");
+        let request = server.recv();
+        assert_eq!(request["method"], "textDocument/rename");
+        assert_eq!(request["params"]["newName"], "y");
+        let edit = |character| {
+            json!({
+                "range": {
+                    "start": { "line": 0, "character": character },
+                    "end": { "line": 0, "character": character + 1 },
+                },
+                "newText": "y",
+            })
+        };
+        server.reply(
+            &request,
+            json!({
+                "documentChanges": [{
+                    "textDocument": { "uri": "file:///proj/src/a%20b.rs", "version": 0 },
+                    "edits": [edit(4), edit(8)],
+                }],
+            }),
+        );
+        let files = wait(pending).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, path);
+        // 编辑器按相对路径发出ApplyEdits
+        assert_eq!(
+            client.relative(&files[0].0),
+            Some(vec!["src".to_string(), "a b.rs".to_string()])
+        );
+        let edits = files[0]
+            .1
+            .iter()
+            .map(|edit| (edit.start, edit.end, edit.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(edits, [((0, 4), (0, 5), "y"), ((0, 8), (0, 9), "y")]);
+    }
+
+    #[test]
+    fn reports_request_errors() {
+        let (client, mut server) = connect();
+        server.initialize(json!({}));
+        let pending = client.hover(Path::new("/proj/a.rs"), (0, 0));
+        let request = server.recv();
+        server.send(json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "error": { "code": -32601, "message": "unsupported" },
+        }));
+        assert_eq!(wait(pending).err().as_deref(), Some("unsupported"));
+    }
+
+    #[test]
+    fn answers_configuration_requests() {
+        let (_client, mut server) = connect();
+        server.initialize(json!({}));
+        server.send(json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "workspace/configuration",
+            "params": { "items": [{}, {}] },
+        }));
+        let reply = server.recv();
+        assert_eq!(reply["id"], 7);
+        assert_eq!(reply["result"], json!([null, null]));
+    }
+
+    #[test]
+    fn forwards_published_diagnostics() {
+        let receiver = NamedPipe::open_receiver(String::from("Diagnostics"));
+        let (client, mut server) = connect();
+        server.initialize(json!({}));
+        let path = Path::new("/proj/src/a.rs");
+        client.did_open(path, "rust", "let 名 = x;\n".to_string());
+        server.recv();
+        server.send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": "file:///proj/src/a.rs",
+                "diagnostics": [{
+                    "range": {
+                        "start": { "line": 0, "character": 8 },
+                        "end": { "line": 0, "character": 9 },
+                    },
+                    "severity": 2,
+                    "message": "unused",
+                }],
+            },
+        }));
+        let start = Instant::now();
+        let object = loop {
+            if let Ok(object) = receiver.blocking_write().try_recv() {
+                break object;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "no diagnostics");
+            thread::sleep(Duration::from_millis(1));
+        };
+        let PipeObject::Diagnostics(source, files) = object else {
+            panic!("expected diagnostics");
+        };
+        assert_eq!(source, "fake");
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, ["src", "a.rs"]);
+        let markers = &files[0].1;
+        assert_eq!(markers.len(), 1);
+        assert!(markers[0].severity == Severity::Warning);
+        assert_eq!((markers[0].start, markers[0].end), ((0, 8), (0, 9)));
+        assert_eq!(markers[0].message, "unused");
+    }
+
+    /// 用shell按Content-Length收发消息的服务器，把收到的方法名记在项目根目录的log中
+    const SCRIPTED: &str = r#"#!/bin/sh
+recv() {
+    len=
+    while IFS= read -r line; do
+        line=$(printf '%s' "$line" | tr -d '\r')
+        [ -z "$line" ] && break
+        case "$line" in Content-Length:*) len=${line#Content-Length: } ;; esac
+    done
+    [ -n "$len" ] || exit 1
+    body=$(dd bs=1 count="$len" 2>/dev/null)
+    method=$(printf '%s' "$body" | sed -n 's/.*"method":"\([^"]*\)".*/\1/p')
+    id=$(printf '%s' "$body" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+    echo "$method" >> log
+}
+send() {
+    printf 'Content-Length: %s\r\n\r\n%s' "${#1}" "$1"
+}
+recv
+send "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"capabilities\":{}}}"
+recv
+recv
+send "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"contents\":\"hover from $method\"}}"
+recv
+send "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":null}"
+recv
+[ "$method" = exit ] && echo bye >> log
+"#;
+
+    #[test]
+    fn spawns_server_over_stdio_and_shuts_it_down() {
+        let root = testing::project("lsp-spawn", &[("server.sh", SCRIPTED)]);
+        let script = format!("{}/server.sh", root);
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        // 其他测试不会打开C文件，用clangd的环境变量换成脚本
+        env::set_var("IDE_TERMUX_LSP_C", &script);
+        let (server, _) = server_for("main.c").unwrap();
+        let client = Client::spawn(server, &root).unwrap();
+
+        let path = PathBuf::from(format!("{}/main.c", root));
+        let hover = wait(client.hover(&path, (0, 0)));
+        assert_eq!(hover, Ok(Some("hover from textDocument/hover".to_string())));
+
+        drop(client);
+        let log = fs::read_to_string(format!("{}/log", root)).unwrap();
+        assert_eq!(
+            log.lines().collect::<Vec<_>>(),
+            [
+                "initialize",
+                "initialized",
+                "textDocument/hover",
+                "shutdown",
+                "exit",
+                "bye"
+            ]
+        );
+    }
+}
@@ -0,0 +1,6 @@
+mod client;
+mod rpc;
+mod server;
+
+pub use client::{Client, CompletionItem, Location, Pending, TextEdit};
+pub use server::server_for;
@@ -0,0 +1,65 @@
+use std::env;
+
+/// 一种语言服务器的启动方式
+pub struct Server {
+    /// 服务器名，也是它给出的诊断的来源
+    pub name: &'static str,
+    /// 覆盖启动命令的环境变量
+    env: &'static str,
+    command: &'static [&'static str],
+    /// (扩展名, languageId)
+    languages: &'static [(&'static str, &'static str)],
+}
+
+const SERVERS: [Server; 3] = [
+    Server {
+        name: "rust-analyzer",
+        env: "IDE_TERMUX_LSP_RUST",
+        command: &["rust-analyzer"],
+        languages: &[("rs", "rust")],
+    },
+    Server {
+        name: "clangd",
+        env: "IDE_TERMUX_LSP_C",
+        command: &["clangd"],
+        languages: &[
+            ("c", "c"),
+            ("h", "c"),
+            ("cc", "cpp"),
+            ("cpp", "cpp"),
+            ("cxx", "cpp"),
+            ("hh", "cpp"),
+            ("hpp", "cpp"),
+        ],
+    },
+    Server {
+        name: "pyright",
+        env: "IDE_TERMUX_LSP_PYTHON",
+        command: &["pyright-langserver", "--stdio"],
+        languages: &[("py", "python")],
+    },
+];
+
+impl Server {
+    /// 启动命令及参数，环境变量中的命令按空白分割
+    pub fn command(&self) -> Vec<String> {
+        match env::var(self.env) {
+            Ok(command) if !command.trim().is_empty() => {
+                command.split_whitespace().map(str::to_string).collect()
+            }
+            _ => self.command.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+/// 按文件名找到负责的服务器和文件的languageId
+pub fn server_for(file_name: &str) -> Option<(&'static Server, &'static str)> {
+    let (_, extension) = file_name.rsplit_once('.')?;
+    SERVERS.iter().find_map(|server| {
+        server
+            .languages
+            .iter()
+            .find(|(ext, _)| *ext == extension)
+            .map(|(_, language)| (server, *language))
+    })
+}
@@ -1,3 +1,4 @@
+#[cfg(not(test))]
 use lazy_static::lazy_static;
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::{
@@ -5,57 +6,74 @@ use tokio::sync::{
     RwLock,
 };
 
-use crate::components::editor::Editing;
+use crate::{components::editor::Editing, diagnostic::FileMarkers, lsp::TextEdit};
 
+#[cfg(not(test))]
 lazy_static! {
     static ref PIPECTL: Arc<RwLock<NamedPipe>> = Arc::new(RwLock::new(NamedPipe {
         map: HashMap::new()
     }));
 }
 
+// 测试并行运行在各自的线程中，每个线程用自己的管道表，互不干扰
+#[cfg(test)]
+thread_local! {
+    static PIPECTL: std::cell::RefCell<NamedPipe> = std::cell::RefCell::new(NamedPipe {
+        map: HashMap::new()
+    });
+}
+
+type PipeEnds = (
+    Arc<RwLock<Sender<PipeObject>>>,
+    Arc<RwLock<Receiver<PipeObject>>>,
+);
+
 pub struct NamedPipe {
-    map: HashMap<
-        String,
-        (
-            Arc<RwLock<Sender<PipeObject>>>,
-            Arc<RwLock<Receiver<PipeObject>>>,
-        ),
-    >,
+    map: HashMap<String, PipeEnds>,
 }
 
 impl NamedPipe {
     pub fn open_sender(name: String) -> Arc<RwLock<Sender<PipeObject>>> {
-        if PIPECTL.blocking_read().map.contains_key(&name) {
-            Arc::clone(&PIPECTL.blocking_read().map.get(&name).unwrap().0)
-        } else {
-            let (sender, receiver) = mpsc::channel(4);
-            let sender = Arc::new(RwLock::new(sender));
-            PIPECTL
-                .blocking_write()
-                .map
-                .insert(name, (Arc::clone(&sender), Arc::new(RwLock::new(receiver))));
-            sender
-        }
+        Self::with_ends(name, |ends| Arc::clone(&ends.0))
     }
 
     pub fn open_receiver(name: String) -> Arc<RwLock<Receiver<PipeObject>>> {
-        if PIPECTL.blocking_read().map.contains_key(&name) {
-            Arc::clone(&PIPECTL.blocking_read().map.get(&name).unwrap().1)
-        } else {
-            let (sender, receiver) = mpsc::channel(4);
-            let receiver = Arc::new(RwLock::new(receiver));
-            PIPECTL
-                .blocking_write()
-                .map
-                .insert(name, (Arc::new(RwLock::new(sender)), Arc::clone(&receiver)));
-            receiver
-        }
+        Self::with_ends(name, |ends| Arc::clone(&ends.1))
+    }
+
+    /// 取得名为name的管道的两端，不存在时先创建
+    fn with_ends<T>(name: String, f: impl FnOnce(&PipeEnds) -> T) -> T {
+        let open = |pipes: &mut NamedPipe| {
+            f(pipes.map.entry(name).or_insert_with(|| {
+                let (sender, receiver) = mpsc::channel(4);
+                (
+                    Arc::new(RwLock::new(sender)),
+                    Arc::new(RwLock::new(receiver)),
+                )
+            }))
+        };
+        #[cfg(not(test))]
+        return open(&mut PIPECTL.blocking_write());
+        #[cfg(test)]
+        return PIPECTL.with(|pipes| open(&mut pipes.borrow_mut()));
     }
 }
 
 pub enum PipeObject {
     Editing(Arc<RwLock<Editing>>),
     MoveFocus,
+    /// 打开项目中的文件，路径相对于项目根目录，可以指定光标位置
+    OpenFile(Vec<String>, Option<(usize, usize)>),
+    /// 后台任务有了新结果，需要重新渲染
+    Redraw,
+    /// 关闭当前的弹出窗口
+    ClosePopup,
+    /// 某个来源的全部诊断，按相对于项目根目录的路径分组，替换该来源之前的诊断
+    Diagnostics(&'static str, FileMarkers),
+    /// 修改项目中的文件，路径相对于项目根目录
+    ApplyEdits(Vec<(Vec<String>, Vec<TextEdit>)>),
+    /// 执行命令面板中选中的命令
+    RunCommand(String),
 }
 
 unsafe impl Sync for PipeObject {}
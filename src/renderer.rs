@@ -1,50 +1,383 @@
 use crossterm::{
     cursor, queue,
-    style::{self, StyledContent},
+    style::{self, Attribute, ContentStyle, StyledContent},
 };
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use crate::text_buffer::char_width;
+
+/// 只能画出一半的宽字符用它代替
+const CUT: char = ' ';
+
+/// 屏幕上的一格
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    ch: char,
+    style: ContentStyle,
+    /// 是前一格宽字符的右半边
+    wide_tail: bool,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            ch: ' ',
+            style: ContentStyle::default(),
+            wide_tail: false,
+        }
+    }
+}
+
+/// 整个屏幕的字符格
+#[derive(Clone)]
+pub struct Buffer {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Buffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Buffer {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// 第y行的文字，宽字符的右半边不重复出现
+    pub fn line(&self, y: usize) -> String {
+        self.cells[y * self.width..(y + 1) * self.width]
+            .iter()
+            .filter(|cell| !cell.wide_tail)
+            .map(|cell| cell.ch)
+            .collect()
+    }
+
+    /// (x, y)处的字符和样式，宽字符的右半边为空格和左半边的样式
+    pub fn cell(&self, x: usize, y: usize) -> Option<(char, ContentStyle)> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let cell = self.cells[y * self.width + x];
+        Some((cell.ch, cell.style))
+    }
+
+    /// 清空所有格子
+    pub fn clear(&mut self) {
+        self.cells.fill(Cell::default());
+    }
+
+    /// 在(x, y)处放一个宽为width的字符，被盖住一半的宽字符换成空格
+    fn put(&mut self, x: usize, y: usize, ch: char, width: usize, style: ContentStyle) {
+        if y >= self.height || x + width > self.width {
+            return;
+        }
+        let row = y * self.width;
+        if self.cells[row + x].wide_tail && x > 0 {
+            self.cells[row + x - 1].ch = ' ';
+        }
+        let end = x + width;
+        if end < self.width && self.cells[row + end].wide_tail {
+            self.cells[row + end] = Cell {
+                ch: ' ',
+                wide_tail: false,
+                ..self.cells[row + end]
+            };
+        }
+        self.cells[row + x] = Cell {
+            ch,
+            style,
+            wide_tail: false,
+        };
+        for tail in &mut self.cells[row + x + 1..row + end] {
+            *tail = Cell {
+                ch: ' ',
+                style,
+                wide_tail: true,
+            };
+        }
+    }
+
+    /// 把与上一帧prev不同的格子写到out，相邻的格子合并输出，样式不变时不重复设置
+    pub fn flush_diff(&self, prev: &Buffer, out: &mut impl Write) -> std::io::Result<()> {
+        let same_size = prev.width == self.width && prev.height == self.height;
+        let mut style = None;
+        for y in 0..self.height {
+            // 终端光标当前所在的列
+            let mut at = None;
+            let mut run = String::new();
+            let row = &self.cells[y * self.width..(y + 1) * self.width];
+            let unchanged = |x: usize| same_size && prev.cells[y * self.width + x] == row[x];
+            for (x, cell) in row.iter().enumerate() {
+                // 宽字符的右半边变了时也要重画整个字符
+                if cell.wide_tail || (x..x + char_width(cell.ch).max(1)).all(unchanged) {
+                    continue;
+                }
+                if at != Some(x) || style != Some(cell.style) {
+                    queue!(out, style::Print(&run))?;
+                    run.clear();
+                }
+                if at != Some(x) {
+                    queue!(out, cursor::MoveTo(x as u16, y as u16))?;
+                }
+                if style != Some(cell.style) {
+                    queue!(
+                        out,
+                        style::SetAttribute(Attribute::Reset),
+                        style::SetStyle(cell.style)
+                    )?;
+                    style = Some(cell.style);
+                }
+                run.push(cell.ch);
+                at = Some(x + char_width(cell.ch).max(1));
+            }
+            queue!(out, style::Print(&run))?;
+        }
+        // 什么都没画时不输出任何内容
+        if style.is_some() {
+            queue!(out, style::SetAttribute(Attribute::Reset))?;
+        }
+        Ok(())
+    }
+}
+
+/// 按显示宽度把chars截断到width列，返回截断后的文字和占用的列数，控制字符被去掉
+pub fn truncate(chars: impl IntoIterator<Item = char>, width: usize) -> (String, usize) {
+    let mut res = String::new();
+    let mut used = 0;
+    for ch in chars.into_iter().filter(|ch| !ch.is_control()) {
+        let w = char_width(ch);
+        if used + w > width {
+            break;
+        }
+        res.push(ch);
+        used += w;
+    }
+    (res, used)
+}
+
+/// 把chars截断或用空格补齐到正好width列
+pub fn fit(chars: impl IntoIterator<Item = char>, width: usize) -> String {
+    let (mut res, used) = truncate(chars, width);
+    res.extend(std::iter::repeat_n(' ', width - used));
+    res
+}
 
 pub struct Renderer {
     pub x: usize,
     pub y: usize,
     width: usize,
     height: usize,
+    buffer: Rc<RefCell<Buffer>>,
 }
 
 impl Renderer {
-    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+    pub fn new(
+        buffer: Rc<RefCell<Buffer>>,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Self {
         Renderer {
             x,
             y,
             width,
             height,
+            buffer,
         }
     }
 
+    /// 在自己的(x, y)处开一个子区域，超出自己的部分被裁掉
+    pub fn sub(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        Renderer::new(
+            Rc::clone(&self.buffer),
+            self.x + x,
+            self.y + y,
+            width.min(self.width - x),
+            height.min(self.height - y),
+        )
+    }
+
     pub fn get_size(&self) -> (usize, usize) {
         (self.width, self.height)
     }
 
     pub fn set(&self, x: usize, y: usize, ch: StyledContent<char>) {
-        if x > self.width || y > self.height {
+        if y >= self.height {
             return;
         }
-        queue!(
-            std::io::stdout(),
-            cursor::MoveTo((self.x + x) as u16, (self.y + y) as u16),
-            style::PrintStyledContent(ch)
-        )
-        .unwrap();
+        self.put(x, y, *ch.content(), *ch.style());
     }
 
+    /// 从(x, y)开始写st，超出右边界的部分被截掉
     pub fn set_section(&self, x: usize, y: usize, st: StyledContent<String>) {
-        if x > self.width || y > self.height {
+        if y >= self.height {
             return;
         }
+        let mut x = x;
+        for ch in st.content().chars() {
+            if x >= self.width {
+                break;
+            }
+            x += self.put(x, y, ch, *st.style());
+        }
+    }
+
+    /// 在区域内写一个字符，返回占用的列数，跨过右边界的宽字符换成CUT
+    fn put(&self, x: usize, y: usize, ch: char, style: ContentStyle) -> usize {
+        let width = char_width(ch);
+        if width == 0 || x >= self.width {
+            return 0;
+        }
+        let mut buffer = self.buffer.borrow_mut();
+        if x + width > self.width {
+            for x in x..self.width {
+                buffer.put(self.x + x, self.y + y, CUT, 1, style);
+            }
+            return self.width - x;
+        }
+        buffer.put(self.x + x, self.y + y, ch, width, style);
+        width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::style::{Color, Stylize};
+
+    fn flushed(frame: &Buffer, prev: &Buffer) -> Vec<u8> {
+        let mut out = Vec::new();
+        frame.flush_diff(prev, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn unchanged_frame_writes_nothing() {
+        let mut frame = Buffer::new(6, 3);
+        frame.put(1, 1, '中', 2, ContentStyle::default());
+        assert!(flushed(&frame, &frame.clone()).is_empty());
+    }
+
+    #[test]
+    fn one_changed_cell_writes_one_move() {
+        let prev = Buffer::new(6, 3);
+        let mut frame = prev.clone();
+        let style = ContentStyle::new().red();
+        frame.put(4, 2, 'x', 1, style);
+
+        let mut expected = Vec::new();
         queue!(
-            std::io::stdout(),
-            cursor::MoveTo((self.x + x) as u16, (self.y + y) as u16),
-            style::PrintStyledContent(st)
+            expected,
+            cursor::MoveTo(4, 2),
+            style::SetAttribute(Attribute::Reset),
+            style::SetStyle(style),
+            style::Print("x"),
+            style::SetAttribute(Attribute::Reset)
         )
         .unwrap();
+        assert_eq!(flushed(&frame, &prev), expected);
+    }
+
+    #[test]
+    fn adjacent_changes_share_one_move() {
+        let prev = Buffer::new(6, 1);
+        let mut frame = prev.clone();
+        frame.put(1, 0, 'a', 1, ContentStyle::default());
+        frame.put(2, 0, '中', 2, ContentStyle::default());
+        frame.put(4, 0, 'b', 1, ContentStyle::default());
+
+        let mut expected = Vec::new();
+        queue!(
+            expected,
+            cursor::MoveTo(1, 0),
+            style::SetAttribute(Attribute::Reset),
+            style::SetStyle(ContentStyle::default()),
+            style::Print("a中b"),
+            style::SetAttribute(Attribute::Reset)
+        )
+        .unwrap();
+        assert_eq!(flushed(&frame, &prev), expected);
+    }
+
+    #[test]
+    fn truncate_by_display_width() {
+        assert_eq!(truncate("abc".chars(), 2), ("ab".to_string(), 2));
+        // 放不下的宽字符整个去掉
+        assert_eq!(truncate("a中文".chars(), 4), ("a中".to_string(), 3));
+        assert_eq!(truncate("a\tb\n".chars(), 5), ("ab".to_string(), 2));
+    }
+
+    #[test]
+    fn fit_pads_to_width() {
+        assert_eq!(fit("ab".chars(), 4), "ab  ");
+        assert_eq!(fit("中文".chars(), 3), "中 ");
+        assert_eq!(fit("".chars(), 0), "");
+    }
+
+    fn screen(width: usize, height: usize) -> (Rc<RefCell<Buffer>>, Renderer) {
+        let buffer = Rc::new(RefCell::new(Buffer::new(width, height)));
+        let renderer = Renderer::new(Rc::clone(&buffer), 0, 0, width, height);
+        (buffer, renderer)
+    }
+
+    #[test]
+    fn section_past_right_edge_is_cut() {
+        let (buffer, screen) = screen(10, 2);
+        let area = screen.sub(2, 1, 4, 1);
+        area.set_section(1, 0, "abcdef".to_string().stylize());
+        assert_eq!(buffer.borrow().line(1), "   abc    ");
+        // 区域下方的行也不会被写到
+        area.set_section(0, 1, "x".to_string().stylize());
+        assert_eq!(buffer.borrow().line(0), " ".repeat(10));
+    }
+
+    #[test]
+    fn wide_char_across_edge_does_not_bleed() {
+        let (buffer, screen) = screen(6, 1);
+        let left = screen.sub(0, 0, 3, 1);
+        let right = screen.sub(3, 0, 3, 1);
+        right.set_section(0, 0, "xyz".to_string().stylize());
+        left.set_section(0, 0, "ab中".to_string().red());
+        assert_eq!(buffer.borrow().line(0), format!("ab{CUT}xyz"));
+        let (ch, style) = buffer.borrow().cell(2, 0).unwrap();
+        assert_eq!((ch, style.foreground_color), (CUT, Some(Color::Red)));
+        assert_eq!(buffer.borrow().cell(3, 0).unwrap().0, 'x');
+    }
+
+    #[test]
+    fn x_at_width_is_rejected() {
+        let (buffer, screen) = screen(6, 1);
+        let left = screen.sub(0, 0, 3, 1);
+        left.set(3, 0, 'q'.stylize());
+        left.set_section(3, 0, "q".to_string().stylize());
+        left.set(2, 0, 'p'.stylize());
+        assert_eq!(buffer.borrow().line(0), "  p   ");
+    }
+
+    #[test]
+    fn child_outside_parent_is_clamped() {
+        let (buffer, screen) = screen(10, 4);
+        let parent = screen.sub(2, 1, 4, 2);
+        let child = parent.sub(3, 1, 5, 5);
+        assert_eq!((child.x, child.y, child.get_size()), (5, 2, (1, 1)));
+        child.set_section(0, 0, "xyz".to_string().stylize());
+        assert_eq!(buffer.borrow().line(2), "     x    ");
+
+        // 完全在父区域外的子区域大小为0，什么都写不进去
+        let outside = parent.sub(10, 10, 2, 2);
+        assert_eq!(outside.get_size(), (0, 0));
+        outside.set(0, 0, 'q'.stylize());
+        outside.set_section(0, 0, "q".to_string().stylize());
+        let buffer = buffer.borrow();
+        assert!((0..4).all(|y| !buffer.line(y).contains('q')));
     }
 }
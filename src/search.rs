@@ -0,0 +1,257 @@
+use regex::{Regex, RegexBuilder};
+use std::fs;
+
+use crate::text_buffer::TextBuffer;
+
+#[derive(Clone, Copy, Default)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub regex: bool,
+}
+
+/// 编译好的查询，匹配不跨行
+pub struct Matcher {
+    regex: Regex,
+    options: SearchOptions,
+}
+
+impl Matcher {
+    /// 空查询返回Ok(None)，正则写错时返回错误信息
+    pub fn new(query: &str, options: SearchOptions) -> Result<Option<Self>, String> {
+        if query.is_empty() {
+            return Ok(None);
+        }
+        let pattern = if options.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        let pattern = if options.whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!options.case_sensitive)
+            .build()
+            .map(|regex| Some(Matcher { regex, options }))
+            .map_err(|e| e.to_string().lines().last().unwrap_or_default().to_string())
+    }
+
+    /// 一行中所有非空匹配的字符下标区间
+    pub fn find_in_line(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut res = Vec::new();
+        // 字节偏移转换为字符下标，匹配按顺序出现所以只需向前数
+        let mut chars = 0;
+        let mut byte = 0;
+        for m in self.regex.find_iter(line).filter(|m| !m.is_empty()) {
+            chars += line[byte..m.start()].chars().count();
+            let start = chars;
+            chars += m.as_str().chars().count();
+            byte = m.end();
+            res.push((start, chars));
+        }
+        res
+    }
+
+    /// 从from开始查找下一个(forward)或上一个匹配，到头后绕回
+    ///
+    /// 向后查找包括从from开始的匹配，向前查找只找开始于from之前的匹配；
+    /// 返回匹配所在行和字符下标区间
+    pub fn find(
+        &self,
+        buffer: &TextBuffer,
+        from: (usize, usize),
+        forward: bool,
+    ) -> Option<(usize, (usize, usize))> {
+        let count = buffer.line_count();
+        if count == 0 {
+            return None;
+        }
+        let from = (from.0.min(count - 1), from.1);
+        for step in 0..=count {
+            let line = if forward {
+                (from.0 + step) % count
+            } else {
+                (from.0 + count * 2 - step) % count
+            };
+            let matches = self.find_in_line(&line_text(buffer, line));
+            let found = match (step, forward) {
+                (0, true) => matches.into_iter().find(|m| m.0 >= from.1),
+                (0, false) => matches.into_iter().rev().find(|m| m.0 < from.1),
+                // 绕回起始行时只看另一侧
+                (s, true) if s == count => matches.into_iter().find(|m| m.0 < from.1),
+                (s, false) if s == count => matches.into_iter().rev().find(|m| m.0 >= from.1),
+                (_, true) => matches.into_iter().next(),
+                (_, false) => matches.into_iter().next_back(),
+            };
+            if let Some(m) = found {
+                return Some((line, m));
+            }
+        }
+        None
+    }
+
+    /// 替换line中range处的匹配得到的文本，正则模式下展开$1之类的分组引用
+    pub fn replacement(&self, line: &str, range: (usize, usize), with: &str) -> String {
+        if !self.options.regex {
+            return with.to_string();
+        }
+        let start = line
+            .char_indices()
+            .nth(range.0)
+            .map(|(b, _)| b)
+            .unwrap_or(line.len());
+        match self.regex.captures_at(line, start) {
+            Some(caps) => {
+                let mut res = String::new();
+                caps.expand(with, &mut res);
+                res
+            }
+            None => with.to_string(),
+        }
+    }
+}
+
+/// 不包括行尾换行符的一行文本
+pub fn line_text(buffer: &TextBuffer, line: usize) -> String {
+    buffer
+        .line(line)
+        .map(|l| l.chars().take(buffer.content_len(line)).collect())
+        .unwrap_or_default()
+}
+
+/// 文件中含有匹配的一行，ranges为行内各个匹配的字符下标区间
+pub struct LineMatch {
+    pub line: usize,
+    pub text: String,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+/// 在文件中查找所有匹配，二进制文件和无法读取的文件返回None
+pub fn grep_file(path: &str, matcher: &Matcher) -> Option<Vec<LineMatch>> {
+    let bytes = fs::read(path).ok()?;
+    // 和git一样只检查开头是否有NUL
+    if bytes.iter().take(8000).any(|&b| b == 0) {
+        return None;
+    }
+    let content = String::from_utf8(bytes).ok()?;
+    Some(
+        content
+            .lines()
+            .enumerate()
+            .filter_map(|(line, text)| {
+                let ranges = matcher.find_in_line(text);
+                (!ranges.is_empty()).then(|| LineMatch {
+                    line,
+                    text: text.to_string(),
+                    ranges,
+                })
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(query: &str, case_sensitive: bool, whole_word: bool, regex: bool) -> Matcher {
+        let options = SearchOptions {
+            case_sensitive,
+            whole_word,
+            regex,
+        };
+        Matcher::new(query, options).unwrap().unwrap()
+    }
+
+    fn buffer(text: &str) -> TextBuffer {
+        TextBuffer::from_reader(text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn empty_query_and_bad_regex() {
+        assert!(Matcher::new("", SearchOptions::default())
+            .unwrap()
+            .is_none());
+        let options = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        assert!(Matcher::new("(", options).is_err());
+    }
+
+    #[test]
+    fn literal_and_case() {
+        let m = matcher("a.b", false, false, false);
+        assert_eq!(m.find_in_line("A.B axb a.b"), [(0, 3), (8, 11)]);
+        let m = matcher("a.b", true, false, false);
+        assert_eq!(m.find_in_line("A.B axb a.b"), [(8, 11)]);
+    }
+
+    #[test]
+    fn whole_word() {
+        let m = matcher("foo", true, true, false);
+        assert_eq!(m.find_in_line("foo foobar _foo foo."), [(0, 3), (16, 19)]);
+        let m = matcher("fo+|ba", true, true, true);
+        assert_eq!(m.find_in_line("fooo bar ba"), [(0, 4), (9, 11)]);
+    }
+
+    #[test]
+    fn ranges_are_char_indices() {
+        let m = matcher("x", true, false, false);
+        assert_eq!(m.find_in_line("中x文x"), [(1, 2), (3, 4)]);
+        // 空匹配被忽略
+        let m = matcher("y*", true, false, true);
+        assert_eq!(m.find_in_line("ayy"), [(1, 3)]);
+    }
+
+    #[test]
+    fn find_wraps_around() {
+        let buf = buffer("ab\nxx ab\nab\n");
+        let m = matcher("ab", true, false, false);
+        assert_eq!(m.find(&buf, (0, 0), true), Some((0, (0, 2))));
+        assert_eq!(m.find(&buf, (0, 1), true), Some((1, (3, 5))));
+        assert_eq!(m.find(&buf, (2, 1), true), Some((0, (0, 2))));
+        assert_eq!(m.find(&buf, (1, 3), false), Some((0, (0, 2))));
+        assert_eq!(m.find(&buf, (0, 0), false), Some((2, (0, 2))));
+        // 只有一个匹配时绕一圈回到它
+        let m = matcher("xx", true, false, false);
+        assert_eq!(m.find(&buf, (1, 1), true), Some((1, (0, 2))));
+        assert_eq!(m.find(&buf, (1, 0), false), Some((1, (0, 2))));
+        let m = matcher("zz", true, false, false);
+        assert_eq!(m.find(&buf, (0, 0), true), None);
+    }
+
+    #[test]
+    fn replacement_expands_groups_in_regex_mode() {
+        let m = matcher(r"(\w+)=(\w+)", true, false, true);
+        let line = "中 a=b c=d";
+        assert_eq!(m.replacement(line, (6, 9), "$2=$1"), "d=c");
+        let m = matcher("a", true, false, false);
+        assert_eq!(m.replacement("a", (0, 1), "$1"), "$1");
+    }
+
+    #[test]
+    fn grep_file_skips_binary_files() {
+        let dir = std::env::temp_dir().join(format!("ide-termux-grep-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text = dir.join("text.rs");
+        let binary = dir.join("binary");
+        fs::write(&text, "fn main() {}\n\nlet main = 1;\n").unwrap();
+        fs::write(&binary, b"main\0main").unwrap();
+
+        let m = matcher("main", true, false, false);
+        let found = grep_file(text.to_str().unwrap(), &m).unwrap();
+        let lines = found
+            .iter()
+            .map(|l| (l.line, l.ranges.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(0, vec![(3, 7)]), (2, vec![(4, 8)])]);
+        assert_eq!(found[1].text, "let main = 1;");
+        assert!(grep_file(binary.to_str().unwrap(), &m).is_none());
+        assert!(grep_file(dir.join("missing").to_str().unwrap(), &m).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
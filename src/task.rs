@@ -0,0 +1,233 @@
+use serde_json::Value;
+use std::{
+    io::{self, BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    diagnostic::Severity,
+    named_pipe::{NamedPipe, PipeObject},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TaskKind {
+    Build,
+    Test,
+    Clippy,
+}
+
+impl TaskKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaskKind::Build => "cargo build",
+            TaskKind::Test => "cargo test",
+            TaskKind::Clippy => "cargo clippy",
+        }
+    }
+
+    fn args(&self) -> &'static [&'static str] {
+        match self {
+            TaskKind::Build => &["build"],
+            TaskKind::Test => &["test"],
+            TaskKind::Clippy => &["clippy", "--all-targets"],
+        }
+    }
+}
+
+/// 编译器给出的一条诊断
+#[derive(Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// cargo输出的路径，相对于workspace根目录
+    pub file: String,
+    /// 主要位置的起止(行号, 字符下标)，都从0开始
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub message: String,
+}
+
+pub enum TaskEvent {
+    Diagnostic(Diagnostic),
+    /// 进程退出，参数为是否成功以及失败时stderr的最后一行
+    Finished(bool, Option<String>),
+}
+
+/// 在后台运行的cargo命令，放下时结束进程
+pub struct Task {
+    pub kind: TaskKind,
+    child: Arc<Mutex<Child>>,
+    receiver: Receiver<TaskEvent>,
+}
+
+impl Task {
+    /// 在dir中以--message-format=json运行kind对应的cargo命令
+    pub fn spawn(kind: TaskKind, dir: &str) -> io::Result<Self> {
+        let mut child = Command::new("cargo")
+            .args(kind.args())
+            .arg("--message-format=json")
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+        let child = Arc::new(Mutex::new(child));
+        let (sender, receiver) = mpsc::channel();
+        let redraw_sender = NamedPipe::open_sender(String::from("Redraw"));
+        // stderr单独读，避免管道写满后cargo卡住
+        let stderr = thread::spawn(move || {
+            BufReader::new(stderr)
+                .lines()
+                .map_while(Result::ok)
+                .filter(|line| !line.trim().is_empty())
+                .last()
+        });
+        let waiter = Arc::clone(&child);
+        thread::spawn(move || {
+            let mut seen = Vec::new();
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                // cargo test时测试程序的普通输出也在stdout中
+                let Some(diagnostic) = parse_message(&line) else {
+                    continue;
+                };
+                // 同一文件被多个target编译时诊断会重复
+                if seen.contains(&diagnostic) {
+                    continue;
+                }
+                seen.push(diagnostic.clone());
+                if sender.send(TaskEvent::Diagnostic(diagnostic)).is_err() {
+                    return;
+                }
+                let _ = redraw_sender.blocking_read().try_send(PipeObject::Redraw);
+            }
+            let last_error = stderr.join().ok().flatten();
+            let success = waiter
+                .lock()
+                .unwrap()
+                .wait()
+                .is_ok_and(|status| status.success());
+            let _ = sender.send(TaskEvent::Finished(
+                success,
+                if success { None } else { last_error },
+            ));
+            let _ = redraw_sender.blocking_read().try_send(PipeObject::Redraw);
+        });
+        Ok(Task {
+            kind,
+            child,
+            receiver,
+        })
+    }
+
+    /// 取出已经收到的事件
+    pub fn events(&self) -> Vec<TaskEvent> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        let _ = self.child.lock().unwrap().kill();
+    }
+}
+
+/// 解析一行cargo的JSON输出，只取带位置的编译器诊断
+fn parse_message(line: &str) -> Option<Diagnostic> {
+    let value = serde_json::from_str::<Value>(line).ok()?;
+    if value["reason"] != "compiler-message" {
+        return None;
+    }
+    let message = &value["message"];
+    let severity = match message["level"].as_str()? {
+        "error" | "error: internal compiler error" => Severity::Error,
+        "warning" => Severity::Warning,
+        "note" => Severity::Note,
+        "help" => Severity::Help,
+        _ => return None,
+    };
+    let span = message["spans"]
+        .as_array()?
+        .iter()
+        .find(|span| span["is_primary"] == true)?;
+    let position = |line: &str, column: &str| {
+        Some((
+            (span[line].as_u64()? as usize).saturating_sub(1),
+            (span[column].as_u64()? as usize).saturating_sub(1),
+        ))
+    };
+    Some(Diagnostic {
+        severity,
+        file: span["file_name"].as_str()?.to_string(),
+        start: position("line_start", "column_start")?,
+        end: position("line_end", "column_end")?,
+        message: message["message"].as_str()?.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiler_message(level: &str, primary: bool) -> String {
+        serde_json::json!({
+            "reason": "compiler-message",
+            "package_id": "demo 0.1.0",
+            "message": {
+                "level": level,
+                "message": "unused variable: `x`",
+                "spans": [
+                    {
+                        "file_name": "src/other.rs",
+                        "is_primary": false,
+                        "line_start": 1, "line_end": 1,
+                        "column_start": 1, "column_end": 2
+                    },
+                    {
+                        "file_name": "src/main.rs",
+                        "is_primary": primary,
+                        "line_start": 3, "line_end": 4,
+                        "column_start": 9, "column_end": 1
+                    }
+                ]
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn parses_the_primary_span() {
+        let d = parse_message(&compiler_message("warning", true)).unwrap();
+        assert!(d.severity == Severity::Warning);
+        assert_eq!(d.file, "src/main.rs");
+        assert_eq!((d.start, d.end), ((2, 8), (3, 0)));
+        assert_eq!(d.message, "unused variable: `x`");
+    }
+
+    #[test]
+    fn levels() {
+        for (level, severity) in [
+            ("error", Severity::Error),
+            ("error: internal compiler error", Severity::Error),
+            ("note", Severity::Note),
+            ("help", Severity::Help),
+        ] {
+            let d = parse_message(&compiler_message(level, true)).unwrap();
+            assert!(d.severity == severity);
+        }
+        assert!(parse_message(&compiler_message("failure-note", true)).is_none());
+    }
+
+    #[test]
+    fn skips_other_lines() {
+        // 没有主要位置的诊断，例如"aborting due to previous error"
+        assert!(parse_message(&compiler_message("error", false)).is_none());
+        assert!(parse_message(r#"{"reason":"build-finished","success":true}"#).is_none());
+        assert!(parse_message("running 3 tests").is_none());
+    }
+}
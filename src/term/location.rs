@@ -0,0 +1,81 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // 例如rustc的 --> src/lib.rs:42:7 和gcc的 main.c:3:5: error
+    static ref LOCATION: Regex =
+        Regex::new(r"([\w.~+@-]*[/.][\w./~+@-]*):(\d+)(?::(\d+))?").unwrap();
+}
+
+/// 输出中的文件位置
+pub struct Location {
+    pub path: String,
+    /// 从1开始的行号和列号，没有列号时为1
+    pub line: usize,
+    pub column: usize,
+    /// 在文本中的字符下标范围
+    pub range: (usize, usize),
+}
+
+/// 找出text中所有 path:line[:col] 形式的位置
+pub fn find_locations(text: &str) -> Vec<Location> {
+    LOCATION
+        .captures_iter(text)
+        .filter_map(|caps| {
+            let whole = caps.get(0)?;
+            let line = caps[2].parse().ok().filter(|&n| n > 0)?;
+            let column = match caps.get(3) {
+                Some(c) => c.as_str().parse().ok().filter(|&n| n > 0)?,
+                None => 1,
+            };
+            let start = text[..whole.start()].chars().count();
+            Some(Location {
+                path: caps[1].to_string(),
+                line,
+                column,
+                range: (start, start + whole.as_str().chars().count()),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(text: &str) -> Vec<(String, usize, usize, (usize, usize))> {
+        find_locations(text)
+            .into_iter()
+            .map(|l| (l.path, l.line, l.column, l.range))
+            .collect()
+    }
+
+    #[test]
+    fn rustc_and_gcc_locations() {
+        assert_eq!(
+            found("  --> src/lib.rs:42:7"),
+            [("src/lib.rs".to_string(), 42, 7, (6, 21))]
+        );
+        assert_eq!(
+            found("main.c:3:5: error: expected ';'"),
+            [("main.c".to_string(), 3, 5, (0, 10))]
+        );
+    }
+
+    #[test]
+    fn column_is_optional() {
+        assert_eq!(
+            found("at ../a/b.py:12 and ~/x.rs:1:2"),
+            [
+                ("../a/b.py".to_string(), 12, 1, (3, 15)),
+                ("~/x.rs".to_string(), 1, 2, (20, 30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_count_characters_and_zero_is_rejected() {
+        assert_eq!(found("错误 a.rs:1")[0].3, (3, 9));
+        assert!(found("a.rs:0:1 time 12:30 localhost:8080").is_empty());
+    }
+}
@@ -0,0 +1,9 @@
+mod input;
+mod location;
+mod pty;
+mod screen;
+
+pub use input::key_bytes;
+pub use location::{find_locations, Location};
+pub use pty::Pty;
+pub use screen::{Cell, Screen, Style};
@@ -0,0 +1,135 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    ptr,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::named_pipe::{NamedPipe, PipeObject};
+
+/// 运行在伪终端上的子进程
+///
+/// 读取线程把子进程的输出送进output，每次有输出时请求重绘
+pub struct Pty {
+    master: File,
+    child: Child,
+    output: Receiver<Vec<u8>>,
+}
+
+impl Pty {
+    pub fn spawn(program: &str, cols: usize, rows: usize) -> io::Result<Self> {
+        let mut master = -1;
+        let mut slave = -1;
+        let size = winsize(cols, rows);
+        // SAFETY: 传入的指针都指向有效的局部变量
+        if unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null(),
+                &size,
+            )
+        } != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: openpty成功后两个fd都归我们所有，master不应被子进程继承
+        let (master, slave) = unsafe {
+            libc::fcntl(master, libc::F_SETFD, libc::FD_CLOEXEC);
+            (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+        };
+        let mut command = Command::new(program);
+        command
+            .env("TERM", "xterm-256color")
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // SAFETY: 只调用async-signal-safe的函数
+        unsafe {
+            command.pre_exec(|| {
+                // 新会话，并以伪终端作为控制终端
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        let master = File::from(master);
+        let mut reader = master.try_clone()?;
+        let (sender, output) = mpsc::channel();
+        let redraw_sender = NamedPipe::open_sender(String::from("Redraw"));
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // 子进程退出后读取会返回EIO
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 || sender.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+                let _ = redraw_sender.blocking_read().try_send(PipeObject::Redraw);
+            }
+            drop(sender);
+            let _ = redraw_sender.blocking_read().try_send(PipeObject::Redraw);
+        });
+        Ok(Pty {
+            master,
+            child,
+            output,
+        })
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.master.write_all(bytes)
+    }
+
+    pub fn resize(&self, cols: usize, rows: usize) -> io::Result<()> {
+        let size = winsize(cols, rows);
+        // SAFETY: master是有效的fd，size是有效的winsize
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &size) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// 子进程的当前目录
+    pub fn cwd(&self) -> Option<PathBuf> {
+        fs::read_link(format!("/proc/{}/cwd", self.child.id())).ok()
+    }
+
+    /// 取出目前为止的输出，子进程已经退出并且输出读完时返回None
+    pub fn read(&self) -> Option<Vec<u8>> {
+        let mut res = Vec::new();
+        loop {
+            match self.output.try_recv() {
+                Ok(bytes) => res.extend(bytes),
+                Err(mpsc::TryRecvError::Empty) => return Some(res),
+                Err(mpsc::TryRecvError::Disconnected) if res.is_empty() => return None,
+                Err(mpsc::TryRecvError::Disconnected) => return Some(res),
+            }
+        }
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn winsize(cols: usize, rows: usize) -> libc::winsize {
+    libc::winsize {
+        ws_row: rows.clamp(1, u16::MAX as usize) as u16,
+        ws_col: cols.clamp(1, u16::MAX as usize) as u16,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
@@ -0,0 +1,900 @@
+use crossterm::style::{Attribute, Attributes, Color, ContentStyle};
+use std::{collections::VecDeque, mem};
+use vte::{Params, Parser, Perform};
+
+use crate::text_buffer::char_width;
+
+const TAB_WIDTH: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            fg: Color::Reset,
+            bg: Color::Reset,
+            bold: false,
+            dim: false,
+            italic: false,
+            underline: false,
+            reverse: false,
+        }
+    }
+}
+
+impl Style {
+    pub fn content_style(&self) -> ContentStyle {
+        let (fg, bg) = if self.reverse {
+            // 默认颜色反转后用白底黑字
+            (
+                match self.bg {
+                    Color::Reset => Color::Black,
+                    c => c,
+                },
+                match self.fg {
+                    Color::Reset => Color::Grey,
+                    c => c,
+                },
+            )
+        } else {
+            (self.fg, self.bg)
+        };
+        let mut attributes = Attributes::default();
+        for (on, attr) in [
+            (self.bold, Attribute::Bold),
+            (self.dim, Attribute::Dim),
+            (self.italic, Attribute::Italic),
+            (self.underline, Attribute::Underlined),
+        ] {
+            if on {
+                attributes.set(attr);
+            }
+        }
+        ContentStyle {
+            foreground_color: Some(fg),
+            background_color: Some(bg),
+            underline_color: None,
+            attributes,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Cell {
+    pub ch: char,
+    pub style: Style,
+    /// 宽字符占据的第二列，绘制时跳过
+    pub wide_tail: bool,
+}
+
+impl Cell {
+    fn blank(style: Style) -> Self {
+        // 擦除时保留背景色
+        Cell {
+            ch: ' ',
+            style: Style {
+                bg: style.bg,
+                ..Style::default()
+            },
+            wide_tail: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct SavedCursor {
+    row: usize,
+    col: usize,
+    style: Style,
+    line_drawing: bool,
+}
+
+/// 终端模拟器的屏幕：按VT100/xterm的控制序列维护字符网格
+pub struct Screen {
+    parser: Parser,
+    grid: Grid,
+}
+
+impl Screen {
+    /// scrollback是最多保留的滚出屏幕的行数
+    pub fn new(cols: usize, rows: usize, scrollback: usize) -> Self {
+        Screen {
+            parser: Parser::new(),
+            grid: Grid::new(cols.max(1), rows.max(1), scrollback),
+        }
+    }
+
+    /// 返回这次滚进历史的行数
+    pub fn feed(&mut self, bytes: &[u8]) -> usize {
+        self.grid.scrolled = 0;
+        self.parser.advance(&mut self.grid, bytes);
+        self.grid.scrolled
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.grid.resize(cols.max(1), rows.max(1));
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.grid.cols, self.grid.rows)
+    }
+
+    /// 可以回看的历史行数，备用屏幕上没有历史
+    pub fn history_len(&self) -> usize {
+        if self.grid.primary.is_some() {
+            0
+        } else {
+            self.grid.scrollback.len()
+        }
+    }
+
+    /// 第index行，历史在前，屏幕上的行在后
+    pub fn line(&self, index: usize) -> &[Cell] {
+        let history = self.history_len();
+        if index < history {
+            &self.grid.scrollback[index]
+        } else {
+            &self.grid.cells[index - history]
+        }
+    }
+
+    /// 光标的(列, 行)，隐藏时为None
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        self.grid
+            .cursor_visible
+            .then_some((self.grid.col, self.grid.row))
+    }
+
+    /// 方向键是否使用应用模式(ESC O x)
+    pub fn application_cursor(&self) -> bool {
+        self.grid.application_cursor
+    }
+
+    pub fn bracketed_paste(&self) -> bool {
+        self.grid.bracketed_paste
+    }
+
+    /// 取出需要回复给子进程的数据，例如光标位置报告
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        mem::take(&mut self.grid.responses)
+    }
+}
+
+struct Grid {
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<Cell>>,
+    row: usize,
+    col: usize,
+    /// 写满最后一列后，下一个字符才换行
+    wrap_pending: bool,
+    style: Style,
+    /// 滚动区域的首行和末行
+    scroll_top: usize,
+    scroll_bottom: usize,
+    saved: SavedCursor,
+    /// 切换到备用屏幕时保存的主屏幕
+    primary: Option<(Vec<Vec<Cell>>, SavedCursor)>,
+    autowrap: bool,
+    origin_mode: bool,
+    insert_mode: bool,
+    cursor_visible: bool,
+    application_cursor: bool,
+    bracketed_paste: bool,
+    /// G0字符集为DEC画线字符
+    line_drawing: bool,
+    responses: Vec<u8>,
+    /// 主屏幕滚出顶端的行
+    scrollback: VecDeque<Vec<Cell>>,
+    scrollback_limit: usize,
+    /// 本次feed滚进历史的行数
+    scrolled: usize,
+}
+
+impl Grid {
+    fn new(cols: usize, rows: usize, scrollback_limit: usize) -> Self {
+        Grid {
+            cols,
+            rows,
+            cells: vec![vec![Cell::blank(Style::default()); cols]; rows],
+            row: 0,
+            col: 0,
+            wrap_pending: false,
+            style: Style::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            saved: SavedCursor::default(),
+            primary: None,
+            autowrap: true,
+            origin_mode: false,
+            insert_mode: false,
+            cursor_visible: true,
+            application_cursor: false,
+            bracketed_paste: false,
+            line_drawing: false,
+            responses: Vec::new(),
+            scrollback: VecDeque::new(),
+            scrollback_limit,
+            scrolled: 0,
+        }
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        let blank = Cell::blank(Style::default());
+        let resize_cells = |cells: &mut Vec<Vec<Cell>>, cursor_row: usize| {
+            // 行数变少时从上面去掉，保证光标所在行留在屏幕上
+            let removed = if rows < cells.len() {
+                let remove = (cells.len() - rows).min(cursor_row);
+                let removed = cells.drain(..remove).collect::<Vec<_>>();
+                cells.truncate(rows);
+                removed
+            } else {
+                cells.resize(rows, vec![blank; cols]);
+                Vec::new()
+            };
+            for line in cells.iter_mut() {
+                line.resize(cols, blank);
+            }
+            removed
+        };
+        let removed = resize_cells(&mut self.cells, self.row);
+        self.row -= removed.len();
+        // 主屏幕去掉的行进入历史
+        let removed = match &mut self.primary {
+            Some((cells, saved)) => {
+                let removed = resize_cells(cells, saved.row);
+                saved.row -= removed.len();
+                removed
+            }
+            None => removed,
+        };
+        self.push_history(removed);
+        self.cols = cols;
+        self.rows = rows;
+        self.row = self.row.min(rows - 1);
+        self.col = self.col.min(cols - 1);
+        self.wrap_pending = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+    }
+
+    fn blank(&self) -> Cell {
+        Cell::blank(self.style)
+    }
+
+    fn put_char(&mut self, ch: char) {
+        let ch = if self.line_drawing {
+            line_drawing(ch)
+        } else {
+            ch
+        };
+        let width = char_width(ch);
+        if width == 0 {
+            return;
+        }
+        // 只有一列时宽字符放不下，用空格代替
+        let (ch, width) = if width > self.cols {
+            (' ', 1)
+        } else {
+            (ch, width)
+        };
+        if self.wrap_pending || self.col + width > self.cols {
+            if self.autowrap {
+                self.col = 0;
+                self.line_feed();
+            } else {
+                self.col = self.cols.saturating_sub(width);
+            }
+        }
+        self.wrap_pending = false;
+        if self.insert_mode {
+            self.insert_blanks(width);
+        }
+        let tail = width == 2 && self.col + 1 < self.cols;
+        self.clear_wide(self.row, self.col);
+        if tail {
+            self.clear_wide(self.row, self.col + 1);
+        }
+        let style = self.style;
+        let line = &mut self.cells[self.row];
+        line[self.col] = Cell {
+            ch,
+            style,
+            wide_tail: false,
+        };
+        if tail {
+            line[self.col + 1] = Cell {
+                ch: ' ',
+                style,
+                wide_tail: true,
+            };
+        }
+        if self.col + width >= self.cols {
+            self.col = self.cols - 1;
+            self.wrap_pending = true;
+        } else {
+            self.col += width;
+        }
+    }
+
+    /// 覆盖(row, col)前清掉与它同属一个宽字符的另一半
+    fn clear_wide(&mut self, row: usize, col: usize) {
+        let blank = self.blank();
+        let line = &mut self.cells[row];
+        if line[col].wide_tail && col > 0 {
+            line[col - 1] = blank;
+        } else if col + 1 < line.len() && line[col + 1].wide_tail {
+            line[col + 1] = blank;
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.row + 1 < self.rows {
+            self.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        if self.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.row > 0 {
+            self.row -= 1;
+        }
+    }
+
+    /// 滚动区域内容上移n行，主屏幕顶端滚出的行进入历史
+    fn scroll_up(&mut self, n: usize) {
+        let n = n.min(self.scroll_bottom + 1 - self.scroll_top);
+        let blank = vec![self.blank(); self.cols];
+        let removed = self
+            .cells
+            .drain(self.scroll_top..self.scroll_top + n)
+            .collect::<Vec<_>>();
+        if self.scroll_top == 0 && self.primary.is_none() {
+            self.push_history(removed);
+        }
+        for _ in 0..n {
+            self.cells.insert(self.scroll_bottom + 1 - n, blank.clone());
+        }
+    }
+
+    fn push_history(&mut self, lines: Vec<Vec<Cell>>) {
+        self.scrolled += lines.len();
+        self.scrollback.extend(lines);
+        while self.scrollback.len() > self.scrollback_limit {
+            self.scrollback.pop_front();
+        }
+    }
+
+    /// 滚动区域内容下移n行
+    fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.scroll_bottom + 1 - self.scroll_top);
+        let blank = vec![self.blank(); self.cols];
+        self.cells
+            .drain(self.scroll_bottom + 1 - n..=self.scroll_bottom);
+        for _ in 0..n {
+            self.cells.insert(self.scroll_top, blank.clone());
+        }
+    }
+
+    fn insert_blanks(&mut self, n: usize) {
+        let blank = self.blank();
+        let line = &mut self.cells[self.row];
+        let n = n.min(self.cols - self.col);
+        line.truncate(self.cols - n);
+        line.splice(self.col..self.col, vec![blank; n]);
+    }
+
+    fn delete_chars(&mut self, n: usize) {
+        let blank = self.blank();
+        let line = &mut self.cells[self.row];
+        let n = n.min(self.cols - self.col);
+        line.drain(self.col..self.col + n);
+        line.resize(self.cols, blank);
+    }
+
+    /// 在光标所在行插入n行，只在滚动区域内有效
+    fn insert_lines(&mut self, n: usize) {
+        if self.row < self.scroll_top || self.row > self.scroll_bottom {
+            return;
+        }
+        let top = mem::replace(&mut self.scroll_top, self.row);
+        self.scroll_down(n);
+        self.scroll_top = top;
+        self.col = 0;
+    }
+
+    fn delete_lines(&mut self, n: usize) {
+        if self.row < self.scroll_top || self.row > self.scroll_bottom {
+            return;
+        }
+        let top = mem::replace(&mut self.scroll_top, self.row);
+        self.scroll_up(n);
+        self.scroll_top = top;
+        self.col = 0;
+    }
+
+    fn erase(&mut self, row: usize, cols: std::ops::Range<usize>) {
+        let blank = self.blank();
+        for cell in &mut self.cells[row][cols] {
+            *cell = blank;
+        }
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        let (top, bottom) = if self.origin_mode {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows - 1)
+        };
+        self.row = (top + row).min(bottom);
+        self.col = col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = SavedCursor {
+            row: self.row,
+            col: self.col,
+            style: self.style,
+            line_drawing: self.line_drawing,
+        };
+    }
+
+    fn restore_cursor(&mut self) {
+        self.row = self.saved.row.min(self.rows - 1);
+        self.col = self.saved.col.min(self.cols - 1);
+        self.style = self.saved.style;
+        self.line_drawing = self.saved.line_drawing;
+        self.wrap_pending = false;
+    }
+
+    fn enter_alternate(&mut self) {
+        if self.primary.is_none() {
+            self.save_cursor();
+            let blank = vec![vec![Cell::blank(Style::default()); self.cols]; self.rows];
+            let cells = mem::replace(&mut self.cells, blank);
+            self.primary = Some((cells, self.saved));
+        }
+    }
+
+    fn leave_alternate(&mut self) {
+        if let Some((cells, saved)) = self.primary.take() {
+            self.cells = cells;
+            self.saved = saved;
+            self.restore_cursor();
+        }
+    }
+
+    fn set_mode(&mut self, private: bool, mode: u16, on: bool) {
+        match (private, mode) {
+            (false, 4) => self.insert_mode = on,
+            (true, 1) => self.application_cursor = on,
+            (true, 6) => {
+                self.origin_mode = on;
+                self.move_to(0, 0);
+            }
+            (true, 7) => self.autowrap = on,
+            (true, 25) => self.cursor_visible = on,
+            (true, 47) | (true, 1047) | (true, 1049) => {
+                if on {
+                    self.enter_alternate();
+                } else {
+                    self.leave_alternate();
+                }
+            }
+            (true, 2004) => self.bracketed_paste = on,
+            _ => (),
+        }
+    }
+
+    fn sgr(&mut self, params: &[Vec<u16>]) {
+        if params.is_empty() {
+            self.style = Style::default();
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            let param = &params[i];
+            match param[0] {
+                0 => self.style = Style::default(),
+                1 => self.style.bold = true,
+                2 => self.style.dim = true,
+                3 => self.style.italic = true,
+                4 => self.style.underline = true,
+                7 => self.style.reverse = true,
+                22 => {
+                    self.style.bold = false;
+                    self.style.dim = false;
+                }
+                23 => self.style.italic = false,
+                24 => self.style.underline = false,
+                27 => self.style.reverse = false,
+                n @ 30..=37 => self.style.fg = Color::AnsiValue((n - 30) as u8),
+                39 => self.style.fg = Color::Reset,
+                n @ 40..=47 => self.style.bg = Color::AnsiValue((n - 40) as u8),
+                49 => self.style.bg = Color::Reset,
+                n @ 90..=97 => self.style.fg = Color::AnsiValue((n - 90 + 8) as u8),
+                n @ 100..=107 => self.style.bg = Color::AnsiValue((n - 100 + 8) as u8),
+                n @ (38 | 48) => {
+                    // 38:5:n这种写法参数在子参数里，38;5;n则在后面的参数里
+                    let rest = if param.len() > 1 {
+                        param[1..].to_vec()
+                    } else {
+                        let rest = params[i + 1..].iter().map(|p| p[0]).collect::<Vec<_>>();
+                        i += match rest.first() {
+                            Some(5) => 2,
+                            Some(2) => 4,
+                            _ => 0,
+                        };
+                        rest
+                    };
+                    let color = match rest.as_slice() {
+                        [5, n, ..] => Some(Color::AnsiValue(*n as u8)),
+                        [2, r, g, b, ..] => Some(Color::Rgb {
+                            r: *r as u8,
+                            g: *g as u8,
+                            b: *b as u8,
+                        }),
+                        _ => None,
+                    };
+                    if let Some(color) = color {
+                        if n == 38 {
+                            self.style.fg = color;
+                        } else {
+                            self.style.bg = color;
+                        }
+                    }
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+    }
+}
+
+impl Perform for Grid {
+    fn print(&mut self, c: char) {
+        self.put_char(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\n' | 0x0b | 0x0c => self.line_feed(),
+            b'\r' => {
+                self.col = 0;
+                self.wrap_pending = false;
+            }
+            0x08 => {
+                self.col = self.col.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            b'\t' => {
+                self.col = ((self.col / TAB_WIDTH + 1) * TAB_WIDTH).min(self.cols - 1);
+            }
+            // SO/SI切换字符集
+            0x0e => self.line_drawing = true,
+            0x0f => self.line_drawing = false,
+            _ => (),
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
+        let params = params.iter().map(|p| p.to_vec()).collect::<Vec<_>>();
+        // 第i个参数，缺省或为0时取default
+        let arg = |i: usize, default: usize| {
+            params
+                .get(i)
+                .map(|p| p[0] as usize)
+                .filter(|&n| n != 0)
+                .unwrap_or(default)
+        };
+        let private = intermediates.first() == Some(&b'?');
+        match action {
+            'A' => {
+                let top = if self.row >= self.scroll_top {
+                    self.scroll_top
+                } else {
+                    0
+                };
+                self.row = self.row.saturating_sub(arg(0, 1)).max(top);
+                self.wrap_pending = false;
+            }
+            'B' | 'e' => {
+                let bottom = if self.row <= self.scroll_bottom {
+                    self.scroll_bottom
+                } else {
+                    self.rows - 1
+                };
+                self.row = (self.row + arg(0, 1)).min(bottom);
+                self.wrap_pending = false;
+            }
+            'C' | 'a' => {
+                self.col = (self.col + arg(0, 1)).min(self.cols - 1);
+                self.wrap_pending = false;
+            }
+            'D' => {
+                self.col = self.col.saturating_sub(arg(0, 1));
+                self.wrap_pending = false;
+            }
+            'E' => {
+                self.row = (self.row + arg(0, 1)).min(self.rows - 1);
+                self.col = 0;
+                self.wrap_pending = false;
+            }
+            'F' => {
+                self.row = self.row.saturating_sub(arg(0, 1));
+                self.col = 0;
+                self.wrap_pending = false;
+            }
+            'G' | '`' => {
+                self.col = (arg(0, 1) - 1).min(self.cols - 1);
+                self.wrap_pending = false;
+            }
+            'd' => {
+                let col = self.col;
+                self.move_to(arg(0, 1) - 1, col);
+            }
+            'H' | 'f' => self.move_to(arg(0, 1) - 1, arg(1, 1) - 1),
+            'J' => {
+                let (row, col, rows, cols) = (self.row, self.col, self.rows, self.cols);
+                match arg(0, 0) {
+                    0 => {
+                        self.erase(row, col..cols);
+                        for r in row + 1..rows {
+                            self.erase(r, 0..cols);
+                        }
+                    }
+                    1 => {
+                        for r in 0..row {
+                            self.erase(r, 0..cols);
+                        }
+                        self.erase(row, 0..col + 1);
+                    }
+                    2 | 3 => {
+                        for r in 0..rows {
+                            self.erase(r, 0..cols);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            'K' => {
+                let (row, col, cols) = (self.row, self.col, self.cols);
+                match arg(0, 0) {
+                    0 => self.erase(row, col..cols),
+                    1 => self.erase(row, 0..col + 1),
+                    2 => self.erase(row, 0..cols),
+                    _ => (),
+                }
+            }
+            'X' => {
+                let (row, col) = (self.row, self.col);
+                let end = (col + arg(0, 1)).min(self.cols);
+                self.erase(row, col..end);
+            }
+            '@' => self.insert_blanks(arg(0, 1)),
+            'P' => self.delete_chars(arg(0, 1)),
+            'L' => self.insert_lines(arg(0, 1)),
+            'M' => self.delete_lines(arg(0, 1)),
+            'S' => self.scroll_up(arg(0, 1)),
+            'T' if !private => self.scroll_down(arg(0, 1)),
+            'r' if !private => {
+                let top = arg(0, 1) - 1;
+                let bottom = arg(1, self.rows).min(self.rows) - 1;
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            }
+            'm' if intermediates.is_empty() => self.sgr(&params),
+            'h' | 'l' => {
+                for p in &params {
+                    self.set_mode(private, p[0], action == 'h');
+                }
+            }
+            's' if !private => self.save_cursor(),
+            'u' if !private => self.restore_cursor(),
+            'n' => match arg(0, 0) {
+                5 => self.responses.extend_from_slice(b"\x1b[0n"),
+                6 => {
+                    let report = format!("\x1b[{};{}R", self.row + 1, self.col + 1);
+                    self.responses.extend_from_slice(report.as_bytes());
+                }
+                _ => (),
+            },
+            'c' if intermediates.is_empty() => {
+                self.responses.extend_from_slice(b"\x1b[?1;2c");
+            }
+            _ => (),
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.line_feed(),
+            ([], b'E') => {
+                self.col = 0;
+                self.line_feed();
+            }
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => {
+                // 重置终端但保留历史
+                let scrollback = mem::take(&mut self.scrollback);
+                *self = Grid::new(self.cols, self.rows, self.scrollback_limit);
+                self.scrollback = scrollback;
+            }
+            ([b'('], b'0') => self.line_drawing = true,
+            ([b'('], _) => self.line_drawing = false,
+            _ => (),
+        }
+    }
+}
+
+/// DEC画线字符集
+fn line_drawing(ch: char) -> char {
+    match ch {
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'q' => '─',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'a' => '▒',
+        '`' => '◆',
+        '~' => '·',
+        'f' => '°',
+        'g' => '±',
+        _ => ch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(cols: usize, rows: usize, input: &str) -> Screen {
+        let mut screen = Screen::new(cols, rows, 100);
+        screen.feed(input.as_bytes());
+        screen
+    }
+
+    /// 屏幕上的各行，不包括历史
+    fn rows(screen: &Screen) -> Vec<String> {
+        let history = screen.history_len();
+        (0..screen.size().1)
+            .map(|row| {
+                screen
+                    .line(history + row)
+                    .iter()
+                    .filter(|cell| !cell.wide_tail)
+                    .map(|cell| cell.ch)
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn text_wraps_and_scrolls_into_history() {
+        let screen = screen(3, 2, "abcdef\r\ngh");
+        assert_eq!(rows(&screen), ["def", "gh"]);
+        assert_eq!(screen.history_len(), 1);
+        assert_eq!(
+            screen.line(0).iter().map(|c| c.ch).collect::<String>(),
+            "abc"
+        );
+        assert_eq!(screen.cursor(), Some((2, 1)));
+    }
+
+    #[test]
+    fn wide_character_in_last_column_wraps() {
+        let screen = screen(3, 2, "ab中");
+        assert_eq!(rows(&screen), ["ab", "中"]);
+        assert!(screen.line(1)[1].wide_tail);
+        // 关闭自动换行时写在最后能放下的位置
+        let screen = self::screen(3, 2, "\x1b[?7lab中");
+        assert_eq!(rows(&screen), ["a中", ""]);
+    }
+
+    #[test]
+    fn wide_character_in_one_column() {
+        let screen = screen(1, 2, "中a");
+        assert_eq!(rows(&screen), ["", "a"]);
+        assert!(screen.line(0).iter().all(|cell| !cell.wide_tail));
+        let screen = self::screen(1, 2, "\x1b[?7l中中");
+        assert_eq!(rows(&screen), ["", ""]);
+    }
+
+    #[test]
+    fn overwriting_half_of_a_wide_character_clears_it() {
+        let screen = screen(4, 1, "中文\x1b[2Gx");
+        assert_eq!(rows(&screen), [" x文"]);
+    }
+
+    #[test]
+    fn cursor_movement_and_erase() {
+        let screen = screen(
+            5,
+            3,
+            "aaaaa\r\nbbbbb\r\nccccc\x1b[2;3HX\x1b[K\x1b[1;2H\x1b[1K",
+        );
+        assert_eq!(rows(&screen), ["  aaa", "bbX", "ccccc"]);
+        let screen = self::screen(5, 3, "aaaaa\r\nbbbbb\x1b[2J");
+        assert_eq!(rows(&screen), ["", "", ""]);
+        assert_eq!(screen.cursor(), Some((4, 1)));
+    }
+
+    #[test]
+    fn sgr_sets_cell_style() {
+        let screen = screen(4, 1, "\x1b[1;31ma\x1b[0;7mb\x1b[mc");
+        let cells = screen.line(0);
+        assert!(cells[0].style.bold);
+        assert_eq!(cells[0].style.fg, Color::AnsiValue(1));
+        assert!(!cells[1].style.bold && cells[1].style.reverse);
+        assert!(cells[2].style == Style::default());
+    }
+
+    #[test]
+    fn scroll_region() {
+        let screen = screen(2, 4, "1\r\n2\r\n3\r\n4\x1b[2;3r\x1b[3;1H\n");
+        assert_eq!(rows(&screen), ["1", "3", "", "4"]);
+        // 滚动区域内滚出的行不进入历史
+        assert_eq!(screen.history_len(), 0);
+    }
+
+    #[test]
+    fn alternate_screen_restores_primary() {
+        let mut screen = screen(3, 2, "ab");
+        screen.feed(b"\x1b[?1049h\x1b[Hxy");
+        assert_eq!(rows(&screen), ["xy", ""]);
+        screen.feed(b"\x1b[?1049l");
+        assert_eq!(rows(&screen), ["ab", ""]);
+        assert_eq!(screen.cursor(), Some((2, 0)));
+    }
+
+    #[test]
+    fn modes_and_responses() {
+        let mut screen = screen(5, 3, "\x1b[2;3H\x1b[6n\x1b[?1h\x1b[?2004h\x1b[?25l");
+        assert_eq!(screen.take_responses(), b"\x1b[2;3R");
+        assert!(screen.take_responses().is_empty());
+        assert!(screen.application_cursor());
+        assert!(screen.bracketed_paste());
+        assert_eq!(screen.cursor(), None);
+    }
+
+    #[test]
+    fn dec_line_drawing() {
+        let screen = screen(3, 1, "\x1b(0qx\x1b(Bq");
+        assert_eq!(rows(&screen), ["─│q"]);
+    }
+
+    #[test]
+    fn resize_keeps_the_cursor_line() {
+        let mut screen = screen(4, 3, "a\r\nb\r\nc");
+        screen.resize(2, 2);
+        assert_eq!(rows(&screen), ["b", "c"]);
+        assert_eq!(screen.history_len(), 1);
+        assert_eq!(screen.cursor(), Some((1, 1)));
+    }
+}
@@ -0,0 +1,224 @@
+use ropey::{Rope, RopeSlice};
+use std::io::{self, Read, Write};
+use unicode_width::UnicodeWidthChar;
+
+/// 制表符展开到的列宽，按下Tab时也插入这么多空格
+pub const TAB_WIDTH: usize = 4;
+
+/// 基于rope的文本存储
+///
+/// 行号从0开始，只有'\n'分行，每行包括行尾的'\n'；位置都是(行号, 字符下标)
+pub struct TextBuffer {
+    rope: Rope,
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        TextBuffer { rope: Rope::new() }
+    }
+
+    pub fn from_reader(reader: impl Read) -> io::Result<Self> {
+        Ok(TextBuffer {
+            rope: Rope::from_reader(reader)?,
+        })
+    }
+
+    pub fn write_to(&self, writer: impl Write) -> io::Result<()> {
+        self.rope.write_to(writer)
+    }
+
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn line(&self, line: usize) -> Option<RopeSlice<'_>> {
+        self.rope.get_line(line)
+    }
+
+    /// 从first开始最多count行
+    pub fn lines(&self, first: usize, count: usize) -> impl Iterator<Item = RopeSlice<'_>> {
+        let first = first.min(self.rope.len_lines());
+        self.rope.lines_at(first).take(count)
+    }
+
+    /// 不包括行尾换行符（'\n'、"\r\n"或'\r'）的字符数
+    pub fn content_len(&self, line: usize) -> usize {
+        self.line(line).map(|l| content_len(&l)).unwrap_or(0)
+    }
+
+    /// 第index个字符在行中的显示列
+    pub fn column_of(&self, line: usize, index: usize) -> usize {
+        self.line(line)
+            .map(|l| {
+                l.chars()
+                    .take(index)
+                    .fold(0, |column, ch| column + width_at(column, ch))
+            })
+            .unwrap_or(0)
+    }
+
+    /// 显示列column所在字符的下标，落在宽字符中间时取该字符
+    pub fn index_of(&self, line: usize, column: usize) -> usize {
+        let Some(l) = self.line(line) else {
+            return 0;
+        };
+        let len = content_len(&l);
+        let mut width = 0;
+        for (i, ch) in l.chars().take(len).enumerate() {
+            width += width_at(width, ch);
+            if width > column {
+                return i;
+            }
+        }
+        len
+    }
+
+    /// 用text替换start到end之间的内容，返回被删除的内容
+    pub fn splice(
+        &mut self,
+        start: (usize, usize),
+        end: (usize, usize),
+        text: &[char],
+    ) -> Vec<char> {
+        let from = self.rope.line_to_char(start.0) + start.1;
+        let to = self.rope.line_to_char(end.0) + end.1;
+        let removed = self.rope.slice(from..to).chars().collect();
+        self.rope.remove(from..to);
+        self.rope.insert(from, &text.iter().collect::<String>());
+        removed
+    }
+}
+
+fn content_len(line: &RopeSlice) -> usize {
+    let mut len = line.len_chars();
+    if len > 0 && line.char(len - 1) == '\n' {
+        len -= 1;
+    }
+    if len > 0 && line.char(len - 1) == '\r' {
+        len -= 1;
+    }
+    len
+}
+
+/// 字符的显示宽度，控制字符按0处理
+pub fn char_width(ch: char) -> usize {
+    UnicodeWidthChar::width(ch).unwrap_or(0)
+}
+
+/// 字符从column列开始时占的列数，制表符展开到下一个制表位
+pub fn width_at(column: usize, ch: char) -> usize {
+    if ch == '\t' {
+        TAB_WIDTH - column % TAB_WIDTH
+    } else {
+        char_width(ch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> TextBuffer {
+        TextBuffer::from_reader(text.as_bytes()).unwrap()
+    }
+
+    fn text(buf: &TextBuffer) -> String {
+        let mut bytes = Vec::new();
+        buf.write_to(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn lines_and_lengths() {
+        let buf = buffer("ab\n中文\n");
+        assert_eq!(buf.line_count(), 3);
+        assert_eq!(buf.content_len(0), 2);
+        assert_eq!(buf.content_len(1), 2);
+        assert_eq!(buf.content_len(2), 0);
+        assert_eq!(buf.content_len(9), 0);
+        let lines = buf.lines(1, 5).map(|l| l.to_string()).collect::<Vec<_>>();
+        assert_eq!(lines, ["中文\n", ""]);
+    }
+
+    #[test]
+    fn columns_of_wide_characters() {
+        let buf = buffer("a中b\n");
+        assert_eq!(buf.column_of(0, 2), 3);
+        assert_eq!(buf.index_of(0, 0), 0);
+        // 落在宽字符的右半边时取这个字符
+        assert_eq!(buf.index_of(0, 1), 1);
+        assert_eq!(buf.index_of(0, 2), 1);
+        assert_eq!(buf.index_of(0, 3), 2);
+        // 超过行尾时停在换行符前
+        assert_eq!(buf.index_of(0, 10), 3);
+    }
+
+    #[test]
+    fn splice_across_lines() {
+        let mut buf = buffer("one\ntwo\nthree\n");
+        let removed = buf.splice((0, 1), (2, 2), &['X']);
+        assert_eq!(removed.iter().collect::<String>(), "ne\ntwo\nth");
+        assert_eq!(text(&buf), "oXree\n");
+
+        let removed = buf.splice((0, 5), (0, 5), &['\n', 'y']);
+        assert!(removed.is_empty());
+        assert_eq!(text(&buf), "oXree\ny\n");
+    }
+
+    #[test]
+    fn edits_at_end_of_crlf_line() {
+        let mut buf = buffer("ab\r\ncd\r\n");
+        assert_eq!(buf.line_count(), 3);
+        assert_eq!(buf.content_len(0), 2);
+        assert_eq!(buf.index_of(0, 10), 2);
+        buf.splice((0, 2), (0, 2), &['x']);
+        assert_eq!(text(&buf), "abx\r\ncd\r\n");
+        // 接行时连同"\r\n"一起删掉
+        buf.splice((0, 3), (1, 0), &[]);
+        assert_eq!(text(&buf), "abxcd\r\n");
+        assert_eq!(buf.line_count(), 2);
+    }
+
+    #[test]
+    fn edits_at_end_of_lone_cr_line() {
+        // 单独的'\r'不分行
+        let mut buf = buffer("ab\rcd\r");
+        assert_eq!(buf.line_count(), 1);
+        assert_eq!(buf.content_len(0), 5);
+        assert_eq!(buf.index_of(0, 10), 5);
+        buf.splice((0, 5), (0, 5), &['x']);
+        assert_eq!(text(&buf), "ab\rcdx\r");
+        assert_eq!(buf.line_count(), 1);
+    }
+
+    #[test]
+    fn control_characters_have_no_width() {
+        assert_eq!(char_width('\t'), 0);
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('文'), 2);
+    }
+
+    #[test]
+    fn tabs_expand_to_next_stop() {
+        assert_eq!(width_at(0, '\t'), TAB_WIDTH);
+        assert_eq!(width_at(TAB_WIDTH - 1, '\t'), 1);
+        assert_eq!(width_at(TAB_WIDTH, '\t'), TAB_WIDTH);
+        assert_eq!(width_at(3, '文'), 2);
+
+        let buf = buffer("\tx\n中\tb\n");
+        assert_eq!(buf.column_of(0, 1), TAB_WIDTH);
+        assert_eq!(buf.column_of(0, 2), TAB_WIDTH + 1);
+        // 宽字符之后的制表符只补到下一个制表位
+        assert_eq!(buf.column_of(1, 2), TAB_WIDTH);
+        // 落在制表符中间时取制表符
+        assert_eq!(buf.index_of(0, 0), 0);
+        assert_eq!(buf.index_of(0, TAB_WIDTH - 1), 0);
+        assert_eq!(buf.index_of(0, TAB_WIDTH), 1);
+        assert_eq!(buf.index_of(1, 3), 1);
+        assert_eq!(buf.index_of(1, TAB_WIDTH), 2);
+    }
+}
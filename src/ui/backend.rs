@@ -0,0 +1,84 @@
+use crossterm::{
+    cursor::{self, MoveTo},
+    queue,
+    terminal::{
+        disable_raw_mode, enable_raw_mode, window_size, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+use std::io::Write;
+
+use crate::renderer::Buffer;
+
+/// Framework把渲染好的每一帧交给Backend输出
+pub trait Backend {
+    /// 屏幕的列数和行数
+    fn size(&self) -> (usize, usize);
+
+    /// 输出frame中与上一帧prev不同的格子，clear时先清屏，cursor为显示光标的位置
+    fn draw(&mut self, frame: &Buffer, prev: &Buffer, clear: bool, cursor: Option<(usize, usize)>);
+}
+
+/// 在备用屏幕上以raw mode输出到终端，drop时恢复终端
+pub struct TerminalBackend;
+
+impl TerminalBackend {
+    pub fn enter() -> Self {
+        enable_raw_mode().unwrap();
+        queue!(
+            std::io::stdout(),    //
+            EnterAlternateScreen, //
+            cursor::Hide
+        )
+        .unwrap();
+        std::io::stdout().flush().unwrap();
+        TerminalBackend
+    }
+}
+
+impl Backend for TerminalBackend {
+    fn size(&self) -> (usize, usize) {
+        let size = window_size().unwrap();
+        (size.columns as usize, size.rows as usize)
+    }
+
+    fn draw(&mut self, frame: &Buffer, prev: &Buffer, clear: bool, cursor: Option<(usize, usize)>) {
+        let mut stdout = std::io::stdout();
+        queue!(stdout, cursor::Hide).unwrap();
+        if clear {
+            queue!(stdout, Clear(ClearType::All)).unwrap();
+        }
+        frame.flush_diff(prev, &mut stdout).unwrap();
+        if let Some((x, y)) = cursor {
+            queue!(stdout, MoveTo(x as u16, y as u16), cursor::Show).unwrap();
+        }
+        stdout.flush().unwrap();
+    }
+}
+
+impl Drop for TerminalBackend {
+    fn drop(&mut self) {
+        queue!(std::io::stdout(), cursor::Show, LeaveAlternateScreen).unwrap();
+        disable_raw_mode().unwrap();
+    }
+}
+
+/// 不输出任何内容，画好的一帧留在Framework::screen中，用于无终端地运行组件
+pub struct HeadlessBackend {
+    width: usize,
+    height: usize,
+}
+
+impl HeadlessBackend {
+    pub fn new(width: usize, height: usize) -> Self {
+        HeadlessBackend { width, height }
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn draw(&mut self, _: &Buffer, _: &Buffer, _: bool, _: Option<(usize, usize)>) {}
+}
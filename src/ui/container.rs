@@ -1,6 +1,8 @@
 use crate::{
     components::{
-        component::Component, editor::Editor, project_viewer::ProjectViewer, terminal::Terminal,
+        command_palette::CommandPalette, completion::Completion, component::Component,
+        editor::Editor, file_finder::FileFinder, problems::Problems,
+        project_viewer::ProjectViewer, search_panel::SearchPanel, terminal::Terminal,
     },
     renderer::Renderer,
 };
@@ -9,21 +11,41 @@ use std::sync::{Arc, RwLock};
 
 pub enum ContainerType {
     Father {
-        subconts: [Option<Arc<RwLock<Container>>>; 2],
-        /// 两个子Containero是否是垂直布局
+        /// 后加入的在前面，即上方或左边
+        subconts: Vec<Arc<RwLock<Container>>>,
+        /// 子Container是否是垂直布局
         vert_layout: bool,
-        /// 独占
-        all_own: bool,
+        /// 有焦点的子Container占满，其他的只留最小大小
+        zoom: bool,
     },
     ProjectViewer(Arc<RwLock<ProjectViewer>>),
     Terminal(Arc<RwLock<Terminal>>),
     Editor(Arc<RwLock<Editor>>),
+    SearchPanel(Arc<RwLock<SearchPanel>>),
+    FileFinder(Arc<RwLock<FileFinder>>),
+    CommandPalette(Arc<RwLock<CommandPalette>>),
+    Problems(Arc<RwLock<Problems>>),
+    Completion(Arc<RwLock<Completion>>),
     None,
 }
 
 /// 这是一个闭包，闭包中不可以对带锁的Container对象解锁
+///
+/// 闭包不是Send，所以Arc<RwLock<Container>>也不是，构造组件的函数因此允许arc_with_non_send_sync
 type EventHandler = dyn FnMut(Event, (usize, usize));
 
+/// 屏幕上的(x, y, 宽, 高)
+pub type Rect = (usize, usize, usize, usize);
+
+/// 子Container在父Container中沿布局方向占的大小
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Size {
+    /// 与其他子Container按权重分固定大小之外的空间
+    Weight(usize),
+    /// 固定的行数或列数
+    Fixed(usize),
+}
+
 pub struct Container {
     name: String,
     x: usize,
@@ -32,6 +54,9 @@ pub struct Container {
     height: usize,
     focused: bool,
     cont_type: ContainerType,
+    /// 在父Container中的大小，但不少于最小的行数或列数
+    size: Size,
+    min_size: usize,
 
     eve_handler: Option<Box<EventHandler>>,
 }
@@ -46,6 +71,8 @@ impl Container {
             height: 0,
             focused: false,
             cont_type: ContainerType::None,
+            size: Size::Weight(1),
+            min_size: 1,
             eve_handler: f,
         }
     }
@@ -59,10 +86,12 @@ impl Container {
             height,
             focused: true,
             cont_type: ContainerType::Father {
-                subconts: [None, None],
+                subconts: Vec::new(),
                 vert_layout: true,
-                all_own: true,
+                zoom: true,
             },
+            size: Size::Weight(1),
+            min_size: 1,
             eve_handler: f,
         }
     }
@@ -139,10 +168,8 @@ impl Container {
     pub fn dispatch(&mut self, event: Event) {
         let size = self.get_size();
         if let ContainerType::Father { subconts, .. } = &mut self.cont_type {
-            for cont in subconts {
-                if let Some(cont) = cont {
-                    cont.write().unwrap().dispatch(event.clone());
-                }
+            for cont in subconts.iter_mut() {
+                cont.write().unwrap().dispatch(event.clone());
             }
         } else if self.focused {
             if let Some(handler) = &mut self.eve_handler {
@@ -157,73 +184,101 @@ impl Container {
 
     pub fn focus_path(&mut self, path: &[&str]) {
         self.focused = true;
-        if let ContainerType::Father { subconts, .. } = &self.cont_type {
-            if let Some(down_cont) = &subconts[1] {
-                if down_cont.read().unwrap().name == path[0] {
-                    down_cont.write().unwrap().focus_path(&path[1..]);
-                } else {
-                    if let Some(up_cont) = &subconts[0] {
-                        if up_cont.read().unwrap().name == path[0] {
-                            up_cont.write().unwrap().focus_path(&path[1..]);
-                        }
-                    }
-                }
-            }
+        if let Some(cont) = self.child(path) {
+            cont.write().unwrap().focus_path(&path[1..]);
         }
     }
 
     pub fn disfocus_path(&mut self, path: &[&str]) {
         self.focused = false;
-        if let ContainerType::Father { subconts, .. } = &self.cont_type {
-            if let Some(down_cont) = &subconts[1] {
-                if down_cont.read().unwrap().name == path[0] {
-                    down_cont.write().unwrap().disfocus_path(&path[1..]);
-                } else {
-                    if let Some(up_cont) = &subconts[0] {
-                        if up_cont.read().unwrap().name == path[0] {
-                            up_cont.write().unwrap().disfocus_path(&path[1..]);
-                        }
-                    }
-                }
-            }
+        if let Some(cont) = self.child(path) {
+            cont.write().unwrap().disfocus_path(&path[1..]);
         }
     }
 
+    /// 名字为path[0]的子Container
+    fn child(&self, path: &[&str]) -> Option<&Arc<RwLock<Container>>> {
+        let ContainerType::Father { subconts, .. } = &self.cont_type else {
+            return None;
+        };
+        let name = path.first()?;
+        subconts
+            .iter()
+            .find(|cont| cont.read().unwrap().name == *name)
+    }
+
     pub fn render(&self, renderer: &Renderer) -> (bool, (usize, usize)) {
         match &self.cont_type {
             ContainerType::Father { subconts, .. } => {
                 let mut res = None;
-                for cont in subconts {
-                    if let Some(cont) = cont {
-                        let size = cont.read().unwrap().get_size();
-                        let location = cont.read().unwrap().get_location();
-                        let subrend = Renderer::new(
-                            renderer.x + location.0,
-                            renderer.y + location.1,
-                            size.0,
-                            size.1,
-                        );
-                        let r = cont.read().unwrap().render(&subrend);
-                        if cont.read().unwrap().focused {
-                            res = Some(r);
-                        }
+                for cont in subconts.iter() {
+                    let size = cont.read().unwrap().get_size();
+                    let location = cont.read().unwrap().get_location();
+                    let subrend = renderer.sub(location.0, location.1, size.0, size.1);
+                    let r = cont.read().unwrap().render(&subrend);
+                    if cont.read().unwrap().focused {
+                        res = Some(r);
                     }
                 }
-                if let Some(res) = res {
-                    res
-                } else {
-                    (false, (0, 0))
-                }
+                res.unwrap_or_default()
             }
             ContainerType::ProjectViewer(proj_viewer) => {
                 proj_viewer.write().unwrap().render(renderer)
             }
             ContainerType::Terminal(terminal) => terminal.write().unwrap().render(renderer),
             ContainerType::Editor(editor) => editor.write().unwrap().render(renderer),
+            ContainerType::SearchPanel(panel) => panel.write().unwrap().render(renderer),
+            ContainerType::FileFinder(finder) => finder.write().unwrap().render(renderer),
+            ContainerType::CommandPalette(palette) => palette.write().unwrap().render(renderer),
+            ContainerType::Problems(problems) => problems.write().unwrap().render(renderer),
+            ContainerType::Completion(completion) => completion.write().unwrap().render(renderer),
             _ => (false, (0, 0)),
         }
     }
 
+    /// 收集所有叶子Container的路径和在屏幕上的位置，origin为自己左上角在屏幕上的位置
+    pub fn panes(&self, path: &str, origin: (usize, usize), out: &mut Vec<(String, Rect)>) {
+        let ContainerType::Father { subconts, .. } = &self.cont_type else {
+            out.push((
+                path.to_string(),
+                (origin.0, origin.1, self.width, self.height),
+            ));
+            return;
+        };
+        for cont in subconts.iter() {
+            let cont = cont.read().unwrap();
+            cont.panes(
+                &format!("{}/{}", path, cont.name),
+                (origin.0 + cont.x, origin.1 + cont.y),
+                out,
+            );
+        }
+    }
+
+    /// 有焦点的组件当前的按键绑定上下文，见keymap
+    pub fn key_context(&self) -> Option<&'static str> {
+        match &self.cont_type {
+            ContainerType::Father { subconts, .. } => subconts
+                .iter()
+                .find(|cont| cont.read().unwrap().focused)
+                .and_then(|cont| cont.read().unwrap().key_context()),
+            ContainerType::ProjectViewer(_) => Some("project_viewer"),
+            ContainerType::Terminal(terminal) => terminal.read().unwrap().key_context(),
+            ContainerType::Editor(editor) => editor.read().unwrap().key_context(),
+            ContainerType::SearchPanel(_) => Some("search_panel"),
+            ContainerType::Problems(_) => Some("problems"),
+            _ => None,
+        }
+    }
+
+    /// 跟随光标显示的窗口需要的大小，不需要显示时为None
+    pub fn wanted_size(&self) -> Option<(usize, usize)> {
+        match &self.cont_type {
+            ContainerType::Completion(completion) => completion.read().unwrap().wanted_size(),
+            _ => None,
+        }
+    }
+
     pub fn set_type(&mut self, _type: ContainerType) {
         self.cont_type = _type;
         let (w, h) = (self.width, self.height);
@@ -239,69 +294,150 @@ impl Container {
         if let ContainerType::Father {
             subconts,
             vert_layout,
-            all_own,
-        } = &mut self.cont_type
+            zoom,
+        } = &self.cont_type
         {
-            if *vert_layout {
-                if let Some(up_cont) = &subconts[0] {
-                    if let Some(down_cont) = &subconts[1] {
-                        let h = if down_cont.read().unwrap().focused {
-                            if *all_own {
-                                height - 1
-                            } else {
-                                height / 3 * 2
-                            }
-                        } else {
-                            if !up_cont.read().unwrap().focused {
-                                height / 2
-                            } else if *all_own {
-                                1
-                            } else {
-                                height / 3
-                            }
-                        };
-                        down_cont.write().unwrap().set_size(width, h);
-                        down_cont.write().unwrap().set_location(0, height - h);
-                        up_cont.write().unwrap().set_size(width, height - h);
-                    } else {
-                        up_cont.write().unwrap().set_size(width, height);
-                    }
+            let total = if *vert_layout { height } else { width };
+            let mut offset = 0;
+            for (cont, size) in subconts.iter().zip(layout(subconts, total, *zoom)) {
+                let mut cont = cont.write().unwrap();
+                if *vert_layout {
+                    cont.set_size(width, size);
+                    cont.set_location(0, offset);
                 } else {
-                    if let Some(down_cont) = &subconts[1] {
-                        down_cont.write().unwrap().set_size(width, height);
-                        down_cont.write().unwrap().set_location(0, 0);
-                    }
+                    cont.set_size(size, height);
+                    cont.set_location(offset, 0);
                 }
-            } else {
-                if let Some(up_cont) = &subconts[0] {
-                    if let Some(down_cont) = &subconts[1] {
-                        let w = if down_cont.read().unwrap().focused {
-                            if *all_own {
-                                width - 1
-                            } else {
-                                width / 3 * 2
-                            }
-                        } else {
-                            if *all_own {
-                                1
-                            } else {
-                                width / 3
-                            }
-                        };
-                        down_cont.write().unwrap().set_size(w, height);
-                        down_cont.write().unwrap().set_location(width - w, 0);
-                        up_cont.write().unwrap().set_size(width - w, height);
-                    } else {
-                        up_cont.write().unwrap().set_size(width, height);
-                    }
+                offset += size;
+            }
+        }
+    }
+
+    /// 设置在父Container中的大小和最小的行数或列数
+    pub fn set_split(&mut self, size: Size, min_size: usize) {
+        self.size = size;
+        self.min_size = min_size;
+    }
+
+    /// 打开或关闭自己和所有子孙的缩放
+    pub fn set_zoom(&mut self, on: bool) {
+        if let ContainerType::Father { subconts, zoom, .. } = &mut self.cont_type {
+            *zoom = on;
+            for cont in subconts.iter() {
+                cont.write().unwrap().set_zoom(on);
+            }
+        }
+    }
+
+    /// 把path处的Container放大delta行（vertical为true时）或列，delta为负时缩小
+    ///
+    /// 从最近的一层往上找沿这个方向布局、有兄弟、还能调整大小的一层，放大时从相邻的兄弟中挪出空间，
+    /// 缩小时把空间还给相邻的兄弟，返回是否找到了这样的一层
+    pub fn resize_path(&mut self, path: &[&str], delta: isize, vertical: bool) -> bool {
+        let Some(cont) = self.child(path) else {
+            return false;
+        };
+        if cont
+            .write()
+            .unwrap()
+            .resize_path(&path[1..], delta, vertical)
+        {
+            return true;
+        }
+        let ContainerType::Father {
+            subconts,
+            vert_layout,
+            ..
+        } = &self.cont_type
+        else {
+            return false;
+        };
+        if *vert_layout != vertical || subconts.len() < 2 {
+            return false;
+        }
+        let index = subconts
+            .iter()
+            .position(|sub| Arc::ptr_eq(sub, cont))
+            .unwrap();
+        let (mut sizes, mins): (Vec<_>, Vec<_>) = subconts
+            .iter()
+            .map(|cont| {
+                let cont = cont.read().unwrap();
+                let size = if vertical { cont.height } else { cont.width };
+                (size, cont.min_size)
+            })
+            .unzip();
+        // 离得近的兄弟在前
+        let siblings = (1..subconts.len())
+            .flat_map(|d| [index.checked_sub(d), Some(index + d)])
+            .flatten()
+            .filter(|&i| i < subconts.len())
+            .collect::<Vec<_>>();
+        let before = sizes[index];
+        if delta > 0 {
+            let mut need = delta as usize;
+            for &i in &siblings {
+                let take = need.min(sizes[i] - sizes[i].min(mins[i]));
+                sizes[i] -= take;
+                sizes[index] += take;
+                need -= take;
+            }
+        } else {
+            let give = delta
+                .unsigned_abs()
+                .min(sizes[index].saturating_sub(mins[index]));
+            sizes[index] -= give;
+            sizes[siblings[0]] += give;
+        }
+        // 这一层已经调不动了，交给上一层
+        if sizes[index] == before {
+            return false;
+        }
+        let change = sizes[index] as isize - before as isize;
+        // 用现在的大小作为权重或固定大小，布局不变时各子Container正好是这些大小
+        for (cont, size) in subconts.iter().zip(sizes) {
+            cont.write().unwrap().resized_to(size);
+        }
+        // 下面调不动的各层把多出或少了的空间都算在path上
+        cont.write().unwrap().claim(&path[1..], change, vertical);
+        true
+    }
+
+    /// 自己沿vertical方向变大了change行或列，在有兄弟的各层中都给path处的Container
+    fn claim(&mut self, path: &[&str], change: isize, vertical: bool) {
+        let Some(cont) = self.child(path) else {
+            return;
+        };
+        let ContainerType::Father {
+            subconts,
+            vert_layout,
+            ..
+        } = &self.cont_type
+        else {
+            return;
+        };
+        if *vert_layout == vertical {
+            for sub in subconts.iter() {
+                let on_path = Arc::ptr_eq(sub, cont);
+                let mut sub = sub.write().unwrap();
+                let size = if vertical { sub.height } else { sub.width };
+                let size = if on_path {
+                    size.saturating_add_signed(change).max(sub.min_size)
                 } else {
-                    if let Some(down_cont) = &subconts[1] {
-                        down_cont.write().unwrap().set_size(width, height);
-                        down_cont.write().unwrap().set_location(0, 0);
-                    }
-                }
+                    size
+                };
+                sub.resized_to(size);
             }
         }
+        cont.write().unwrap().claim(&path[1..], change, vertical);
+    }
+
+    /// 调整大小后保持原来的种类，固定大小的改为size，按权重分的以size为权重
+    fn resized_to(&mut self, size: usize) {
+        self.size = match self.size {
+            Size::Weight(_) => Size::Weight(size.max(1)),
+            Size::Fixed(_) => Size::Fixed(size),
+        };
     }
 
     pub fn set_location(&mut self, x: usize, y: usize) {
@@ -314,32 +450,20 @@ impl Container {
         path: &[&str],
         container: Arc<RwLock<Container>>,
     ) -> Result<(), String> {
-        if path.len() == 0 {
+        if path.is_empty() {
             if let ContainerType::Father { subconts, .. } = &mut self.cont_type {
-                if let Some(_) = &subconts[1] {
-                    if let Some(_) = &subconts[0] {
-                        Err(format!("{} unable to add a new container.", self.name))?;
-                    } else {
-                        subconts[0] = Some(container);
-                    }
-                } else {
-                    subconts[1] = Some(container);
-                }
+                subconts.insert(0, container);
             } else {
                 Err(format!("{} is not a father container.", self.name))?;
             }
         } else {
             if let ContainerType::Father { subconts, .. } = &mut self.cont_type {
-                if let Some(cont) = &subconts[0] {
-                    if cont.read().unwrap().name == path[0] {
-                        cont.write().unwrap().add_container(&path[1..], container)?;
-                    } else if let Some(cont) = &subconts[1] {
-                        if cont.read().unwrap().name == path[0] {
-                            cont.write().unwrap().add_container(&path[1..], container)?;
-                        }
-                    } else {
-                        Err(format!("No container names {}.", path[0]))?;
-                    }
+                match subconts
+                    .iter()
+                    .find(|cont| cont.read().unwrap().name == path[0])
+                {
+                    Some(cont) => cont.write().unwrap().add_container(&path[1..], container)?,
+                    None => Err(format!("No container names {}.", path[0]))?,
                 }
             } else {
                 Err(format!("{} is not a father container.", self.name))?;
@@ -348,3 +472,216 @@ impl Container {
         Ok(())
     }
 }
+
+/// 沿布局方向把total行或列分给各个子Container
+///
+/// 缩放时有焦点的子Container占满，其他的只留一行或一列显示标题；否则先给固定大小，
+/// 但给按权重分的留出最小大小，剩下的按权重分，分不到最小大小的按最小大小。
+/// 空间不够时后面的子Container被压缩
+fn layout(subconts: &[Arc<RwLock<Container>>], total: usize, zoom: bool) -> Vec<usize> {
+    let specs = subconts
+        .iter()
+        .map(|cont| {
+            let cont = cont.read().unwrap();
+            (cont.size, cont.min_size, cont.focused)
+        })
+        .collect::<Vec<_>>();
+    let mut sizes = specs.iter().map(|&(_, min, _)| min).collect::<Vec<_>>();
+    match specs.iter().position(|&(_, _, focused)| focused) {
+        Some(index) if zoom => {
+            sizes.fill(1);
+            sizes[index] = total.saturating_sub(sizes.len() - 1);
+        }
+        _ => {
+            let mut rest = total;
+            let mut weighted = Vec::new();
+            let reserved = specs
+                .iter()
+                .filter(|(size, _, _)| matches!(size, Size::Weight(_)))
+                .map(|&(_, min, _)| min)
+                .sum::<usize>();
+            for (index, &(size, min, _)) in specs.iter().enumerate() {
+                match size {
+                    Size::Fixed(n) => {
+                        sizes[index] = n.min(rest.saturating_sub(reserved)).max(min);
+                        rest = rest.saturating_sub(sizes[index]);
+                    }
+                    Size::Weight(w) => weighted.push((index, w.max(1), min)),
+                }
+            }
+            // 按比例分到的不够最小大小时先给最小大小，再重新分剩下的
+            while let Some(short) = {
+                let weight = weighted.iter().map(|&(_, w, _)| w).sum::<usize>();
+                weighted
+                    .iter()
+                    .position(|&(_, w, min)| rest * w / weight < min)
+            } {
+                let (index, _, min) = weighted.remove(short);
+                sizes[index] = min;
+                rest = rest.saturating_sub(min);
+            }
+            let weight = weighted.iter().map(|&(_, w, _)| w).sum::<usize>();
+            let (mut acc, mut given) = (0, 0);
+            for &(index, w, _) in &weighted {
+                acc += w;
+                sizes[index] = rest * acc / weight - given;
+                given += sizes[index];
+            }
+        }
+    }
+    let mut left = total;
+    for size in &mut sizes {
+        *size = (*size).min(left);
+        left -= *size;
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn shared(cont: Container) -> Arc<RwLock<Container>> {
+        Arc::new(RwLock::new(cont))
+    }
+
+    fn leaf(name: &str, weight: usize, min_size: usize) -> Arc<RwLock<Container>> {
+        let mut cont = Container::new(name, None);
+        cont.set_split(Size::Weight(weight), min_size);
+        shared(cont)
+    }
+
+    fn fixed(name: &str, size: usize, min_size: usize) -> Arc<RwLock<Container>> {
+        let mut cont = Container::new(name, None);
+        cont.set_split(Size::Fixed(size), min_size);
+        shared(cont)
+    }
+
+    fn father(name: &str, vert_layout: bool, subconts: Vec<Arc<RwLock<Container>>>) -> Container {
+        let mut cont = Container::new(name, None);
+        cont.set_type(ContainerType::Father {
+            subconts,
+            vert_layout,
+            zoom: false,
+        });
+        cont
+    }
+
+    #[test]
+    fn splits_by_weight() {
+        let subconts = [leaf("a", 3, 1), leaf("b", 2, 1)];
+        assert_eq!(layout(&subconts, 10, false), [6, 4]);
+        // 除不尽的部分给后面的
+        let subconts = [leaf("a", 1, 1), leaf("b", 1, 1), leaf("c", 1, 1)];
+        assert_eq!(layout(&subconts, 10, false), [3, 3, 4]);
+    }
+
+    #[test]
+    fn gives_at_least_min_size() {
+        let subconts = [leaf("a", 1, 3), leaf("b", 9, 1)];
+        assert_eq!(layout(&subconts, 10, false), [3, 7]);
+    }
+
+    #[test]
+    fn fixed_size_comes_before_weights() {
+        let subconts = [leaf("a", 1, 1), fixed("b", 4, 2), leaf("c", 1, 1)];
+        assert_eq!(layout(&subconts, 10, false), [3, 4, 3]);
+        assert_eq!(layout(&subconts, 20, false), [8, 4, 8]);
+        // 窗口小时先给按权重分的留出最小大小，但不小于自己的最小大小
+        assert_eq!(layout(&subconts, 5, false), [1, 3, 1]);
+        assert_eq!(layout(&subconts, 3, false), [1, 2, 0]);
+    }
+
+    #[test]
+    fn resizing_keeps_fixed_size_fixed() {
+        let mut root = father(
+            "Root",
+            true,
+            vec![leaf("Work", 1, 1), fixed("Bottom", 4, 2)],
+        );
+        root.set_size(10, 20);
+        assert_eq!(size_of(&root, &["Bottom"], true), 4);
+        assert!(root.resize_path(&["Bottom"], 3, true));
+        root.set_size(10, 30);
+        assert_eq!(size_of(&root, &["Bottom"], true), 7);
+        assert_eq!(size_of(&root, &["Work"], true), 23);
+        assert!(root.resize_path(&["Bottom"], -10, true));
+        root.set_size(10, 20);
+        assert_eq!(size_of(&root, &["Bottom"], true), 2);
+    }
+
+    #[test]
+    fn squeezes_later_containers_when_short() {
+        let subconts = [leaf("a", 1, 4), leaf("b", 1, 4)];
+        assert_eq!(layout(&subconts, 6, false), [4, 2]);
+        assert_eq!(layout(&subconts, 3, false), [3, 0]);
+    }
+
+    #[test]
+    fn zoom_fills_focused_container() {
+        let subconts = [leaf("a", 1, 1), leaf("b", 1, 1), leaf("c", 5, 2)];
+        subconts[1].write().unwrap().focus();
+        // 缩放时其他的只留一行，不管最小大小
+        assert_eq!(layout(&subconts, 10, true), [1, 8, 1]);
+        assert_eq!(layout(&subconts, 10, false), [1, 1, 8]);
+    }
+
+    /// path处的Container沿vertical方向的大小
+    fn size_of(cont: &Container, path: &[&str], vertical: bool) -> usize {
+        let cont = cont.child(path).unwrap().read().unwrap();
+        if path.len() > 1 {
+            return size_of(&cont, &path[1..], vertical);
+        }
+        if vertical {
+            cont.height
+        } else {
+            cont.width
+        }
+    }
+
+    #[test]
+    fn resizes_along_direction() {
+        // 上方左右分开的Side和Main，下方为Bottom
+        let work = father("Work", false, vec![leaf("Side", 1, 1), leaf("Main", 3, 1)]);
+        let mut root = father("Root", true, vec![shared(work), leaf("Bottom", 1, 1)]);
+        root.set_size(40, 20);
+        assert_eq!(size_of(&root, &["Work", "Side"], false), 10);
+        assert_eq!(size_of(&root, &["Work"], true), 10);
+
+        assert!(root.resize_path(&["Work", "Side"], 4, false));
+        root.set_size(40, 20);
+        assert_eq!(size_of(&root, &["Work", "Side"], false), 14);
+        assert_eq!(size_of(&root, &["Work", "Main"], false), 26);
+
+        // Work中没有上下分开的兄弟，调整的是Work和Bottom
+        assert!(root.resize_path(&["Work", "Side"], 4, true));
+        root.set_size(40, 20);
+        assert_eq!(size_of(&root, &["Work"], true), 14);
+        assert_eq!(size_of(&root, &["Bottom"], true), 6);
+
+        assert!(root.resize_path(&["Bottom"], -2, true));
+        root.set_size(40, 20);
+        assert_eq!(size_of(&root, &["Bottom"], true), 4);
+        assert!(!root.resize_path(&["Bottom"], 2, false));
+    }
+
+    #[test]
+    fn resize_walks_up_when_level_is_stuck() {
+        let top = father("Top", true, vec![leaf("X", 1, 1), leaf("Y", 1, 5)]);
+        let mut root = father("Root", true, vec![shared(top), leaf("Bottom", 1, 2)]);
+        root.set_size(10, 20);
+        assert_eq!(size_of(&root, &["Top", "X"], true), 5);
+        // Y已经是最小大小，从Bottom挪出空间
+        assert!(root.resize_path(&["Top", "X"], 3, true));
+        root.set_size(10, 20);
+        assert_eq!(size_of(&root, &["Top"], true), 13);
+        assert_eq!(size_of(&root, &["Top", "X"], true), 8);
+        assert_eq!(size_of(&root, &["Bottom"], true), 7);
+        // 都到了最小大小时找不到可以调整的一层
+        assert!(root.resize_path(&["Top", "X"], 100, true));
+        root.set_size(10, 20);
+        assert_eq!(size_of(&root, &["Bottom"], true), 2);
+        assert!(!root.resize_path(&["Top", "X"], 1, true));
+    }
+}
@@ -1,93 +1,259 @@
-use crossterm::{
-    cursor::{self, MoveTo},
-    queue,
-    terminal::{
-        disable_raw_mode, enable_raw_mode, window_size, EnterAlternateScreen, LeaveAlternateScreen,
-    },
-};
 use std::{
+    cell::RefCell,
     collections::HashMap,
-    io::Write,
+    rc::Rc,
     sync::{Arc, RwLock},
 };
 
-use crate::renderer::Renderer;
+use crate::renderer::{Buffer, Renderer};
 
-use super::{container::Container, ChangeFocusEvent, Event};
+use super::{
+    backend::Backend,
+    container::{Container, Rect},
+    overlay::{Overlay, Placement},
+    ChangeFocusEvent, Event,
+};
 
 pub struct Framework {
+    backend: Box<dyn Backend>,
     width: usize,
     height: usize,
     container: Option<Arc<RwLock<Container>>>,
+    /// 画在布局上方的浮层，后打开的在上面
+    overlays: Vec<Overlay>,
+    /// 关闭浮层或改变布局后下次渲染前清屏，Container不一定会重画之前别处画过的每一格
+    clear: bool,
+    /// 组件画在back上，渲染时只输出与上一帧front不同的格子
+    front: Buffer,
+    back: Rc<RefCell<Buffer>>,
+    /// 上一帧显示光标的位置
+    cursor: Option<(usize, usize)>,
+    /// 有焦点的Container占满屏幕，其他的只留标题
+    zoomed: bool,
 
     focused_path: String,
 
-    path_ajac_table: HashMap<
-        String,
-        (
-            Option<String>,
-            Option<String>,
-            Option<String>,
-            Option<String>,
-        ),
-    >,
+    path_ajac_table: HashMap<String, Adjacency>,
 }
 
+/// 上、下、左、右四个方向相邻的Container路径，为None的方向按位置计算
+type Adjacency = (
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
 impl Framework {
-    pub fn new() -> Self {
-        enable_raw_mode().unwrap();
-        queue!(
-            std::io::stdout(),    //
-            EnterAlternateScreen, //
-            cursor::Hide
-        )
-        .unwrap();
-        std::io::stdout().flush().unwrap();
+    /// 画好的每一帧交给backend输出
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(backend: Box<dyn Backend>) -> Self {
+        let (width, height) = backend.size();
         let mut framework = Framework {
-            width: window_size().unwrap().columns as usize,
-            height: window_size().unwrap().rows as usize,
+            backend,
+            width,
+            height,
             container: None,
+            overlays: Vec::new(),
+            // 屏幕上还有启动前的内容
+            clear: true,
+            front: Buffer::new(width, height),
+            back: Rc::new(RefCell::new(Buffer::new(width, height))),
+            cursor: None,
+            zoomed: true,
             focused_path: String::new(),
             path_ajac_table: HashMap::new(),
         };
         let root = Container::new_root(framework.get_size().0, framework.get_size().1, None);
         let root = Arc::new(RwLock::new(root));
         framework.set_container(Arc::clone(&root));
-        framework.set_focused_path("/WorkArea/ProjectViewer");
+        framework.set_focused_path("/WorkArea/SideBar/ProjectViewer");
         framework
     }
 
     pub fn render(&mut self) {
-        let mut stdout = std::io::stdout();
-        queue!(stdout, cursor::Hide).unwrap();
-        if let Some(container) = &self.container {
-            let renderer = Renderer::new(0, 0, self.width, self.height);
-            let location = container.read().unwrap().render(&renderer);
-            if location.0 {
-                queue!(
-                    stdout,
-                    MoveTo(location.1 .0 as u16, location.1 .1 as u16),
-                    cursor::Show,
-                )
-                .unwrap();
-            } else {
-                queue!(stdout, cursor::Hide).unwrap();
+        let Some(container) = &self.container else {
+            return;
+        };
+        if self.clear {
+            self.back.borrow_mut().clear();
+        }
+        let renderer = Renderer::new(Rc::clone(&self.back), 0, 0, self.width, self.height);
+        let mut location = container.read().unwrap().render(&renderer);
+        for overlay in &self.overlays {
+            let res = match overlay.placement {
+                Placement::Center => {
+                    let container = overlay.container.read().unwrap();
+                    let (x, y) = container.get_location();
+                    let (width, height) = container.get_size();
+                    container.render(&renderer.sub(x, y, width, height))
+                }
+                Placement::Cursor if location.0 => {
+                    self.render_at_cursor(&renderer, &overlay.container, location.1)
+                }
+                Placement::Cursor => continue,
+            };
+            // 模态浮层总是决定光标，其他浮层只在需要光标时决定
+            if overlay.modal || res.0 {
+                location = res;
             }
         }
-        stdout.flush().unwrap();
+        if self.clear {
+            self.front.clear();
+        }
+        self.cursor = location.0.then_some(location.1);
+        self.backend
+            .draw(&self.back.borrow(), &self.front, self.clear, self.cursor);
+        self.front.clone_from(&self.back.borrow());
+        self.clear = false;
+    }
+
+    /// 最近一次渲染出的屏幕内容
+    pub fn screen(&self) -> &Buffer {
+        &self.front
+    }
+
+    /// 最近一次渲染时显示光标的位置，不显示光标时为None
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        self.cursor
+    }
+
+    /// 在光标cursor下方画container，下方放不下时画在上方
+    fn render_at_cursor(
+        &self,
+        renderer: &Renderer,
+        container: &Arc<RwLock<Container>>,
+        cursor: (usize, usize),
+    ) -> (bool, (usize, usize)) {
+        let mut container = container.write().unwrap();
+        let Some((width, height)) = container.wanted_size() else {
+            return (false, (0, 0));
+        };
+        let width = width.min(self.width);
+        let x = cursor.0.min(self.width - width);
+        let below = self.height.saturating_sub(cursor.1 + 1);
+        let (y, height) = if height <= below || cursor.1 < below {
+            (cursor.1 + 1, height.min(below))
+        } else {
+            (cursor.1 - height.min(cursor.1), height.min(cursor.1))
+        };
+        if height == 0 {
+            return (false, (0, 0));
+        }
+        container.set_size(width, height);
+        container.set_location(x, y);
+        container.render(&renderer.sub(x, y, width, height))
+    }
+
+    /// 有焦点的Container的路径
+    pub fn focused_path(&self) -> &str {
+        &self.focused_path
     }
 
     pub fn set_focused_path(&mut self, path: &str) {
         self.focused_path = path.to_string();
     }
 
+    /// 把焦点直接移到path处的Container
+    pub fn set_focus(&mut self, path: &str) {
+        // 重新布局会给组件发送Resize事件
+        if path == self.focused_path {
+            return;
+        }
+        if let Some(container) = &self.container {
+            let bpath = self
+                .focused_path
+                .split("/")
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<&str>>();
+            let rpath = path
+                .split("/")
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<&str>>();
+            container.write().unwrap().disfocus_path(&bpath);
+            container.write().unwrap().focus_path(&rpath);
+            container.write().unwrap().set_size(self.width, self.height);
+        }
+        self.focused_path = path.to_string();
+        // 缩放时布局变了，不缩放时原来有焦点的组件的标题也要重画
+        self.clear = true;
+    }
+
     pub fn set_size(&mut self, width: usize, height: usize) {
         let width = if width < 11 { 11 } else { width };
         self.width = width;
         self.height = height;
+        self.front = Buffer::new(width, height);
+        *self.back.borrow_mut() = Buffer::new(width, height);
+        self.clear = true;
         if let Some(container) = &self.container {
             container.write().unwrap().set_size(width, height);
         }
+        self.layout_overlays();
+    }
+
+    /// 把container作为浮层放到最上面，已经打开时移到最上面
+    pub fn open_overlay(
+        &mut self,
+        container: Arc<RwLock<Container>>,
+        placement: Placement,
+        modal: bool,
+    ) {
+        self.close_overlay(&container);
+        if modal {
+            container.write().unwrap().focus();
+        }
+        self.overlays.push(Overlay {
+            container,
+            placement,
+            modal,
+        });
+        self.layout_overlays();
+    }
+
+    pub fn close_overlay(&mut self, container: &Arc<RwLock<Container>>) {
+        let count = self.overlays.len();
+        self.overlays
+            .retain(|overlay| !Arc::ptr_eq(&overlay.container, container));
+        self.clear |= self.overlays.len() < count;
+    }
+
+    /// 关闭最上面的模态浮层
+    pub fn close_modal(&mut self) {
+        if let Some(index) = self.overlays.iter().rposition(|overlay| overlay.modal) {
+            self.overlays.remove(index);
+            self.clear = true;
+        }
+    }
+
+    /// 接收按键的浮层
+    fn modal(&self) -> Option<&Arc<RwLock<Container>>> {
+        self.overlays
+            .iter()
+            .rev()
+            .find(|overlay| overlay.modal)
+            .map(|overlay| &overlay.container)
+    }
+
+    /// 按键绑定的上下文，模态浮层打开时为None
+    pub fn key_context(&self) -> Option<&'static str> {
+        if self.modal().is_some() {
+            return None;
+        }
+        self.container.as_ref()?.read().unwrap().key_context()
+    }
+
+    /// 跟随光标的浮层在渲染时才确定位置
+    fn layout_overlays(&self) {
+        let width = (self.width * 3 / 4).max(self.width.min(40));
+        let height = (self.height * 2 / 3).max(self.height.min(5));
+        for overlay in &self.overlays {
+            if let Placement::Center = overlay.placement {
+                let mut container = overlay.container.write().unwrap();
+                container.set_size(width, height);
+                container.set_location((self.width - width) / 2, (self.height - height) / 4);
+            }
+        }
     }
 
     pub fn get_size(&self) -> (usize, usize) {
@@ -103,6 +269,7 @@ impl Framework {
         path: &str,
         container: Arc<RwLock<Container>>,
     ) -> Result<(), String> {
+        container.write().unwrap().set_zoom(self.zoomed);
         if let Some(cont) = &mut self.container {
             let path = path
                 .split("/")
@@ -115,138 +282,81 @@ impl Framework {
         Ok(())
     }
 
-    pub fn set_adjacy(
-        &mut self,
-        key: String,
-        val: (
-            Option<String>,
-            Option<String>,
-            Option<String>,
-            Option<String>,
-        ),
-    ) {
+    /// 打开或关闭缩放
+    pub fn toggle_zoom(&mut self) {
+        self.zoomed = !self.zoomed;
+        if let Some(container) = &self.container {
+            let mut container = container.write().unwrap();
+            container.set_zoom(self.zoomed);
+            container.set_size(self.width, self.height);
+        }
+        self.clear = true;
+    }
+
+    /// 把有焦点的Container放大delta行（vertical为true时）或列，delta为负时缩小，缩放时先关闭缩放
+    pub fn resize_focused(&mut self, delta: isize, vertical: bool) {
+        if self.zoomed {
+            self.toggle_zoom();
+        }
+        if let Some(container) = &self.container {
+            let path = self
+                .focused_path
+                .split("/")
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<&str>>();
+            let mut container = container.write().unwrap();
+            container.resize_path(&path, delta, vertical);
+            container.set_size(self.width, self.height);
+        }
+        self.clear = true;
+    }
+
+    /// 手动指定key处的Container在各方向上相邻的Container，没有指定的方向按位置计算
+    pub fn set_adjacy(&mut self, key: String, val: Adjacency) {
         self.path_ajac_table.insert(key, val);
     }
 
+    /// 焦点在direction方向上要移到的Container
+    fn neighbor(&self, direction: ChangeFocusEvent) -> Option<String> {
+        if let Some(adjacency) = self.path_ajac_table.get(&self.focused_path) {
+            let path = match direction {
+                ChangeFocusEvent::Up => &adjacency.0,
+                ChangeFocusEvent::Down => &adjacency.1,
+                ChangeFocusEvent::Left => &adjacency.2,
+                ChangeFocusEvent::Right => &adjacency.3,
+            };
+            if path.is_some() {
+                return path.clone();
+            }
+        }
+        let mut panes = Vec::new();
+        self.container
+            .as_ref()?
+            .read()
+            .unwrap()
+            .panes("", (0, 0), &mut panes);
+        let (_, from) = panes.iter().find(|(path, _)| *path == self.focused_path)?;
+        panes
+            .iter()
+            .filter(|(_, rect)| rect.2 > 0 && rect.3 > 0)
+            .filter_map(|(path, rect)| Some((distance(*from, *rect, direction)?, path)))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, path)| path.clone())
+    }
+
     pub fn dispatch(&mut self, event: Event) {
         match event {
-            Event::ChangeFocus(which) => {
-                match which {
-                    ChangeFocusEvent::Up => {
-                        if let Some(container) = &self.container {
-                            let bpath = self
-                                .focused_path
-                                .split("/")
-                                .filter(|x| !x.is_empty())
-                                .collect::<Vec<&str>>();
-                            let new_path =
-                                if let Some(path) = self.path_ajac_table.get(&self.focused_path) {
-                                    let rpath = &path.0;
-                                    if let Some(rrpath) = rpath {
-                                        let rpath = rrpath
-                                            .split("/")
-                                            .filter(|x| !x.is_empty())
-                                            .collect::<Vec<&str>>();
-                                        container.write().unwrap().disfocus_path(&bpath);
-                                        container.write().unwrap().focus_path(&rpath);
-                                        rrpath.to_string()
-                                    } else {
-                                        self.focused_path.clone()
-                                    }
-                                } else {
-                                    self.focused_path.clone()
-                                };
-                            self.focused_path = new_path;
-                        }
-                    }
-                    ChangeFocusEvent::Down => {
-                        if let Some(container) = &self.container {
-                            let bpath = self
-                                .focused_path
-                                .split("/")
-                                .filter(|x| !x.is_empty())
-                                .collect::<Vec<&str>>();
-                            let new_path =
-                                if let Some(path) = self.path_ajac_table.get(&self.focused_path) {
-                                    let rpath = &path.1;
-                                    if let Some(rrpath) = rpath {
-                                        let rpath = rrpath
-                                            .split("/")
-                                            .filter(|x| !x.is_empty())
-                                            .collect::<Vec<&str>>();
-                                        container.write().unwrap().disfocus_path(&bpath);
-                                        container.write().unwrap().focus_path(&rpath);
-                                        rrpath.to_string()
-                                    } else {
-                                        self.focused_path.clone()
-                                    }
-                                } else {
-                                    self.focused_path.clone()
-                                };
-                            self.focused_path = new_path;
-                        }
-                    }
-                    ChangeFocusEvent::Left => {
-                        if let Some(container) = &self.container {
-                            let bpath = self
-                                .focused_path
-                                .split("/")
-                                .filter(|x| !x.is_empty())
-                                .collect::<Vec<&str>>();
-                            let new_path =
-                                if let Some(path) = self.path_ajac_table.get(&self.focused_path) {
-                                    let rpath = &path.2;
-                                    if let Some(rrpath) = rpath {
-                                        let rpath = rrpath
-                                            .split("/")
-                                            .filter(|x| !x.is_empty())
-                                            .collect::<Vec<&str>>();
-                                        container.write().unwrap().disfocus_path(&bpath);
-                                        container.write().unwrap().focus_path(&rpath);
-                                        rrpath.to_string()
-                                    } else {
-                                        self.focused_path.clone()
-                                    }
-                                } else {
-                                    self.focused_path.clone()
-                                };
-                            self.focused_path = new_path;
-                        }
-                    }
-                    ChangeFocusEvent::Right => {
-                        if let Some(container) = &self.container {
-                            let bpath = self
-                                .focused_path
-                                .split("/")
-                                .filter(|x| !x.is_empty())
-                                .collect::<Vec<&str>>();
-                            let new_path =
-                                if let Some(path) = self.path_ajac_table.get(&self.focused_path) {
-                                    let rpath = &path.3;
-                                    if let Some(rrpath) = rpath {
-                                        let rpath = rrpath
-                                            .split("/")
-                                            .filter(|x| !x.is_empty())
-                                            .collect::<Vec<&str>>();
-                                        container.write().unwrap().disfocus_path(&bpath);
-                                        container.write().unwrap().focus_path(&rpath);
-                                        rrpath.to_string()
-                                    } else {
-                                        self.focused_path.clone()
-                                    }
-                                } else {
-                                    self.focused_path.clone()
-                                };
-                            self.focused_path = new_path;
-                        }
-                    }
-                }
-                if let Some(container) = &self.container {
-                    container.write().unwrap().set_size(self.width, self.height);
+            // 模态浮层打开时不切换焦点
+            Event::ChangeFocus(_) if self.modal().is_some() => (),
+            Event::ChangeFocus(direction) => {
+                if let Some(path) = self.neighbor(direction) {
+                    self.set_focus(&path);
                 }
             }
             Event::Crossterm(e) => {
-                if let Some(container) = &self.container {
+                if let Some(modal) = self.modal() {
+                    modal.write().unwrap().dispatch(e);
+                } else if let Some(container) = &self.container {
                     container.write().unwrap().dispatch(e);
                 }
             }
@@ -254,9 +364,183 @@ impl Framework {
     }
 }
 
-impl Drop for Framework {
-    fn drop(&mut self) {
-        queue!(std::io::stdout(), cursor::Show, LeaveAlternateScreen).unwrap();
-        disable_raw_mode().unwrap();
+/// 从from往direction方向到to的距离，to不在这个方向上时为None
+///
+/// 先比较两个矩形之间的间隔，再比较垂直方向上中线的偏移
+fn distance(from: Rect, to: Rect, direction: ChangeFocusEvent) -> Option<(usize, usize)> {
+    let gap = match direction {
+        ChangeFocusEvent::Up => from.1.checked_sub(to.1 + to.3)?,
+        ChangeFocusEvent::Down => to.1.checked_sub(from.1 + from.3)?,
+        ChangeFocusEvent::Left => from.0.checked_sub(to.0 + to.2)?,
+        ChangeFocusEvent::Right => to.0.checked_sub(from.0 + from.2)?,
+    };
+    let (a, b) = match direction {
+        ChangeFocusEvent::Up | ChangeFocusEvent::Down => {
+            ((from.0, from.0 + from.2), (to.0, to.0 + to.2))
+        }
+        ChangeFocusEvent::Left | ChangeFocusEvent::Right => {
+            ((from.1, from.1 + from.3), (to.1, to.1 + to.3))
+        }
+    };
+    // 垂直方向上不重叠时两者之间的距离
+    let apart = a.0.saturating_sub(b.1).max(b.0.saturating_sub(a.1));
+    Some((gap + apart, (a.0 + a.1).abs_diff(b.0 + b.1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::{
+        backend::HeadlessBackend,
+        container::{ContainerType, Size},
+    };
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn pane(name: &str, size: Size) -> Arc<RwLock<Container>> {
+        let mut container = Container::new(name, None);
+        container.set_split(size, 1);
+        Arc::new(RwLock::new(container))
+    }
+
+    fn father(name: &str, vertical: bool) -> Arc<RwLock<Container>> {
+        let container = pane(name, Size::Weight(1));
+        container.write().unwrap().set_type(ContainerType::Father {
+            subconts: Vec::new(),
+            vert_layout: vertical,
+            zoom: false,
+        });
+        container
+    }
+
+    /// 20x10的屏幕，上半是A(0..10)、B(10..20)，下半是C(0..4)、D(4..16)、E(16..20)
+    fn layout() -> Framework {
+        let mut framework = Framework::new(Box::new(HeadlessBackend::new(20, 10)));
+        framework.toggle_zoom();
+        // 后加入的排在前面
+        framework
+            .add_container("/", father("Bottom", false))
+            .unwrap();
+        framework.add_container("/", father("Top", false)).unwrap();
+        framework
+            .add_container("/Top", pane("B", Size::Weight(1)))
+            .unwrap();
+        framework
+            .add_container("/Top", pane("A", Size::Weight(1)))
+            .unwrap();
+        framework
+            .add_container("/Bottom", pane("E", Size::Weight(1)))
+            .unwrap();
+        framework
+            .add_container("/Bottom", pane("D", Size::Fixed(12)))
+            .unwrap();
+        framework
+            .add_container("/Bottom", pane("C", Size::Fixed(4)))
+            .unwrap();
+        framework.set_size(20, 10);
+        framework
+    }
+
+    fn moved(framework: &mut Framework, from: &str, direction: ChangeFocusEvent) -> String {
+        framework.set_focus(from);
+        framework.dispatch(Event::ChangeFocus(direction));
+        framework.focused_path().to_string()
+    }
+
+    #[test]
+    fn distance_only_counts_panes_in_that_direction() {
+        let from = (0, 0, 10, 5);
+        assert_eq!(
+            distance(from, (0, 5, 4, 5), ChangeFocusEvent::Down),
+            Some((0, 6))
+        );
+        assert_eq!(
+            distance(from, (12, 5, 4, 5), ChangeFocusEvent::Down),
+            Some((2, 18))
+        );
+        assert_eq!(distance(from, (0, 5, 4, 5), ChangeFocusEvent::Up), None);
+        assert_eq!(
+            distance(from, (10, 0, 10, 5), ChangeFocusEvent::Right),
+            Some((0, 0))
+        );
+        assert_eq!(distance(from, (10, 0, 10, 5), ChangeFocusEvent::Left), None);
+        // 重叠的矩形不在任何方向上
+        assert_eq!(distance(from, (0, 3, 10, 5), ChangeFocusEvent::Down), None);
+        assert_eq!(distance(from, (5, 0, 10, 5), ChangeFocusEvent::Right), None);
+    }
+
+    #[test]
+    fn neighbor_follows_geometry() {
+        let mut framework = layout();
+        assert_eq!(
+            moved(&mut framework, "/Top/A", ChangeFocusEvent::Right),
+            "/Top/B"
+        );
+        assert_eq!(
+            moved(&mut framework, "/Top/B", ChangeFocusEvent::Left),
+            "/Top/A"
+        );
+        assert_eq!(
+            moved(&mut framework, "/Bottom/C", ChangeFocusEvent::Right),
+            "/Bottom/D"
+        );
+        assert_eq!(
+            moved(&mut framework, "/Bottom/E", ChangeFocusEvent::Left),
+            "/Bottom/D"
+        );
+        assert_eq!(
+            moved(&mut framework, "/Bottom/E", ChangeFocusEvent::Up),
+            "/Top/B"
+        );
+        // 这个方向上没有Container时焦点不动
+        assert_eq!(
+            moved(&mut framework, "/Top/A", ChangeFocusEvent::Up),
+            "/Top/A"
+        );
+        assert_eq!(
+            moved(&mut framework, "/Bottom/C", ChangeFocusEvent::Left),
+            "/Bottom/C"
+        );
+    }
+
+    #[test]
+    fn neighbor_breaks_ties_by_center() {
+        let mut framework = layout();
+        // C和D都与A重叠，C的中线离A近
+        assert_eq!(
+            moved(&mut framework, "/Top/A", ChangeFocusEvent::Down),
+            "/Bottom/C"
+        );
+        // D和E都与B重叠，E的中线离B近
+        assert_eq!(
+            moved(&mut framework, "/Top/B", ChangeFocusEvent::Down),
+            "/Bottom/E"
+        );
+        // A和B离D的中线一样近时取布局中靠前的
+        assert_eq!(
+            moved(&mut framework, "/Bottom/D", ChangeFocusEvent::Up),
+            "/Top/A"
+        );
+    }
+
+    #[test]
+    fn adjacency_overrides_geometry() {
+        let mut framework = layout();
+        framework.set_adjacy(
+            "/Top/A".to_string(),
+            (None, Some("/Bottom/E".to_string()), None, None),
+        );
+        assert_eq!(
+            moved(&mut framework, "/Top/A", ChangeFocusEvent::Down),
+            "/Bottom/E"
+        );
+        // 没有指定的方向仍按位置计算
+        assert_eq!(
+            moved(&mut framework, "/Top/A", ChangeFocusEvent::Right),
+            "/Top/B"
+        );
+        assert_eq!(
+            moved(&mut framework, "/Top/B", ChangeFocusEvent::Down),
+            "/Bottom/E"
+        );
     }
 }
@@ -1,13 +1,18 @@
 use crossterm::event;
 
+pub mod backend;
 pub mod container;
 pub mod framework;
+pub mod overlay;
+#[cfg(test)]
+pub mod testing;
 
 pub enum Event {
     ChangeFocus(ChangeFocusEvent),
     Crossterm(event::Event),
 }
 
+#[derive(Clone, Copy)]
 pub enum ChangeFocusEvent {
     Up,
     Down,
@@ -0,0 +1,22 @@
+use std::sync::{Arc, RwLock};
+
+use super::container::Container;
+
+/// 浮层在屏幕上的位置
+#[derive(Clone, Copy)]
+pub enum Placement {
+    /// 在屏幕中间，占大部分空间
+    Center,
+    /// 紧贴光标下方，下方放不下时在上方，大小由Container::wanted_size给出
+    ///
+    /// 没有光标或不需要显示时不画
+    Cursor,
+}
+
+/// 画在Container树上方的窗口，例如弹出窗口、对话框和补全列表
+pub struct Overlay {
+    pub container: Arc<RwLock<Container>>,
+    pub placement: Placement,
+    /// 打开时按键只交给最上面的模态浮层，也不能切换焦点
+    pub modal: bool,
+}
@@ -0,0 +1,97 @@
+//! 组件测试用的工具：在HeadlessBackend上搭出布局，发送按键，读出画好的屏幕
+
+use crossterm::{
+    event::{Event as CrosstermEvent, KeyCode, KeyEvent, KeyModifiers},
+    style::ContentStyle,
+};
+use std::{
+    fs,
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{backend::HeadlessBackend, framework::Framework, Event};
+use crate::components::{
+    areas::{BottomArea, EditorArea, SideBar, WorkArea},
+    component::Component,
+};
+
+/// width列height行的无终端Framework，已经有WorkArea、SideBar、EditorArea和BottomArea
+pub fn framework(width: usize, height: usize) -> Framework {
+    let mut framework = Framework::new(Box::new(HeadlessBackend::new(width, height)));
+    bind(&mut framework, &BottomArea::new());
+    bind(&mut framework, &WorkArea::new());
+    bind(&mut framework, &EditorArea::new());
+    bind(&mut framework, &SideBar::new());
+    framework
+}
+
+pub fn bind(framework: &mut Framework, component: &Arc<RwLock<impl Component>>) {
+    if component.write().unwrap().bind_to(framework).is_err() {
+        panic!("failed to bind component");
+    }
+}
+
+/// 加入所有组件后重新布局，把焦点移到path并画出一帧
+pub fn show(framework: &mut Framework, path: &str) {
+    let (width, height) = framework.get_size();
+    framework.set_size(width, height);
+    framework.set_focus(path);
+    framework.render();
+}
+
+pub fn press(framework: &mut Framework, code: KeyCode) {
+    press_with(framework, code, KeyModifiers::NONE);
+}
+
+pub fn press_with(framework: &mut Framework, code: KeyCode, modifiers: KeyModifiers) {
+    let key = KeyEvent::new(code, modifiers);
+    framework.dispatch(Event::Crossterm(CrosstermEvent::Key(key)));
+}
+
+pub fn type_text(framework: &mut Framework, text: &str) {
+    for ch in text.chars() {
+        press(framework, KeyCode::Char(ch));
+    }
+}
+
+/// 屏幕上的每一行，去掉行尾空格
+pub fn lines(framework: &Framework) -> Vec<String> {
+    let screen = framework.screen();
+    (0..screen.size().1)
+        .map(|y| screen.line(y).trim_end().to_string())
+        .collect()
+}
+
+pub fn style(framework: &Framework, x: usize, y: usize) -> ContentStyle {
+    framework.screen().cell(x, y).unwrap().1
+}
+
+/// 反复渲染，直到画出的屏幕满足done，用于等待后台线程的结果
+pub fn render_until(framework: &mut Framework, done: impl Fn(&[String]) -> bool) {
+    let start = Instant::now();
+    loop {
+        framework.render();
+        let lines = lines(framework);
+        if done(&lines) {
+            return;
+        }
+        if start.elapsed() > Duration::from_secs(5) {
+            panic!("timed out, screen:\n{}", lines.join("\n"));
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// 在临时目录中建一个项目，files为(相对路径, 内容)，返回项目根目录
+pub fn project(name: &str, files: &[(&str, &str)]) -> String {
+    let root = std::env::temp_dir().join(format!("ide-termux-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for (path, text) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+    root.to_string_lossy().into_owned()
+}